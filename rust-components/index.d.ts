@@ -3,31 +3,321 @@
 
 /* auto-generated by NAPI-RS */
 
+/** Stable error codes exposed to JS as `error.code` */
+export const enum ErrorCode {
+  FileNotFound = 'FileNotFound',
+  FileExists = 'FileExists',
+  NoPermissions = 'NoPermissions',
+  IsADirectory = 'IsADirectory',
+  NotADirectory = 'NotADirectory',
+  Unavailable = 'Unavailable',
+  Cancelled = 'Cancelled',
+  InvalidArgument = 'InvalidArgument',
+  /** The file changed on disk after it was read for a preview */
+  FileModified = 'FileModified',
+  Unknown = 'Unknown'
+}
+/**
+ * Shape of the errors native calls reject or throw with. File system errors are
+ * named like VS Code's `FileSystemProviderError`, e.g. `EntryNotFound (FileSystemError)`.
+ */
+export interface NativeError extends Error {
+  code: ErrorCode
+  /** OS error number, when the failure came from a system call */
+  errno?: number
+  path?: string
+}
+export interface CacheOptions {
+  /** Cache file contents and stats (default: true) */
+  enabled?: boolean
+  /** Entries older than this are dropped even if the file looks unchanged (default: 30s) */
+  ttlMs?: number
+  /** Memory budget for all entries; least recently used entries are evicted first (default: 64 MiB) */
+  maxBytes?: number
+  /** Larger files are never cached (default: 1 MiB) */
+  maxEntryBytes?: number
+  /**
+   * Also answer `stat` from the cache. Stats cannot be revalidated without a
+   * syscall, so changes made by other processes show up after at most `ttlMs`
+   * unless `invalidateCache` is called (default: false)
+   */
+  cacheStats?: boolean
+}
+/** Options for `read_text_file`, mirroring VS Code's `IReadTextFileOptions` */
+export interface ReadTextFileOptions {
+  /**
+   * VS Code encoding id (e.g. `utf16le`, `shiftjis`) or WHATWG label used when
+   * the file has no BOM (default: `utf8`)
+   */
+  encoding?: string
+  /** Guess the encoding of files without a BOM instead of assuming `encoding` */
+  autoGuess?: boolean
+}
+export interface TextFileContents {
+  value: string
+  /** VS Code encoding id the file was decoded with, `utf8bom` for UTF-8 with a BOM */
+  encoding: string
+}
+/** Options for `delete`, mirroring VS Code's `IFileDeleteOptions` */
+export interface DeleteOptions {
+  recursive?: boolean
+  /** Move to the platform trash instead of deleting permanently */
+  useTrash?: boolean
+}
+/** Options for `rename` and `copy`, mirroring VS Code's `IFileOverwriteOptions` */
+export interface FileOverwriteOptions {
+  overwrite?: boolean
+}
+/** Options for `open`, mirroring VS Code's `IFileOpenOptions` */
+export interface OpenFileOptions {
+  /** Open for writing, creating and truncating the file */
+  create?: boolean
+  /** Clear the readonly flag of an existing file before opening it for writing */
+  unlock?: boolean
+}
+/** Options for `write_file`, mirroring VS Code's `IFileWriteOptions` */
+export interface WriteFileOptions {
+  /** Create the file if it does not exist (default: true) */
+  create?: boolean
+  /** Replace the file if it already exists (default: true) */
+  overwrite?: boolean
+  /** Clear the readonly flag of an existing file before writing */
+  unlock?: boolean
+  /** Write through a temp file that is renamed over the target */
+  atomic?: boolean
+  /** Suffix of the temp file used for atomic writes (default: ".vsctmp") */
+  atomicPostfix?: string
+}
 export interface FileStats {
   size: number
   isFile: boolean
   isDirectory: boolean
+  /** Last modification time in ms since the epoch */
+  modified: number
+  /** Last status change time in ms since the epoch (creation time on Windows) */
+  ctime: number
+  /** Creation time in ms since the epoch, where the filesystem records it */
+  birthtime?: number
+  isSymlink: boolean
+  /** The owner may not write to the file (VS Code `FilePermission.Locked`) */
+  readonly: boolean
+  /** VS Code `FileType` bitmask */
+  fileType: number
+  /** Unix mode bits including the file type; 0 on Windows */
+  mode: number
+  uid: number
+  gid: number
+  /** Inode and device ids identify a file across hard links and renames; 0 on Windows */
+  ino: number
+  dev: number
+}
+export interface DirEntryInfo {
+  name: string
+  /**
+   * The name's bytes when it is not valid UTF-8. `name` then holds a lossy
+   * copy that does not exist on disk, so use these to address the entry.
+   */
+  rawName?: Buffer
+  /** VS Code `FileType` bitmask; symlinks carry the type of their target plus `SymbolicLink` */
+  fileType: number
+  isSymlink: boolean
+  size: number
   modified: number
 }
+export interface ScanEntry {
+  /** Path relative to the scanned root */
+  path: string
+  /** The path's bytes when it is not valid UTF-8, as with `DirEntryInfo::raw_name` */
+  rawPath?: Buffer
+  fileType: number
+  isSymlink: boolean
+  size: number
+  modified: number
+}
+export interface ScanOptions {
+  includeHidden?: boolean
+  /** Honour .gitignore, .ignore and global git excludes (default: true) */
+  respectGitignore?: boolean
+  maxDepth?: number
+  /**
+   * Descend into symlinked folders (default: false). Links back into a
+   * folder that is already being walked are skipped.
+   */
+  followSymlinks?: boolean
+}
+export interface WatcherOptions {
+  /** Globs relative to the watched root that are ignored, e.g. `**/node_modules/**` */
+  excludePatterns?: Array<string>
+  /** Quiet period before a batch of events is delivered (default: 75ms) */
+  debounceMs?: number
+}
+/** Mirrors VS Code's `FileChangeType` */
+export const enum FileChangeType {
+  Updated = 0,
+  Added = 1,
+  Deleted = 2
+}
+export interface FileChangeEvent {
+  path: string
+  changeType: FileChangeType
+}
 export interface SearchOptions {
+  /** Treat the pattern as a regex rather than a literal string (default: true) */
+  isRegex?: boolean
+  /** Match case exactly (default: true); `false` ignores case */
   caseSensitive?: boolean
+  /** Unless `case_sensitive` is true, ignore case when the pattern has no uppercase letters */
+  smartCase?: boolean
+  /** Only match at word boundaries */
+  wholeWord?: boolean
+  /** Let matches span lines; implied by patterns containing `\n` */
+  multiline?: boolean
   includeHidden?: boolean
   disableIgnore?: boolean
   disableGitignore?: boolean
   maxDepth?: number
+  /**
+   * Descend into symlinked folders and search symlinked files (default: false).
+   * Links back into a folder that is already being walked are skipped.
+   */
+  followSymlinks?: boolean
+  /** Skip files larger than this many bytes */
+  maxFilesize?: number
+  /**
+   * Only search files matching one of these globs. As in VS Code's search view,
+   * `*.ts` or `src` match at any depth unless anchored with `./`
+   */
   includePatterns?: Array<string>
+  /** Skip files and folders matching these globs, expanded like `include_patterns` */
   excludePatterns?: Array<string>
+  /** `files.exclude` style globs relative to the root */
+  exclude?: ExcludeRules
+  /** `files.exclude` style globs relative to folders below the root */
+  folderExcludes?: Array<FolderExclude>
+  /** Stop once this many matches have been found and flag the search as truncated */
+  maxResults?: number
+  /** Lines of context to report before each match */
+  beforeContext?: number
+  /** Lines of context to report after each match */
+  afterContext?: number
+  /** Trim match previews and context lines to this many UTF-16 code units (default: 1000, 0 disables trimming) */
+  previewCharsPerLine?: number
+  /**
+   * VS Code encoding id or WHATWG label of files without a BOM; unless set
+   * or unknown, each file's encoding is guessed
+   */
+  encoding?: string
+}
+/**
+ * Globs mapped to `true` to exclude what they match, or to a clause that
+ * only excludes matches with a certain sibling, like VS Code's `files.exclude`
+ */
+export type ExcludeRules = Record<string, boolean | ExcludeClause>
+export interface ExcludeClause {
+  /** The sibling a match needs to be excluded, e.g. `$(basename).ts` for `*.js` */
+  when: string
+}
+export interface FolderExclude {
+  /** Relative to the searched root */
+  folder: string
+  exclude: ExcludeRules
 }
 export interface SearchResult {
   filePath: string
   matches: Array<Match>
+  /** Lines around the matches requested through `before_context`/`after_context`, in file order */
+  context: Array<ContextLine>
 }
+/** One occurrence of the pattern. Lines are 1-based, columns are 0-based UTF-16 offsets. */
 export interface Match {
   lineNumber: number
   columnStart: number
+  /** Differs from `line_number` for multiline matches */
+  endLineNumber: number
   columnEnd: number
+  /** The matched line(s), trimmed around the match when longer than `preview_chars_per_line` */
+  text: string
+  /** Where the match lies within `text` */
+  previewColumnStart: number
+  previewColumnEnd: number
+}
+export interface ContextLine {
+  lineNumber: number
   text: string
 }
+export interface SearchProgress {
+  filesScanned: number
+  filesMatched: number
+}
+export interface SearchBatch {
+  results: Array<SearchResult>
+  progress: SearchProgress
+}
+export interface SearchSummary {
+  filesScanned: number
+  filesMatched: number
+  matchCount: number
+  /** The walk stopped early because `max_results` was reached */
+  truncated: boolean
+  durationMs: number
+}
+export interface ReplaceOptions {
+  /** Keep the casing of each match, e.g. `foo`/`Foo`/`FOO` become `bar`/`Bar`/`BAR` */
+  preserveCase?: boolean
+  /** Unchanged lines shown around each change (default: 3) */
+  contextLines?: number
+}
+export interface ReplacePreview {
+  /** Pass to `apply_replace` or `discard_replace` */
+  id: number
+  files: Array<FileReplacePreview>
+  replacementCount: number
+}
+export interface FileReplacePreview {
+  filePath: string
+  replacementCount: number
+  hunks: Array<DiffHunk>
+}
+/**
+ * A unified diff hunk in jsdiff's `structuredPatch` shape: `lines` start with
+ * `' '`, `'-'` or `'+'`, and line numbers are 1-based
+ */
+export interface DiffHunk {
+  oldStart: number
+  oldLines: number
+  newStart: number
+  newLines: number
+  lines: Array<string>
+}
+export interface ReplaceSummary {
+  filesChanged: number
+  replacementCount: number
+}
+export interface FuzzyMatch {
+  /** Relative to the searched root, with `/` separators */
+  path: string
+  score: number
+  /** The matched characters of `path` as UTF-16 ranges */
+  highlights: Array<HighlightRange>
+}
+/** A half-open `[start, end)` range of UTF-16 code units */
+export interface HighlightRange {
+  start: number
+  end: number
+}
+export interface IndexOptions {
+  /** Folder to save the index in; without it the index lives in memory only */
+  storageDir?: string
+  /** Larger files are not indexed and always searched (default: 16 MiB) */
+  maxFileSize?: number
+}
+export interface IndexStats {
+  files: number
+  trigrams: number
+  /** Binary or oversized files, searched by every query */
+  unindexedFiles: number
+  durationMs: number
+}
 export interface OperationResult {
   name: string
   durationMs: number
@@ -42,13 +332,26 @@ export interface MetricsSummary {
   memoryUsageMb: number
   activeOperations: number
 }
+export interface ContextCacheOptions {
+  /** Entries older than this are dropped unless cached with their own TTL (default: 5 min) */
+  ttlMs?: number
+  /** Memory budget for all entries; least recently used entries are evicted first (default: 32 MiB) */
+  maxBytes?: number
+}
 export interface ContextRequest {
   filePath?: string
   projectPath?: string
   cursorPosition?: Position
   selectedText?: string
+  /**
+   * Look up the symbol at the cursor in an index of `project_path`
+   * (default: true); there are no symbols without a `project_path`
+   */
   includeSymbols?: boolean
+  /** How many lines of the file to preview around the cursor (default 100) */
+  previewLines?: number
 }
+/** A 0-based line and column, as in VS Code */
 export interface Position {
   line: number
   column: number
@@ -62,24 +365,54 @@ export interface Context {
 export interface FileContext {
   path: string
   contentPreview: string
+  /** The 0-based line of the file that `content_preview` starts at */
+  previewStartLine: number
+  /** The VS Code language id, e.g. `typescriptreact` */
   language: string
+  /** The modules the file imports; empty for files over 1 MiB, which are not parsed */
   imports: Array<string>
+  /** The names the file exports; empty for files over 1 MiB */
   exports: Array<string>
 }
 export interface ProjectContext {
   rootPath: string
+  /** The most specific framework detected, or empty when there is none */
   framework: string
+  /**
+   * Every framework detected from dependencies and marker files, then
+   * `VS Code extension` for extensions
+   */
+  frameworks: Array<string>
+  /** The manifests found at the root, e.g. `package.json` */
+  manifests: Array<string>
+  /** `name@version`, or just `name` when the manifest pins no version */
   dependencies: Array<string>
+  /** File counts by language and top-level folder */
   structureSummary: string
 }
 export interface SymbolContext {
+  /**
+   * `path:line:column kind name: source line` for each definition of the
+   * symbol at the cursor, or of the file's own symbols without one
+   */
   definitions: Array<string>
+  /** References to the symbol at the cursor, in the same form */
   references: Array<string>
+  /** Definitions of the classes, interfaces and types the file refers to */
   types: Array<string>
 }
 export interface ContextMetadata {
   preparationTimeMs: number
   totalTokens: number
+  fileTokens: number
+  projectTokens: number
+  symbolTokens: number
+}
+/** The parts of a `Context` that `fitToBudget` can trim */
+export const enum ContextSection {
+  File = 'File',
+  Symbols = 'Symbols',
+  Project = 'Project'
 }
 export interface Task {
   taskType: string
@@ -91,17 +424,170 @@ export interface ModelSelection {
   modelId: string
   reasoning: string
 }
+/**
+ * Cancels native operations from JS. One token may be passed to several calls
+ * to cancel them together; a cancelled token stays cancelled.
+ */
+export declare class CancellationToken {
+  constructor()
+  /** Reject every pending operation this token was passed to with a `Cancelled` error */
+  cancel(): void
+  get isCancelled(): boolean
+}
 export declare class RustFileOperations {
   constructor()
-  readFile(path: string): Promise<Buffer>
-  writeFile(path: string, data: Buffer): Promise<void>
-  readDir(path: string): Promise<Array<string>>
+  /**
+   * Set the file size in bytes at which reads of read-only files switch to
+   * memory-mapped buffers
+   */
+  setMmapThreshold(bytes: number): void
+  /** Configure the read cache; omitted options fall back to their defaults */
+  configureCache(options?: CacheOptions | undefined | null): void
+  /**
+   * Drop cached contents and stats of `path` and everything below it, e.g.
+   * when a file watcher reports a change made by another process
+   */
+  invalidateCache(path: string): void
+  clearCache(): void
+  /**
+   * Read a whole file.
+   *
+   * Files of at least the mmap threshold are mapped rather than copied only
+   * when they are read-only, on the assumption that nobody truncates such a
+   * file while it is mapped. That is a deliberate trade-off, not a guarantee:
+   * the owner can make the file writable again and root ignores permissions,
+   * and truncating a mapped file crashes the extension host on next access.
+   * Writable files are always copied.
+   */
+  readFile(path: string, token?: CancellationToken | undefined | null): Promise<Buffer>
+  /**
+   * Read a file as text, decoding it from the encoding announced by its BOM,
+   * the requested one or, with `autoGuess`, the one it looks like
+   */
+  readTextFile(path: string, options?: ReadTextFileOptions | undefined | null, token?: CancellationToken | undefined | null): Promise<TextFileContents>
+  /**
+   * Read `length` bytes starting at `offset`; the result is shorter if the file ends first.
+   *
+   * Ranges of at least the mmap threshold are mapped under the same read-only
+   * policy as `readFile`; writable files are always copied.
+   */
+  readFileRange(path: string, offset: number, length: number, token?: CancellationToken | undefined | null): Promise<Buffer>
+  writeFile(path: string, data: Buffer, options?: WriteFileOptions | undefined | null): Promise<void>
+  /**
+   * List the names in a directory. Names that are not valid UTF-8 come back
+   * as a `Buffer` of their bytes, as Node's `readdir` does with `encoding: 'buffer'`.
+   */
+  readDir(path: string, token?: CancellationToken | undefined | null): Promise<Array<string | Buffer>>
+  /** List a directory with VS Code file types, symlink flags, sizes and mtimes in one call */
+  readDirWithTypes(path: string, token?: CancellationToken | undefined | null): Promise<Array<DirEntryInfo>>
+  /** Describe every entry below `root` using a parallel, gitignore-aware walk */
+  scanTree(root: string, options?: ScanOptions | undefined | null, token?: CancellationToken | undefined | null): Promise<Array<ScanEntry>>
+  /** Stat a path, following symlinks but flagging them via `isSymlink` */
   stat(path: string): Promise<FileStats>
+  /** Stat a path without following a final symlink */
+  lstat(path: string): Promise<FileStats>
+  /** Resolve symlinks and `.`/`..` components to an absolute path */
+  realpath(path: string): Promise<string>
+  /** Delete a file or folder; non-empty folders need `recursive` */
+  delete(path: string, options?: DeleteOptions | undefined | null): Promise<void>
+  rename(from: string, to: string, options?: FileOverwriteOptions | undefined | null): Promise<void>
+  /** Copy a file or folder tree, cloning file contents where the filesystem supports it */
+  copy(from: string, to: string, options?: FileOverwriteOptions | undefined | null): Promise<void>
+  /** Create a folder and any missing parents */
+  mkdirp(path: string): Promise<void>
+  /**
+   * Open a file and return a handle for `read`/`write`/`ftruncate`/`close`.
+   * With `create` the file is opened for writing and truncated, otherwise read-only.
+   */
+  open(path: string, options?: OpenFileOptions | undefined | null): Promise<number>
+  /** Read up to `length` bytes at `position`; a shorter buffer means end of file */
+  read(fd: number, position: number, length: number, token?: CancellationToken | undefined | null): Promise<Buffer>
+  /** Write the whole buffer at `position` and return the number of bytes written */
+  write(fd: number, position: number, data: Buffer): Promise<number>
+  ftruncate(fd: number, length: number): Promise<void>
+  /** Close a handle, flushing written data to disk first */
+  close(fd: number): Promise<void>
+}
+/**
+ * Recursive native file watcher delivering batched, coalesced change events.
+ *
+ * Each watched root is served by its own background thread, so one slow or
+ * overflowing tree never delays events from another.
+ */
+export declare class FileWatcher {
+  constructor()
+  /**
+   * Start watching `root` recursively. `callback` receives `(err, events)` for every batch.
+   * The tree is registered in the background; failures there, such as an exhausted
+   * inotify watch limit, are passed to `callback` as `err`.
+   */
+  watch(root: string, options: WatcherOptions | undefined | null, callback: (err: Error | null, events: FileChangeEvent[]) => void): void
+  /** Stop watching a root previously passed to `watch` */
+  unwatch(root: string): boolean
+  /** Stop all watches */
+  dispose(): void
+  get watchedRoots(): Array<string>
 }
 export declare class SearchEngine {
   constructor()
-  searchPattern(rootPath: string, pattern: string, options?: SearchOptions | undefined | null): Promise<Array<SearchResult>>
-  searchFiles(rootPath: string, filePattern: string): Promise<Array<string>>
+  searchPattern(rootPath: string, pattern: string, options?: SearchOptions | undefined | null, token?: CancellationToken | undefined | null): Promise<Array<SearchResult>>
+  /**
+   * Search like `search_pattern`, but deliver results to `callback` in batches
+   * while the walk is still running. Each batch carries the progress so far;
+   * the returned promise resolves with the final totals after the last batch
+   * has been handled.
+   */
+  searchPatternStream(rootPath: string, pattern: string, options: SearchOptions | undefined | null, callback: (err: Error | null, batch: SearchBatch) => void, token?: CancellationToken | undefined | null): Promise<SearchSummary>
+  /**
+   * Structural search: find code matching `pattern`, a snippet of the VS Code
+   * language `language_id` (TypeScript, JavaScript, Rust, Python or Go) in which
+   * `$NAME` stands for any single syntax node and `$$$NAME` for any number
+   * of them, e.g. `foo($A, $B)` for calls to `foo` with two arguments. Only
+   * files of that language are searched, and results are streamed like
+   * those of `search_pattern_stream`. The pattern and context options of
+   * `options` do not apply.
+   */
+  searchStructuralStream(rootPath: string, pattern: string, languageId: string, options: SearchOptions | undefined | null, callback: (err: Error | null, batch: SearchBatch) => void, token?: CancellationToken | undefined | null): Promise<SearchSummary>
+  /**
+   * Preview replacing every match of `pattern` with `replacement` as diff
+   * hunks per file. Nothing is written until `apply_replace` is called with
+   * the returned id; previews are kept until applied or discarded.
+   */
+  replacePreview(rootPath: string, pattern: string, replacement: string, options?: SearchOptions | undefined | null, replaceOptions?: ReplaceOptions | undefined | null, token?: CancellationToken | undefined | null): Promise<ReplacePreview>
+  /**
+   * Write the replacements of a preview, optionally only for some of its
+   * files. Fails with `FileModified` before writing anything if a file
+   * changed since the preview; if a write fails, files already written are
+   * restored. The preview cannot be applied twice.
+   */
+  applyReplace(previewId: number, filePaths?: Array<string> | undefined | null, token?: CancellationToken | undefined | null): Promise<ReplaceSummary>
+  /** Drop a preview without applying it */
+  discardReplace(previewId: number): boolean
+  /**
+   * Build a trigram index of `root_path` so searches below it only read files
+   * that can match. With `storage_dir` the index is saved there and reused by
+   * later calls, rereading only files whose size or mtime changed. Searches
+   * that include hidden or ignored files, or limit the depth, still walk.
+   */
+  enableIndex(rootPath: string, options?: IndexOptions | undefined | null, token?: CancellationToken | undefined | null): Promise<IndexStats>
+  /** Bring an enabled index up to date with file watcher events */
+  updateIndex(rootPath: string, changes: Array<FileChangeEvent>, token?: CancellationToken | undefined | null): Promise<IndexStats>
+  /** Save and drop the index of `root_path`. Resolves to false if there was none. */
+  disableIndex(rootPath: string): Promise<boolean>
+  /**
+   * Quick Open: rank the files below `root_path` by how well their relative
+   * paths fuzzy-match `query`, best first. The file list is walked on first
+   * use and kept in memory; keep it current with `update_file_list`.
+   */
+  fuzzyFindFiles(rootPath: string, query: string, limit?: number | undefined | null, token?: CancellationToken | undefined | null): Promise<Array<FuzzyMatch>>
+  /**
+   * Apply file watcher events to the Quick Open list of `root_path`.
+   * Resolves to the number of files listed, or 0 if no list is kept.
+   */
+  updateFileList(rootPath: string, changes: Array<FileChangeEvent>, token?: CancellationToken | undefined | null): Promise<number>
+  /** Drop the Quick Open list of `root_path`. Resolves to false if there was none. */
+  forgetFileList(rootPath: string): Promise<boolean>
+  searchFiles(rootPath: string, filePattern: string, token?: CancellationToken | undefined | null): Promise<Array<string>>
 }
 export declare class PerformanceMonitor {
   constructor()
@@ -113,10 +599,49 @@ export declare class PerformanceMonitor {
 export type AIOrchestrator = AiOrchestrator
 export declare class AiOrchestrator {
   constructor()
-  prepareContext(request: ContextRequest): Promise<Context>
-  cacheContext(key: string, context: Context): Promise<void>
+  /**
+   * Gather the file, project and symbol context of `request`. Symbols are
+   * looked up in an index of the project that is built on first use, until
+   * `token` is cancelled.
+   */
+  prepareContext(request: ContextRequest, token?: CancellationToken | undefined | null): Promise<Context>
+  /**
+   * Count tokens with the BPE ranks in a tiktoken file such as
+   * `cl100k_base.tiktoken`. `encoding` is `cl100k_base` or `o200k_base`
+   * and defaults to the one the file is named after. Until a tokenizer is
+   * loaded, token counts are estimated from text length.
+   */
+  loadTokenizer(path: string, encoding?: string | undefined | null): Promise<void>
+  countTokens(text: string): number
+  /**
+   * Trim `context` to at most `max_tokens`, keeping the sections earlier
+   * in `priorities` (default file, symbols, project) intact the longest
+   */
+  fitToBudget(context: Context, maxTokens: number, priorities?: Array<ContextSection> | undefined | null): Promise<Context>
+  /**
+   * Cache `context` under `key` until it is evicted, invalidated or
+   * `ttl_ms` passes (default: the configured TTL)
+   */
+  cacheContext(key: string, context: Context, ttlMs?: number | undefined | null): Promise<void>
   getCachedContext(key: string): Promise<Context | null>
+  /**
+   * Set the TTL and memory budget of the context cache, evicting entries
+   * that no longer fit
+   */
+  configureContextCache(options: ContextCacheOptions): Promise<void>
+  /**
+   * Drop every cached context that includes `path`, or a file below it,
+   * e.g. after the file was edited. Resolves to how many were dropped.
+   */
+  invalidateCachedContexts(path: string): Promise<number>
   routeToModel(task: Task): Promise<ModelSelection>
+  /**
+   * Apply file watcher events to the symbol index of `root_path`.
+   * Resolves to the number of files indexed, or 0 if it has no index yet.
+   */
+  updateSymbolIndex(rootPath: string, changes: Array<FileChangeEvent>, token?: CancellationToken | undefined | null): Promise<number>
+  /** Drop the symbol index of `root_path`. Resolves to false if there was none. */
+  forgetSymbolIndex(rootPath: string): Promise<boolean>
 }
 export type CmdShiftAI = CmdShiftAi
 export declare class CmdShiftAi {
@@ -0,0 +1,224 @@
+use super::tokenizer::{self, Tokenizer};
+use super::{Context, ContextSection, FileContext, ProjectContext, SymbolContext};
+
+/// Sections in the order they keep their tokens when no priorities are given
+pub const DEFAULT_PRIORITIES: [ContextSection; 3] = [ContextSection::File, ContextSection::Symbols, ContextSection::Project];
+
+/// Count the tokens of every section of `context` into its metadata and
+/// return the total
+pub fn measure(context: &mut Context, tokenizer: Option<&Tokenizer>) -> usize {
+    let file = tokenizer::count(tokenizer, &file_text(&context.file));
+    let project = tokenizer::count(tokenizer, &project_text(&context.project));
+    let symbols = tokenizer::count(tokenizer, &symbol_text(&context.symbols));
+    context.metadata.file_tokens = file as f64;
+    context.metadata.project_tokens = project as f64;
+    context.metadata.symbol_tokens = symbols as f64;
+    context.metadata.total_tokens = (file + project + symbols) as f64;
+    file + project + symbols
+}
+
+/// Trim `context` until it fits in `max_tokens`, taking from the sections
+/// missing from `priorities` first and then from the last listed up. Within
+/// a section the least useful parts go first: the edges of the file preview
+/// before its imports and exports, references before definitions, and the
+/// structure summary before dependencies. Paths, languages, frameworks and
+/// manifests are never trimmed, so a tiny budget can still be exceeded.
+pub fn fit(context: &mut Context, max_tokens: usize, priorities: &[ContextSection], tokenizer: Option<&Tokenizer>) {
+    let mut order: Vec<ContextSection> = DEFAULT_PRIORITIES.into_iter().rev().filter(|section| !priorities.contains(section)).collect();
+    order.extend(priorities.iter().rev().copied());
+
+    for section in order {
+        let total = measure(context, tokenizer);
+        if total <= max_tokens {
+            return;
+        }
+        let count = |text: String| tokenizer::count(tokenizer, &text);
+        let section_tokens = match section {
+            ContextSection::File => context.metadata.file_tokens,
+            ContextSection::Project => context.metadata.project_tokens,
+            ContextSection::Symbols => context.metadata.symbol_tokens,
+        } as usize;
+        let target = section_tokens.saturating_sub(total - max_tokens);
+        match section {
+            ContextSection::File => fit_file(&mut context.file, target, &|file| count(file_text(file))),
+            ContextSection::Project => fit_project(&mut context.project, target, &|project| count(project_text(project))),
+            ContextSection::Symbols => fit_symbols(&mut context.symbols, target, &|symbols| count(symbol_text(symbols))),
+        }
+    }
+    measure(context, tokenizer);
+}
+
+fn fit_file(file: &mut FileContext, target: usize, count: &dyn Fn(&FileContext) -> usize) {
+    let lines = file.content_preview.lines().count();
+    // The preview is centered on the cursor, so keep its middle
+    let fitted = shrink(file, target, lines, count, |file, keep| {
+        let lines: Vec<&str> = file.content_preview.lines().collect();
+        let front = (lines.len() - keep) / 2;
+        file.content_preview = lines[front..front + keep].join("\n");
+        file.preview_start_line += front as f64;
+    });
+    if !fitted {
+        let exports = file.exports.len();
+        if !shrink(file, target, exports, count, |file, keep| file.exports.truncate(keep)) {
+            let imports = file.imports.len();
+            shrink(file, target, imports, count, |file, keep| file.imports.truncate(keep));
+        }
+    }
+}
+
+fn fit_project(project: &mut ProjectContext, target: usize, count: &dyn Fn(&ProjectContext) -> usize) {
+    let lines = project.structure_summary.lines().count();
+    let fitted = shrink(project, target, lines, count, |project, keep| {
+        project.structure_summary = project.structure_summary.lines().take(keep).collect::<Vec<_>>().join("\n");
+    });
+    if !fitted {
+        let dependencies = project.dependencies.len();
+        shrink(project, target, dependencies, count, |project, keep| project.dependencies.truncate(keep));
+    }
+}
+
+fn fit_symbols(symbols: &mut SymbolContext, target: usize, count: &dyn Fn(&SymbolContext) -> usize) {
+    let references = symbols.references.len();
+    if !shrink(symbols, target, references, count, |symbols, keep| symbols.references.truncate(keep)) {
+        let types = symbols.types.len();
+        if !shrink(symbols, target, types, count, |symbols, keep| symbols.types.truncate(keep)) {
+            let definitions = symbols.definitions.len();
+            shrink(symbols, target, definitions, count, |symbols, keep| symbols.definitions.truncate(keep));
+        }
+    }
+}
+
+/// Keep as many of the `len` items of one part of `section` as fit in
+/// `target` tokens, where `keep(section, n)` cuts that part down to `n`
+/// items. Returns false when the section is still too big without any.
+fn shrink<T: Clone>(section: &mut T, target: usize, len: usize, count: &dyn Fn(&T) -> usize, keep: impl Fn(&mut T, usize)) -> bool {
+    if count(section) <= target {
+        return true;
+    }
+    let kept = |n| {
+        let mut trimmed = section.clone();
+        keep(&mut trimmed, n);
+        trimmed
+    };
+    if count(&kept(0)) > target {
+        *section = kept(0);
+        return false;
+    }
+    // Counts only grow with the items kept: `lo` fits and `hi` does not
+    let (mut lo, mut hi) = (0, len);
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        if count(&kept(mid)) <= target {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    *section = kept(lo);
+    true
+}
+
+fn file_text(file: &FileContext) -> String {
+    let mut parts = vec![file.path.as_str(), file.language.as_str()];
+    parts.extend(file.imports.iter().chain(&file.exports).map(String::as_str));
+    parts.push(&file.content_preview);
+    lines(parts)
+}
+
+fn project_text(project: &ProjectContext) -> String {
+    let mut parts = vec![project.root_path.as_str(), project.framework.as_str()];
+    parts.extend(project.manifests.iter().chain(&project.dependencies).map(String::as_str));
+    parts.push(&project.structure_summary);
+    lines(parts)
+}
+
+fn symbol_text(symbols: &SymbolContext) -> String {
+    lines(symbols.definitions.iter().chain(&symbols.references).chain(&symbols.types).map(String::as_str).collect())
+}
+
+/// The non-empty parts of a section, one per line, roughly as a prompt would hold them
+fn lines(parts: Vec<&str>) -> String {
+    parts.into_iter().filter(|part| !part.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tokenizer::tests::test_tokenizer;
+    use super::super::ContextMetadata;
+    use super::*;
+
+    fn context() -> Context {
+        let preview: Vec<String> = (10..30).map(|i| format!("line {}", i)).collect();
+        Context {
+            file: FileContext {
+                path: "src/a.ts".to_string(),
+                content_preview: preview.join("\n"),
+                preview_start_line: 10.0,
+                language: "typescript".to_string(),
+                imports: vec!["react".to_string()],
+                exports: vec!["App".to_string()],
+            },
+            project: ProjectContext {
+                root_path: "/p".to_string(),
+                framework: "React".to_string(),
+                dependencies: (0..10).map(|i| format!("dep{}@1.0.0", i)).collect(),
+                structure_summary: "20 files in 3 folders\nLanguages: typescript (20)\nTop level: src/ (20 files)".to_string(),
+                ..Default::default()
+            },
+            symbols: SymbolContext {
+                definitions: vec!["function App()".to_string()],
+                references: (0..10).map(|i| format!("src/b.ts:{}", i)).collect(),
+                types: vec![],
+            },
+            metadata: ContextMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn test_measure() {
+        let mut context = context();
+        let tokenizer = test_tokenizer();
+        let total = measure(&mut context, Some(&tokenizer));
+        assert_eq!(total as f64, context.metadata.file_tokens + context.metadata.project_tokens + context.metadata.symbol_tokens);
+        assert_eq!(context.metadata.total_tokens, total as f64);
+        // The test table has few merges, so it needs more tokens than the estimate
+        assert!(total > measure(&mut context, None));
+    }
+
+    #[test]
+    fn test_fit_by_priority() {
+        let tokenizer = test_tokenizer();
+        let full = measure(&mut context(), Some(&tokenizer));
+
+        // Project is listed last so it gives up its summary and dependencies first
+        let mut fitted = context();
+        fit(&mut fitted, full - 40, &DEFAULT_PRIORITIES, Some(&tokenizer));
+        assert!(fitted.metadata.total_tokens as usize <= full - 40);
+        assert_eq!(fitted.file.content_preview, context().file.content_preview);
+        assert_eq!(fitted.symbols.references.len(), 10);
+        assert!(fitted.project.structure_summary.lines().count() < 3);
+
+        // The preview keeps its middle lines when it is trimmed
+        let mut fitted = context();
+        fit(&mut fitted, full - 40, &[ContextSection::Project, ContextSection::Symbols, ContextSection::File], Some(&tokenizer));
+        assert!(fitted.metadata.total_tokens as usize <= full - 40);
+        assert_eq!(fitted.project.structure_summary, context().project.structure_summary);
+        let kept = fitted.file.content_preview.lines().count();
+        assert!(kept < 20);
+        assert_eq!(fitted.file.preview_start_line as usize, 10 + (20 - kept) / 2);
+        assert!(fitted.file.content_preview.starts_with(&format!("line {}", fitted.file.preview_start_line)));
+
+        // Sections left out of the priorities are trimmed before any listed one
+        let mut fitted = context();
+        fit(&mut fitted, full - 40, &[ContextSection::Project], Some(&tokenizer));
+        assert!(fitted.symbols.references.len() < 10);
+        assert_eq!(fitted.file.content_preview, context().file.content_preview);
+        assert_eq!(fitted.project.dependencies.len(), 10);
+
+        // A budget below what can never be trimmed still keeps the essentials
+        let mut fitted = context();
+        fit(&mut fitted, 1, &DEFAULT_PRIORITIES, Some(&tokenizer));
+        assert_eq!((fitted.file.path.as_str(), fitted.project.framework.as_str()), ("src/a.ts", "React"));
+        assert!(fitted.file.content_preview.is_empty() && fitted.project.dependencies.is_empty() && fitted.symbols.definitions.is_empty());
+    }
+}
@@ -0,0 +1,562 @@
+use super::FileContext;
+use crate::error::{CoreError, CoreResult};
+use crate::file_operations::encoding;
+use crate::search_engine::grammar::Grammar;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use tree_sitter::Node;
+
+/// Lines of the file around the cursor included in a preview by default
+pub const DEFAULT_PREVIEW_LINES: u32 = 100;
+/// Larger files are not parsed, and only the lines of their preview are kept
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// Lines of larger files are cut to this many bytes in the preview
+const MAX_LINE_LEN: usize = 1024;
+
+/// Read `path` and describe it: its language, what it imports and exports,
+/// and up to `preview_lines` lines centered on the 0-based `cursor_line`, or
+/// from the top of the file without a cursor. Files over `MAX_FILE_SIZE`
+/// have no imports or exports.
+pub fn analyze(path: &Path, cursor_line: Option<usize>, preview_lines: usize) -> CoreResult<FileContext> {
+    let read_error = |e: io::Error| CoreError::io("Failed to read file", path, e);
+    let mut file = File::open(path).map_err(read_error)?;
+    if file.metadata().map_err(read_error)?.len() > MAX_FILE_SIZE {
+        let (first_line, content_preview, preview_start_line) = stream_preview(file, cursor_line, preview_lines).map_err(read_error)?;
+        return Ok(FileContext {
+            path: path.to_string_lossy().to_string(),
+            content_preview,
+            preview_start_line: preview_start_line as f64,
+            language: detect_language(path, &first_line).to_string(),
+            imports: Vec::new(),
+            exports: Vec::new(),
+        });
+    }
+
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).map_err(read_error)?;
+    let head = &bytes[..bytes.len().min(encoding::SNIFF_LEN)];
+    let text = match encoding::detect(head, head.len() == bytes.len()) {
+        Some(detected) => encoding::decode(&bytes, detected).0,
+        // Binary files have no text worth showing
+        None => String::new(),
+    };
+
+    let language = detect_language(path, &text);
+    let (imports, exports) = match Grammar::for_language_id(language).and_then(|grammar| Some((grammar, grammar.parse(text.as_bytes())?))) {
+        Some((grammar, tree)) => outline(grammar, tree.root_node(), text.as_bytes()),
+        None => (Vec::new(), Vec::new()),
+    };
+    let (content_preview, preview_start_line) = preview(&text, cursor_line, preview_lines);
+
+    Ok(FileContext {
+        path: path.to_string_lossy().to_string(),
+        content_preview,
+        preview_start_line: preview_start_line as f64,
+        language: language.to_string(),
+        imports,
+        exports,
+    })
+}
+
+/// The VS Code language id of a file, from its name or else its shebang line
+pub fn detect_language(path: &Path, text: &str) -> &'static str {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_ascii_lowercase()).unwrap_or_default();
+    let by_name = match name.as_str() {
+        "dockerfile" => Some("dockerfile"),
+        "makefile" | "gnumakefile" => Some("makefile"),
+        "cmakelists.txt" => Some("cmake"),
+        _ => name.rsplit_once('.').and_then(|(_, extension)| language_for_extension(extension)),
+    };
+    by_name.or_else(|| shebang_language(text)).unwrap_or("plaintext")
+}
+
+fn language_for_extension(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "typescriptreact",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "javascriptreact",
+        "rs" => "rust",
+        "py" | "pyi" | "pyw" => "python",
+        "go" => "go",
+        "json" => "json",
+        "jsonc" => "jsonc",
+        "md" | "markdown" => "markdown",
+        "sh" | "bash" | "zsh" => "shellscript",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "rb" => "ruby",
+        "php" => "php",
+        "swift" => "swift",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "less" => "less",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" => "xml",
+        "sql" => "sql",
+        "lua" => "lua",
+        "pl" | "pm" => "perl",
+        "r" => "r",
+        "dart" => "dart",
+        "scala" => "scala",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        _ => return None,
+    })
+}
+
+/// The language of a script from its `#!` line, e.g. `#!/usr/bin/env python3`
+fn shebang_language(text: &str) -> Option<&'static str> {
+    let line = text.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    // `python3.12` and `node18` run the same language as `python` and `node`
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(match program {
+        "python" | "pypy" => "python",
+        "node" | "nodejs" => "javascript",
+        "deno" | "bun" | "ts-node" | "tsx" => "typescript",
+        "sh" | "bash" | "zsh" | "dash" | "ksh" | "fish" => "shellscript",
+        "ruby" => "ruby",
+        "perl" => "perl",
+        "php" => "php",
+        "lua" => "lua",
+        "Rscript" => "r",
+        _ => return None,
+    })
+}
+
+/// Up to `budget` lines centered on `cursor_line`, and the line they start at
+fn preview(text: &str, cursor_line: Option<usize>, budget: usize) -> (String, usize) {
+    let lines: Vec<&str> = text.lines().collect();
+    let center = cursor_line.unwrap_or(0).min(lines.len().saturating_sub(1));
+    let first = if cursor_line.is_some() { center.saturating_sub(budget / 2) } else { 0 };
+    let last = (first + budget).min(lines.len());
+    let first = last.saturating_sub(budget);
+    (lines[first..last].join("\n"), first)
+}
+
+/// The first line of a file too large to read whole and the preview `preview`
+/// would give, decoding the file as it streams and keeping no more lines than
+/// the preview holds
+fn stream_preview(file: File, cursor_line: Option<usize>, budget: usize) -> io::Result<(String, String, usize)> {
+    let mut reader = BufReader::with_capacity(encoding::SNIFF_LEN, file);
+    let Some(detected) = encoding::detect(reader.fill_buf()?, false) else {
+        return Ok(Default::default());
+    };
+    let mut decoder = detected.new_decoder();
+
+    // Past the cursor's window the preview cannot change, and the file may
+    // end before it, so the last `budget` lines read are kept
+    let end = cursor_line.map_or(0, |line| line.saturating_sub(budget / 2)) + budget;
+    let mut first_line = None;
+    let mut window = VecDeque::with_capacity(budget + 1);
+    let (mut lines, mut line, mut text) = (0, String::new(), String::new());
+    let mut finish_line = |line: &mut String, lines: &mut usize| {
+        let line = std::mem::take(line);
+        first_line.get_or_insert_with(|| line.clone());
+        window.push_back(line);
+        if window.len() > budget {
+            window.pop_front();
+        }
+        *lines += 1;
+    };
+    while lines < end {
+        let bytes = reader.fill_buf()?;
+        let last = bytes.is_empty();
+        text.clear();
+        text.reserve(decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3));
+        let (_, read, _) = decoder.decode_to_string(bytes, &mut text, last);
+        reader.consume(read);
+
+        for piece in text.split_inclusive('\n') {
+            let content = piece.strip_suffix('\n');
+            let content = content.map_or(piece, |content| content.strip_suffix('\r').unwrap_or(content));
+            let mut cut = content.len().min(MAX_LINE_LEN.saturating_sub(line.len()));
+            while !content.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            line.push_str(&content[..cut]);
+            if piece.ends_with('\n') {
+                finish_line(&mut line, &mut lines);
+                if lines == end {
+                    break;
+                }
+            }
+        }
+        if last {
+            if !line.is_empty() {
+                finish_line(&mut line, &mut lines);
+            }
+            break;
+        }
+    }
+    let start = lines - window.len();
+    Ok((first_line.unwrap_or_default(), Vec::from(window).join("\n"), start))
+}
+
+/// The modules a file imports and the names it exports, in file order
+fn outline(grammar: Grammar, root: Node, source: &[u8]) -> (Vec<String>, Vec<String>) {
+    let mut outline = Outline { source, imports: Vec::new(), exports: Vec::new() };
+    match grammar {
+        Grammar::TypeScript | Grammar::Tsx | Grammar::JavaScript => outline.javascript(root),
+        Grammar::Rust => outline.rust(root),
+        Grammar::Python => outline.python(root),
+        Grammar::Go => outline.go(root),
+    }
+    (outline.imports, outline.exports)
+}
+
+struct Outline<'s> {
+    source: &'s [u8],
+    imports: Vec<String>,
+    exports: Vec<String>,
+}
+
+impl Outline<'_> {
+    fn text(&self, node: Node) -> String {
+        node.utf8_text(self.source).unwrap_or_default().to_string()
+    }
+
+    /// A string literal without its quotes
+    fn string(&self, node: Node) -> String {
+        self.text(node).trim_matches(|c| c == '"' || c == '\'' || c == '`').to_string()
+    }
+
+    fn import(&mut self, module: String) {
+        if !module.is_empty() && !self.imports.contains(&module) {
+            self.imports.push(module);
+        }
+    }
+
+    fn export(&mut self, name: String) {
+        if !name.is_empty() && !self.exports.contains(&name) {
+            self.exports.push(name);
+        }
+    }
+
+    fn export_field(&mut self, node: Node, field: &str) {
+        if let Some(name) = node.child_by_field_name(field) {
+            self.export(self.text(name));
+        }
+    }
+
+    fn javascript(&mut self, root: Node) {
+        let mut cursor = root.walk();
+        for node in root.named_children(&mut cursor) {
+            match node.kind() {
+                "import_statement" => {
+                    if let Some(source) = node.child_by_field_name("source") {
+                        self.import(self.string(source));
+                    }
+                }
+                "export_statement" => self.javascript_export(node),
+                _ => {}
+            }
+        }
+        // CommonJS `require` and dynamic `import()` may appear anywhere
+        self.visit(root, &mut |outline, node| {
+            if node.kind() != "call_expression" {
+                return;
+            }
+            let is_import = node
+                .child_by_field_name("function")
+                .is_some_and(|callee| callee.kind() == "import" || (callee.kind() == "identifier" && outline.text(callee) == "require"));
+            let argument = node.child_by_field_name("arguments").and_then(|args| args.named_child(0)).filter(|arg| arg.kind() == "string");
+            if let (true, Some(argument)) = (is_import, argument) {
+                outline.import(outline.string(argument));
+            }
+        });
+    }
+
+    fn javascript_export(&mut self, node: Node) {
+        let mut cursor = node.walk();
+        if node.children(&mut cursor).any(|child| child.kind() == "default") {
+            self.export("default".to_string());
+            return;
+        }
+        if let Some(declaration) = node.child_by_field_name("declaration") {
+            match declaration.kind() {
+                "lexical_declaration" | "variable_declaration" => {
+                    let mut cursor = declaration.walk();
+                    for declarator in declaration.named_children(&mut cursor).filter(|d| d.kind() == "variable_declarator") {
+                        self.export_field(declarator, "name");
+                    }
+                }
+                _ => self.export_field(declaration, "name"),
+            }
+            return;
+        }
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            match child.kind() {
+                "export_clause" => {
+                    let mut cursor = child.walk();
+                    for specifier in child.named_children(&mut cursor) {
+                        let name = specifier.child_by_field_name("alias").or_else(|| specifier.child_by_field_name("name"));
+                        if let Some(name) = name {
+                            self.export(self.text(name));
+                        }
+                    }
+                }
+                "namespace_export" => {
+                    if let Some(name) = child.named_child(0) {
+                        self.export(self.text(name));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if node.named_child_count() == 1 && node.child_by_field_name("source").is_some() {
+            self.export("*".to_string());
+        }
+    }
+
+    fn rust(&mut self, root: Node) {
+        let mut cursor = root.walk();
+        for node in root.named_children(&mut cursor) {
+            let mut inner = node.walk();
+            // `pub(crate)` and the like are not part of the public API
+            let public = node.children(&mut inner).any(|child| child.kind() == "visibility_modifier" && self.text(child) == "pub");
+            match node.kind() {
+                "use_declaration" => {
+                    if let Some(argument) = node.child_by_field_name("argument") {
+                        let path = self.text(argument);
+                        if public {
+                            self.export(path.clone());
+                        }
+                        self.import(path);
+                    }
+                }
+                "extern_crate_declaration" => {
+                    if let Some(name) = node.child_by_field_name("name") {
+                        self.import(self.text(name));
+                    }
+                }
+                "function_item" | "struct_item" | "enum_item" | "union_item" | "trait_item" | "type_item" | "const_item" | "static_item" | "mod_item"
+                    if public =>
+                {
+                    self.export_field(node, "name")
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn python(&mut self, root: Node) {
+        let mut public_names = Vec::new();
+        let mut all = None;
+        let mut cursor = root.walk();
+        for node in root.named_children(&mut cursor) {
+            match node.kind() {
+                "import_statement" => {
+                    let mut cursor = node.walk();
+                    for name in node.children_by_field_name("name", &mut cursor) {
+                        let module = if name.kind() == "aliased_import" { name.child_by_field_name("name").unwrap_or(name) } else { name };
+                        self.import(self.text(module));
+                    }
+                }
+                "import_from_statement" => {
+                    if let Some(module) = node.child_by_field_name("module_name") {
+                        self.import(self.text(module));
+                    }
+                }
+                "function_definition" | "class_definition" => public_names.extend(node.child_by_field_name("name").map(|n| self.text(n))),
+                "decorated_definition" => {
+                    let name = node.child_by_field_name("definition").and_then(|d| d.child_by_field_name("name"));
+                    public_names.extend(name.map(|n| self.text(n)));
+                }
+                "expression_statement" => {
+                    let Some(assignment) = node.named_child(0).filter(|n| n.kind() == "assignment") else { continue };
+                    let Some(target) = assignment.child_by_field_name("left").filter(|n| n.kind() == "identifier") else { continue };
+                    let name = self.text(target);
+                    if name == "__all__" {
+                        all = assignment.child_by_field_name("right");
+                    } else {
+                        public_names.push(name);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // `__all__` is the module's own account of its API
+        match all {
+            Some(list) => {
+                let mut cursor = list.walk();
+                for item in list.named_children(&mut cursor).filter(|item| item.kind() == "string") {
+                    self.export(self.string(item));
+                }
+            }
+            None => {
+                for name in public_names.into_iter().filter(|name| !name.starts_with('_')) {
+                    self.export(name);
+                }
+            }
+        }
+    }
+
+    fn go(&mut self, root: Node) {
+        self.visit(root, &mut |outline, node| {
+            if node.kind() == "import_spec" {
+                if let Some(path) = node.child_by_field_name("path") {
+                    outline.import(outline.string(path));
+                }
+            }
+        });
+
+        let mut cursor = root.walk();
+        for node in root.named_children(&mut cursor) {
+            let mut names = Vec::new();
+            match node.kind() {
+                "function_declaration" | "method_declaration" => names.extend(node.child_by_field_name("name")),
+                "type_declaration" | "const_declaration" | "var_declaration" => {
+                    let mut cursor = node.walk();
+                    for spec in node.named_children(&mut cursor) {
+                        let mut cursor = spec.walk();
+                        names.extend(spec.children_by_field_name("name", &mut cursor));
+                    }
+                }
+                _ => {}
+            }
+            // Go exports exactly the names that start with an uppercase letter
+            for name in names {
+                let name = self.text(name);
+                if name.starts_with(|c: char| c.is_uppercase()) {
+                    self.export(name);
+                }
+            }
+        }
+    }
+
+    /// Call `f` on `node` and every node below it
+    fn visit(&mut self, node: Node, f: &mut impl FnMut(&mut Self, Node)) {
+        let mut cursor = node.walk();
+        loop {
+            f(self, cursor.node());
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() || cursor.node() == node {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn outline_of(file: &str, code: &str) -> (Vec<String>, Vec<String>) {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(file);
+        std::fs::write(&path, code).unwrap();
+        let context = analyze(&path, None, 10).unwrap();
+        (context.imports, context.exports)
+    }
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect_language(Path::new("src/app.tsx"), ""), "typescriptreact");
+        assert_eq!(detect_language(Path::new("Dockerfile"), ""), "dockerfile");
+        assert_eq!(detect_language(Path::new("bin/tool"), "#!/usr/bin/env -S python3.12 -u\n"), "python");
+        assert_eq!(detect_language(Path::new("bin/serve"), "#!/usr/local/bin/node\n"), "javascript");
+        assert_eq!(detect_language(Path::new("notes"), "hello\n"), "plaintext");
+    }
+
+    #[test]
+    fn test_outline() {
+        let (imports, exports) = outline_of(
+            "index.ts",
+            "import React, { useState } from 'react';\nimport * as path from \"path\";\nconst fs = require('fs');\n\
+             export default class App {}\nexport const a = 1, b = 2;\nexport { c, d as e } from './util';\n\
+             export * from './types';\nexport interface Props {}\nexport function load() { return import('./lazy'); }\n",
+        );
+        assert_eq!(imports, ["react", "path", "fs", "./lazy"]);
+        assert_eq!(exports, ["default", "a", "b", "c", "e", "*", "Props", "load"]);
+
+        let (imports, exports) = outline_of(
+            "lib.rs",
+            "use std::collections::HashMap;\npub use crate::error::{CoreError, CoreResult};\nextern crate libc;\n\
+             pub fn run() {}\npub(crate) fn helper() {}\npub struct Config;\nmod private;\npub mod api;\n",
+        );
+        assert_eq!(imports, ["std::collections::HashMap", "crate::error::{CoreError, CoreResult}", "libc"]);
+        assert_eq!(exports, ["crate::error::{CoreError, CoreResult}", "run", "Config", "api"]);
+
+        let (imports, exports) = outline_of(
+            "app.py",
+            "import os, numpy as np\nfrom .models import User\nfrom ..core.db import session\n\ndef main(): pass\n\n@dataclass\nclass Settings: pass\n\n_cache = {}\nVERSION = '1'\n",
+        );
+        assert_eq!(imports, ["os", "numpy", ".models", "..core.db"]);
+        assert_eq!(exports, ["main", "Settings", "VERSION"]);
+        let (_, exports) = outline_of("api.py", "__all__ = ['main']\ndef main(): pass\ndef other(): pass\n");
+        assert_eq!(exports, ["main"]);
+
+        let (imports, exports) = outline_of(
+            "main.go",
+            "package main\n\nimport (\n\t\"fmt\"\n\tlog \"github.com/sirupsen/logrus\"\n)\n\nfunc Serve() {}\nfunc helper() {}\n\
+             func (s *Server) Start() {}\ntype Server struct{}\nconst Version, build = \"1\", \"2\"\n",
+        );
+        assert_eq!(imports, ["fmt", "github.com/sirupsen/logrus"]);
+        assert_eq!(exports, ["Serve", "Start", "Server", "Version"]);
+    }
+
+    #[test]
+    fn test_preview_window() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("lines.txt");
+        let text: Vec<String> = (0..50).map(|i| format!("line {}", i)).collect();
+        std::fs::write(&path, text.join("\n")).unwrap();
+
+        let around = analyze(&path, Some(20), 5).unwrap();
+        assert_eq!(around.preview_start_line, 18.0);
+        assert_eq!(around.content_preview, "line 18\nline 19\nline 20\nline 21\nline 22");
+        // The window keeps its size at the ends of the file
+        let end = analyze(&path, Some(49), 4).unwrap();
+        assert_eq!(end.content_preview, "line 46\nline 47\nline 48\nline 49");
+        let top = analyze(&path, None, 2).unwrap();
+        assert_eq!((top.content_preview.as_str(), top.language.as_str()), ("line 0\nline 1", "plaintext"));
+    }
+
+    #[test]
+    fn test_large_file_preview() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("bundle.js");
+        let mut text = "#!/usr/bin/env node\r\n".to_string();
+        text.extend((1..100_000).map(|i| format!("export const line{} = {};\r\n", i, i)));
+        std::fs::write(&path, &text).unwrap();
+        assert!(text.len() as u64 > MAX_FILE_SIZE);
+
+        // Large files are not outlined, but keep the same preview window
+        let around = analyze(&path, Some(50_000), 3).unwrap();
+        assert!(around.imports.is_empty() && around.exports.is_empty());
+        assert_eq!(around.preview_start_line, 49_999.0);
+        assert_eq!(around.content_preview, "export const line49999 = 49999;\nexport const line50000 = 50000;\nexport const line50001 = 50001;");
+        let end = analyze(&path, Some(200_000), 2).unwrap();
+        assert_eq!((end.preview_start_line, end.content_preview.as_str()), (99_998.0, "export const line99998 = 99998;\nexport const line99999 = 99999;"));
+        // Without an extension the language comes from the shebang
+        std::fs::rename(&path, temp_dir.path().join("bundle")).unwrap();
+        let top = analyze(&temp_dir.path().join("bundle"), None, 1).unwrap();
+        assert_eq!((top.content_preview.as_str(), top.language.as_str()), ("#!/usr/bin/env node", "javascript"));
+
+        // A single minified line is cut rather than read whole
+        let minified = temp_dir.path().join("min.js");
+        std::fs::write(&minified, "é".repeat(MAX_FILE_SIZE as usize)).unwrap();
+        let preview = analyze(&minified, Some(0), 10).unwrap().content_preview;
+        assert_eq!(preview, "é".repeat(MAX_LINE_LEN / 2));
+    }
+}
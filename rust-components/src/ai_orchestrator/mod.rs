@@ -1,12 +1,30 @@
+use crate::cancellation::CancelSignal;
+use crate::error::{blocking, CoreResult, JsResult};
+use crate::file_watcher::FileChangeEvent;
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
 use tokio::sync::RwLock;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::collections::HashMap;
+use std::time::Duration;
+use tokenizer::Tokenizer;
+
+mod budget;
+mod file_context;
+mod project;
+mod store;
+mod symbols;
+mod tokenizer;
+
+pub use store::ContextCacheOptions;
+use store::ContextStore;
 
 #[napi]
 pub struct AIOrchestrator {
     context_store: Arc<RwLock<ContextStore>>,
+    projects: Arc<project::ProjectCache>,
+    symbols: Arc<symbols::SymbolIndexes>,
+    tokenizer: Arc<parking_lot::RwLock<Option<Arc<Tokenizer>>>>,
 }
 
 #[napi]
@@ -15,57 +33,130 @@ impl AIOrchestrator {
     pub fn new() -> Self {
         AIOrchestrator {
             context_store: Arc::new(RwLock::new(ContextStore::new())),
+            projects: Arc::new(project::ProjectCache::default()),
+            symbols: Arc::new(symbols::SymbolIndexes::default()),
+            tokenizer: Arc::new(parking_lot::RwLock::new(None)),
         }
     }
 
+    /// Gather the file, project and symbol context of `request`. Symbols are
+    /// looked up in an index of the project that is built on first use, until
+    /// `token` is cancelled.
     #[napi]
-    pub async fn prepare_context(&self, request: ContextRequest) -> Result<Context> {
+    pub async fn prepare_context(&self, request: ContextRequest, token: Option<CancelSignal>) -> JsResult<Context> {
+        let signal = token.unwrap_or_default();
+        self.build_context(request, &signal).await.into()
+    }
+
+    async fn build_context(&self, request: ContextRequest, signal: &CancelSignal) -> CoreResult<Context> {
         let start = std::time::Instant::now();
 
         // Gather file context
         let file_context = if let Some(ref file_path) = request.file_path {
-            self.get_file_context(&file_path).await?
+            self.get_file_context(file_path, &request).await?
         } else {
             FileContext::default()
         };
 
         // Gather project context
         let project_context = if let Some(ref project_path) = request.project_path {
-            self.get_project_context(&project_path).await?
+            self.get_project_context(project_path).await?
         } else {
             ProjectContext::default()
         };
 
         // Build symbol context
-        let symbol_context = self.get_symbol_context(&request).await?;
+        signal.check()?;
+        let symbol_context = self.get_symbol_context(&request, signal).await?;
 
         let duration = start.elapsed();
         tracing::debug!("Context preparation took {:?}", duration);
 
-        Ok(Context {
+        let mut context = Context {
             file: file_context,
             project: project_context,
             symbols: symbol_context,
             metadata: ContextMetadata {
                 preparation_time_ms: duration.as_millis() as f64,
-                total_tokens: 0.0, // Would be calculated based on tokenizer
+                ..Default::default()
             },
+        };
+        budget::measure(&mut context, self.tokenizer().as_deref());
+        Ok(context)
+    }
+
+    /// Count tokens with the BPE ranks in a tiktoken file such as
+    /// `cl100k_base.tiktoken`. `encoding` is `cl100k_base` or `o200k_base`
+    /// and defaults to the one the file is named after. Until a tokenizer is
+    /// loaded, token counts are estimated from text length.
+    #[napi]
+    pub async fn load_tokenizer(&self, path: String, encoding: Option<String>) -> JsResult<()> {
+        let tokenizer = self.tokenizer.clone();
+        blocking(move || {
+            let loaded = Tokenizer::load(path.as_ref(), encoding.as_deref())?;
+            *tokenizer.write() = Some(Arc::new(loaded));
+            Ok(())
+        })
+        .await
+        .into()
+    }
+
+    #[napi]
+    pub fn count_tokens(&self, text: String) -> u32 {
+        tokenizer::count(self.tokenizer().as_deref(), &text) as u32
+    }
+
+    /// Trim `context` to at most `max_tokens`, keeping the sections earlier
+    /// in `priorities` (default file, symbols, project) intact the longest
+    #[napi]
+    pub async fn fit_to_budget(&self, context: Context, max_tokens: u32, priorities: Option<Vec<ContextSection>>) -> JsResult<Context> {
+        let tokenizer = self.tokenizer();
+        blocking(move || {
+            let mut context = context;
+            let priorities = priorities.unwrap_or_else(|| budget::DEFAULT_PRIORITIES.to_vec());
+            budget::fit(&mut context, max_tokens as usize, &priorities, tokenizer.as_deref());
+            Ok(context)
         })
+        .await
+        .into()
+    }
+
+    fn tokenizer(&self) -> Option<Arc<Tokenizer>> {
+        self.tokenizer.read().clone()
     }
 
+    /// Cache `context` under `key` until it is evicted, invalidated or
+    /// `ttl_ms` passes (default: the configured TTL)
     #[napi]
-    pub async fn cache_context(&self, key: String, context: Context) -> Result<()> {
+    pub async fn cache_context(&self, key: String, context: Context, ttl_ms: Option<u32>) -> Result<()> {
         let mut store = self.context_store.write().await;
-        store.cache(key, context);
+        store.cache(key, context, ttl_ms.map(|ttl| Duration::from_millis(ttl as u64)));
         Ok(())
     }
 
     #[napi]
     pub async fn get_cached_context(&self, key: String) -> Result<Option<Context>> {
-        let store = self.context_store.read().await;
+        let mut store = self.context_store.write().await;
         Ok(store.get(&key))
     }
 
+    /// Set the TTL and memory budget of the context cache, evicting entries
+    /// that no longer fit
+    #[napi]
+    pub async fn configure_context_cache(&self, options: ContextCacheOptions) -> Result<()> {
+        let mut store = self.context_store.write().await;
+        store.configure(&options);
+        Ok(())
+    }
+
+    /// Drop every cached context that includes `path`, or a file below it,
+    /// e.g. after the file was edited. Resolves to how many were dropped.
+    #[napi]
+    pub async fn invalidate_cached_contexts(&self, path: String) -> Result<u32> {
+        let mut store = self.context_store.write().await;
+        Ok(store.invalidate(Path::new(&path)) as u32)
+    }
+
     #[napi]
     pub async fn route_to_model(&self, task: Task) -> Result<ModelSelection> {
         // Intelligent model routing based on task characteristics
@@ -92,62 +183,52 @@ impl AIOrchestrator {
         })
     }
 
-    async fn get_file_context(&self, file_path: &str) -> Result<FileContext> {
-        // In real implementation, would analyze the file
-        Ok(FileContext {
-            path: file_path.to_string(),
-            content_preview: "// File content preview...".to_string(),
-            language: "typescript".to_string(),
-            imports: vec![],
-            exports: vec![],
-        })
+    async fn get_file_context(&self, file_path: &str, request: &ContextRequest) -> CoreResult<FileContext> {
+        let path = PathBuf::from(file_path);
+        let cursor_line = request.cursor_position.as_ref().map(|position| position.line.max(0.0) as usize);
+        let preview_lines = request.preview_lines.unwrap_or(file_context::DEFAULT_PREVIEW_LINES) as usize;
+        blocking(move || file_context::analyze(&path, cursor_line, preview_lines)).await
     }
 
-    async fn get_project_context(&self, project_path: &str) -> Result<ProjectContext> {
-        // In real implementation, would analyze the project
-        Ok(ProjectContext {
-            root_path: project_path.to_string(),
-            framework: "vscode-extension".to_string(),
-            dependencies: vec!["vscode".to_string()],
-            structure_summary: "Standard VS Code extension structure".to_string(),
-        })
+    async fn get_project_context(&self, project_path: &str) -> CoreResult<ProjectContext> {
+        let projects = self.projects.clone();
+        let root = PathBuf::from(project_path);
+        blocking(move || projects.get(&root)).await
     }
 
-    async fn get_symbol_context(&self, _request: &ContextRequest) -> Result<SymbolContext> {
-        // In real implementation, would use language server
-        Ok(SymbolContext {
-            definitions: vec![],
-            references: vec![],
-            types: vec![],
-        })
+    /// Apply file watcher events to the symbol index of `root_path`.
+    /// Resolves to the number of files indexed, or 0 if it has no index yet.
+    #[napi]
+    pub async fn update_symbol_index(&self, root_path: String, changes: Vec<FileChangeEvent>, token: Option<CancelSignal>) -> JsResult<u32> {
+        let signal = token.unwrap_or_default();
+        let symbols = Arc::clone(&self.symbols);
+
+        blocking(move || symbols.update(&root_path, &changes, &signal)).await.into()
     }
-}
 
-struct ContextStore {
-    cache: HashMap<String, Context>,
-    max_size: usize,
-}
+    /// Drop the symbol index of `root_path`. Resolves to false if there was none.
+    #[napi]
+    pub async fn forget_symbol_index(&self, root_path: String) -> JsResult<bool> {
+        let symbols = Arc::clone(&self.symbols);
 
-impl ContextStore {
-    fn new() -> Self {
-        ContextStore {
-            cache: HashMap::new(),
-            max_size: 100,
-        }
+        blocking(move || symbols.forget(&root_path)).await.into()
     }
 
-    fn cache(&mut self, key: String, context: Context) {
-        if self.cache.len() >= self.max_size {
-            // Simple LRU: remove first (oldest) entry
-            if let Some(first_key) = self.cache.keys().next().cloned() {
-                self.cache.remove(&first_key);
-            }
+    /// Definitions and references of the symbol at the cursor, from a tags
+    /// index of the project built on first use. Without a project there is
+    /// no telling how far to index, so there are no symbols.
+    async fn get_symbol_context(&self, request: &ContextRequest, signal: &CancelSignal) -> CoreResult<SymbolContext> {
+        let (Some(file_path), Some(root)) = (request.file_path.clone(), request.project_path.clone()) else {
+            return Ok(SymbolContext::default());
+        };
+        if !request.include_symbols.unwrap_or(true) {
+            return Ok(SymbolContext::default());
         }
-        self.cache.insert(key, context);
-    }
+        let cursor = request.cursor_position.as_ref().map(|position| (position.line.max(0.0) as usize, position.column.max(0.0) as usize));
+        let symbols = Arc::clone(&self.symbols);
+        let signal = signal.clone();
 
-    fn get(&self, key: &str) -> Option<Context> {
-        self.cache.get(key).cloned()
+        blocking(move || symbols.context(&root, &file_path, cursor, &signal)).await
     }
 }
 
@@ -157,9 +238,14 @@ pub struct ContextRequest {
     pub project_path: Option<String>,
     pub cursor_position: Option<Position>,
     pub selected_text: Option<String>,
+    /// Look up the symbol at the cursor in an index of `project_path`
+    /// (default: true); there are no symbols without a `project_path`
     pub include_symbols: Option<bool>,
+    /// How many lines of the file to preview around the cursor (default 100)
+    pub preview_lines: Option<u32>,
 }
 
+/// A 0-based line and column, as in VS Code
 #[napi(object)]
 pub struct Position {
     pub line: f64,
@@ -180,8 +266,13 @@ pub struct Context {
 pub struct FileContext {
     pub path: String,
     pub content_preview: String,
+    /// The 0-based line of the file that `content_preview` starts at
+    pub preview_start_line: f64,
+    /// The VS Code language id, e.g. `typescriptreact`
     pub language: String,
+    /// The modules the file imports; empty for files over 1 MiB, which are not parsed
     pub imports: Vec<String>,
+    /// The names the file exports; empty for files over 1 MiB
     pub exports: Vec<String>,
 }
 
@@ -189,24 +280,48 @@ pub struct FileContext {
 #[derive(Clone, Default)]
 pub struct ProjectContext {
     pub root_path: String,
+    /// The most specific framework detected, or empty when there is none
     pub framework: String,
+    /// Every framework detected from dependencies and marker files, then
+    /// `VS Code extension` for extensions
+    pub frameworks: Vec<String>,
+    /// The manifests found at the root, e.g. `package.json`
+    pub manifests: Vec<String>,
+    /// `name@version`, or just `name` when the manifest pins no version
     pub dependencies: Vec<String>,
+    /// File counts by language and top-level folder
     pub structure_summary: String,
 }
 
 #[napi(object)]
 #[derive(Clone, Default)]
 pub struct SymbolContext {
+    /// `path:line:column kind name: source line` for each definition of the
+    /// symbol at the cursor, or of the file's own symbols without one
     pub definitions: Vec<String>,
+    /// References to the symbol at the cursor, in the same form
     pub references: Vec<String>,
+    /// Definitions of the classes, interfaces and types the file refers to
     pub types: Vec<String>,
 }
 
 #[napi(object)]
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct ContextMetadata {
     pub preparation_time_ms: f64,
     pub total_tokens: f64,
+    pub file_tokens: f64,
+    pub project_tokens: f64,
+    pub symbol_tokens: f64,
+}
+
+/// The parts of a `Context` that `fitToBudget` can trim
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ContextSection {
+    File,
+    Symbols,
+    Project,
 }
 
 #[napi(object)]
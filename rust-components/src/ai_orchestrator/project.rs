@@ -0,0 +1,504 @@
+use super::file_context::detect_language;
+use super::ProjectContext;
+use crate::error::{CoreError, CoreResult};
+use ignore::WalkBuilder;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Manifests read at the project root; a change to any of them invalidates
+/// the cached analysis
+const MANIFESTS: [&str; 6] = ["package.json", "Cargo.toml", "pyproject.toml", "requirements.txt", "go.mod", "pom.xml"];
+/// Files whose presence alone identifies a framework
+const MARKER_FILES: [(&str, &str); 10] = [
+    ("next.config.js", "Next.js"),
+    ("next.config.mjs", "Next.js"),
+    ("next.config.ts", "Next.js"),
+    ("nuxt.config.ts", "Nuxt"),
+    ("angular.json", "Angular"),
+    ("svelte.config.js", "Svelte"),
+    ("manage.py", "Django"),
+    ("Tauri.toml", "Tauri"),
+    ("pubspec.yaml", "Flutter"),
+    ("build.gradle", "Gradle"),
+];
+/// Frameworks and the dependencies that give them away, most specific first
+/// so that e.g. a Next.js app is not reported as plain React
+const FRAMEWORKS: [(&str, &[&str]); 26] = [
+    ("Next.js", &["next"]),
+    ("Nuxt", &["nuxt"]),
+    ("Remix", &["@remix-run/react"]),
+    ("SvelteKit", &["@sveltejs/kit"]),
+    ("Angular", &["@angular/core"]),
+    ("NestJS", &["@nestjs/core"]),
+    ("Electron", &["electron"]),
+    ("React", &["react"]),
+    ("Vue", &["vue"]),
+    ("Svelte", &["svelte"]),
+    ("Express", &["express"]),
+    ("Tauri", &["tauri"]),
+    ("Leptos", &["leptos"]),
+    ("Bevy", &["bevy"]),
+    ("Axum", &["axum"]),
+    ("Actix Web", &["actix-web"]),
+    ("Rocket", &["rocket"]),
+    ("N-API", &["napi"]),
+    ("Django", &["django"]),
+    ("FastAPI", &["fastapi"]),
+    ("Flask", &["flask"]),
+    ("Gin", &["github.com/gin-gonic/gin"]),
+    ("Echo", &["github.com/labstack/echo"]),
+    ("Fiber", &["github.com/gofiber/fiber"]),
+    ("Spring Boot", &["org.springframework.boot"]),
+    ("Quarkus", &["io.quarkus"]),
+];
+/// The structure summary stops counting after this many files
+const MAX_SUMMARY_FILES: usize = 20_000;
+/// Top-level entries and languages listed in the structure summary
+const SUMMARY_ENTRIES: usize = 8;
+/// Projects analysed before the least recently used one is dropped
+const MAX_CACHED_PROJECTS: usize = 32;
+
+/// Project analyses by root, each kept until one of its manifests changes
+/// or `MAX_CACHED_PROJECTS` more recently used ones push it out
+#[derive(Default)]
+pub struct ProjectCache {
+    projects: Mutex<HashMap<PathBuf, CachedProject>>,
+    tick: AtomicU64,
+}
+
+struct CachedProject {
+    fingerprints: Vec<Fingerprint>,
+    context: ProjectContext,
+    last_used: u64,
+}
+
+/// The size and modification time of a manifest, `None` when it is absent
+type Fingerprint = Option<(u64, Option<SystemTime>)>;
+
+impl ProjectCache {
+    /// The analysis of the project at `root`, reusing the cached one while
+    /// its manifests are unchanged
+    pub fn get(&self, root: &Path) -> CoreResult<ProjectContext> {
+        let meta = std::fs::metadata(root).map_err(|e| CoreError::io("Failed to read project", root, e))?;
+        if !meta.is_dir() {
+            return Err(CoreError::InvalidArgument(format!("Not a folder: {}", root.display())));
+        }
+
+        let fingerprints = fingerprints(root);
+        let last_used = self.tick.fetch_add(1, Ordering::Relaxed);
+        if let Some(cached) = self.projects.lock().get_mut(root) {
+            if cached.fingerprints == fingerprints {
+                cached.last_used = last_used;
+                return Ok(cached.context.clone());
+            }
+        }
+        let context = analyze(root);
+        let mut projects = self.projects.lock();
+        if projects.len() >= MAX_CACHED_PROJECTS && !projects.contains_key(root) {
+            if let Some(oldest) = projects.iter().min_by_key(|(_, cached)| cached.last_used).map(|(root, _)| root.clone()) {
+                projects.remove(&oldest);
+            }
+        }
+        projects.insert(root.to_path_buf(), CachedProject { fingerprints, context: context.clone(), last_used });
+        Ok(context)
+    }
+}
+
+fn fingerprints(root: &Path) -> Vec<Fingerprint> {
+    MANIFESTS
+        .iter()
+        .map(|name| std::fs::metadata(root.join(name)).ok().map(|meta| (meta.len(), meta.modified().ok())))
+        .collect()
+}
+
+struct Dependency {
+    name: String,
+    version: Option<String>,
+}
+
+impl Dependency {
+    fn new(name: impl Into<String>, version: Option<&str>) -> Self {
+        Dependency { name: name.into(), version: version.map(str::to_string).filter(|v| !v.is_empty()) }
+    }
+}
+
+/// Read the manifests at `root`, detect its frameworks and summarise its layout
+pub fn analyze(root: &Path) -> ProjectContext {
+    let mut manifests = Vec::new();
+    let mut dependencies = Vec::new();
+    let mut is_vscode_extension = false;
+    for name in MANIFESTS {
+        let Ok(text) = std::fs::read_to_string(root.join(name)) else { continue };
+        manifests.push(name.to_string());
+        let found = match name {
+            "package.json" => package_json(&text).map(|(found, vscode)| {
+                is_vscode_extension = vscode;
+                found
+            }),
+            "Cargo.toml" => cargo_toml(&text),
+            "pyproject.toml" => pyproject_toml(&text),
+            "requirements.txt" => requirements_txt(&text),
+            "go.mod" => go_mod(&text),
+            _ => pom_xml(&text),
+        };
+        if found.is_none() {
+            tracing::debug!("Could not parse {}", root.join(name).display());
+        }
+        dependencies.extend(found.unwrap_or_default());
+    }
+
+    let mut frameworks = Vec::new();
+    for (framework, packages) in FRAMEWORKS {
+        if dependencies.iter().any(|dep| packages.iter().any(|package| provides(&dep.name, package))) {
+            frameworks.push(framework.to_string());
+        }
+    }
+    for (file, framework) in MARKER_FILES {
+        if root.join(file).is_file() && !frameworks.iter().any(|f| f == framework) {
+            frameworks.push(framework.to_string());
+        }
+    }
+    // An extension is built with the frameworks above rather than being one,
+    // so it is only listed
+    let framework = frameworks.first().cloned().unwrap_or_default();
+    if is_vscode_extension {
+        frameworks.push("VS Code extension".to_string());
+    }
+
+    let mut seen = Vec::new();
+    let dependencies = dependencies
+        .into_iter()
+        .filter(|dep| {
+            let new = !seen.contains(&dep.name);
+            seen.push(dep.name.clone());
+            new
+        })
+        .map(|dep| match dep.version {
+            Some(version) => format!("{}@{}", dep.name, version),
+            None => dep.name,
+        })
+        .collect();
+
+    ProjectContext {
+        root_path: root.to_string_lossy().to_string(),
+        framework,
+        frameworks,
+        manifests,
+        dependencies,
+        structure_summary: summarize(root),
+    }
+}
+
+/// Whether the dependency `name` is `package` or part of it: Go module paths
+/// carry a major version suffix and Maven coordinates an artifact id
+fn provides(name: &str, package: &str) -> bool {
+    name.strip_prefix(package).is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || rest.starts_with(':'))
+}
+
+/// The dependencies of a `package.json`, and whether it describes a VS Code extension
+fn package_json(text: &str) -> Option<(Vec<Dependency>, bool)> {
+    let manifest: serde_json::Value = serde_json::from_str(text).ok()?;
+    let mut found = Vec::new();
+    for section in ["dependencies", "devDependencies", "peerDependencies", "optionalDependencies"] {
+        let Some(table) = manifest.get(section).and_then(|s| s.as_object()) else { continue };
+        found.extend(table.iter().map(|(name, version)| Dependency::new(name, version.as_str())));
+    }
+    let vscode = manifest.pointer("/engines/vscode").is_some();
+    Some((found, vscode))
+}
+
+fn cargo_toml(text: &str) -> Option<Vec<Dependency>> {
+    let manifest: toml::Table = text.parse().ok()?;
+    let mut tables = Vec::new();
+    for section in ["dependencies", "dev-dependencies", "build-dependencies"] {
+        tables.extend(manifest.get(section));
+        // Platform-specific dependencies live under `[target.'cfg(..)'.dependencies]`
+        let targets = manifest.get("target").and_then(|t| t.as_table());
+        tables.extend(targets.into_iter().flat_map(|targets| targets.values()).filter_map(|target| target.get(section)));
+    }
+    tables.extend(manifest.get("workspace").and_then(|w| w.get("dependencies")));
+
+    let mut found = Vec::new();
+    for table in tables.iter().filter_map(|t| t.as_table()) {
+        for (name, spec) in table {
+            // `foo = "1"` or `foo = { version = "1", .. }`; path and git dependencies have no version
+            let version = spec.as_str().or_else(|| spec.get("version").and_then(|v| v.as_str()));
+            let name = spec.get("package").and_then(|p| p.as_str()).unwrap_or(name);
+            found.push(Dependency::new(name, version));
+        }
+    }
+    Some(found)
+}
+
+fn pyproject_toml(text: &str) -> Option<Vec<Dependency>> {
+    let manifest: toml::Table = text.parse().ok()?;
+    let mut found = Vec::new();
+
+    // PEP 621 lists requirement strings
+    let project = manifest.get("project");
+    let optional = project.and_then(|p| p.get("optional-dependencies")).and_then(|o| o.as_table());
+    let lists = project.and_then(|p| p.get("dependencies")).into_iter().chain(optional.into_iter().flat_map(|o| o.values()));
+    for list in lists.filter_map(|l| l.as_array()) {
+        found.extend(list.iter().filter_map(|r| r.as_str()).filter_map(requirement));
+    }
+
+    // Poetry keeps a table per group, with the interpreter itself among them
+    let poetry = manifest.get("tool").and_then(|t| t.get("poetry"));
+    let groups = poetry.and_then(|p| p.get("group")).and_then(|g| g.as_table());
+    let tables = poetry
+        .and_then(|p| p.get("dependencies"))
+        .into_iter()
+        .chain(poetry.and_then(|p| p.get("dev-dependencies")))
+        .chain(groups.into_iter().flat_map(|g| g.values()).filter_map(|g| g.get("dependencies")));
+    for table in tables.filter_map(|t| t.as_table()) {
+        for (name, spec) in table.iter().filter(|(name, _)| name.as_str() != "python") {
+            let version = spec.as_str().or_else(|| spec.get("version").and_then(|v| v.as_str()));
+            found.push(Dependency::new(name.to_lowercase(), version));
+        }
+    }
+    Some(found)
+}
+
+fn requirements_txt(text: &str) -> Option<Vec<Dependency>> {
+    Some(
+        text.lines()
+            .map(|line| line.split(" #").next().unwrap_or_default().trim())
+            // Options such as `-r other.txt` and `-e .` name no package
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('-'))
+            .filter_map(requirement)
+            .collect(),
+    )
+}
+
+/// The package in a PEP 508 requirement such as `requests[socks]>=2.31; python_version > "3.8"`
+fn requirement(spec: &str) -> Option<Dependency> {
+    let spec = spec.split(';').next()?.trim();
+    let end = spec.find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))).unwrap_or(spec.len());
+    let (name, rest) = spec.split_at(end);
+    if name.is_empty() {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let rest = match rest.strip_prefix('[') {
+        Some(extras) => extras.split_once(']').map_or("", |(_, rest)| rest).trim(),
+        None => rest,
+    };
+    let version = rest.strip_prefix("==").unwrap_or(rest).trim();
+    Some(Dependency::new(name.to_lowercase(), Some(version)))
+}
+
+fn go_mod(text: &str) -> Option<Vec<Dependency>> {
+    let mut found = Vec::new();
+    let mut in_block = false;
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        let spec = if in_block {
+            if line == ")" {
+                in_block = false;
+                continue;
+            }
+            line
+        } else if let Some(rest) = line.strip_prefix("require") {
+            let rest = rest.trim();
+            if rest == "(" {
+                in_block = true;
+                continue;
+            }
+            rest
+        } else {
+            continue;
+        };
+        let mut words = spec.split_whitespace();
+        if let Some(module) = words.next() {
+            found.push(Dependency::new(module, words.next()));
+        }
+    }
+    Some(found)
+}
+
+fn pom_xml(text: &str) -> Option<Vec<Dependency>> {
+    fn child<'a, 'i>(node: roxmltree::Node<'a, 'i>, name: &str) -> Option<roxmltree::Node<'a, 'i>> {
+        node.children().find(|c| c.tag_name().name() == name)
+    }
+    fn text_of<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+        child(node, name).and_then(|c| c.text()).map(str::trim)
+    }
+
+    let document = roxmltree::Document::parse(text).ok()?;
+
+    let mut found = Vec::new();
+    let project = document.root_element();
+    // The parent POM often names the framework, e.g. `spring-boot-starter-parent`
+    let parent = child(project, "parent").into_iter();
+    let dependencies = child(project, "dependencies").into_iter().flat_map(|d| d.children()).filter(|d| d.tag_name().name() == "dependency");
+    for dependency in parent.chain(dependencies) {
+        let (Some(group), Some(artifact)) = (text_of(dependency, "groupId"), text_of(dependency, "artifactId")) else { continue };
+        found.push(Dependency::new(format!("{}:{}", group, artifact), text_of(dependency, "version")));
+    }
+    Some(found)
+}
+
+/// A few lines on how much code the project holds, in which languages and
+/// where, counting only the files the search walk would visit
+fn summarize(root: &Path) -> String {
+    let mut files = 0;
+    let mut folders = 0;
+    let mut languages: HashMap<&'static str, usize> = HashMap::new();
+    // Files below each top-level folder; top-level files count as `None`
+    let mut top_level: BTreeMap<String, Option<usize>> = BTreeMap::new();
+
+    let walk = WalkBuilder::new(root).hidden(true).ignore(true).git_ignore(true).add_custom_ignore_filename(".rgignore").build();
+    for entry in walk.flatten().filter(|entry| entry.depth() > 0) {
+        if files >= MAX_SUMMARY_FILES {
+            break;
+        }
+        let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let Some(first) = rel.components().next() else { continue };
+        let first = first.as_os_str().to_string_lossy().to_string();
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            folders += 1;
+            top_level.entry(first).or_insert(Some(0));
+            continue;
+        }
+
+        files += 1;
+        if entry.depth() > 1 {
+            *top_level.entry(first).or_insert(Some(0)).get_or_insert(0) += 1;
+        } else {
+            top_level.insert(first, None);
+        }
+        let language = detect_language(entry.path(), "");
+        if language != "plaintext" {
+            *languages.entry(language).or_default() += 1;
+        }
+    }
+
+    let total = if files >= MAX_SUMMARY_FILES { format!("More than {} files", MAX_SUMMARY_FILES) } else { format!("{} files", files) };
+    let mut summary = format!("{} in {} folders", total, folders);
+
+    let mut languages: Vec<_> = languages.into_iter().collect();
+    languages.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    if !languages.is_empty() {
+        let listed: Vec<String> = languages.iter().take(SUMMARY_ENTRIES).map(|(language, count)| format!("{} ({})", language, count)).collect();
+        summary.push_str(&format!("\nLanguages: {}", listed.join(", ")));
+    }
+
+    // Folders by size, then top-level files by name
+    let mut entries: Vec<_> = top_level.into_iter().collect();
+    entries.sort_by_key(|(_, count)| std::cmp::Reverse(count.map(|c| c + 1).unwrap_or(0)));
+    if !entries.is_empty() {
+        let mut listed: Vec<String> = entries
+            .iter()
+            .take(SUMMARY_ENTRIES)
+            .map(|(name, count)| match count {
+                Some(count) => format!("{}/ ({} files)", name, count),
+                None => name.clone(),
+            })
+            .collect();
+        if entries.len() > SUMMARY_ENTRIES {
+            listed.push(format!("{} more", entries.len() - SUMMARY_ENTRIES));
+        }
+        summary.push_str(&format!("\nTop level: {}", listed.join(", ")));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn names(found: Option<Vec<Dependency>>) -> Vec<String> {
+        found
+            .unwrap()
+            .into_iter()
+            .map(|dep| match dep.version {
+                Some(version) => format!("{}@{}", dep.name, version),
+                None => dep.name,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_manifests() {
+        let cargo = "[package]\nname = \"x\"\n[dependencies]\naxum = \"0.7\"\nserde = { version = \"1\", features = [\"derive\"] }\n\
+                     local = { path = \"../local\" }\n[target.'cfg(unix)'.dependencies]\nlibc = \"0.2\"\n";
+        assert_eq!(names(cargo_toml(cargo)), ["axum@0.7", "local", "serde@1", "libc@0.2"]);
+
+        let pyproject = "[project]\ndependencies = [\"Django>=5.0\", \"requests[socks]==2.31; python_version > '3.8'\"]\n\
+                         [tool.poetry.dependencies]\npython = \"^3.11\"\nfastapi = \"^0.110\"\n";
+        assert_eq!(names(pyproject_toml(pyproject)), ["django@>=5.0", "requests@2.31", "fastapi@^0.110"]);
+        assert_eq!(names(requirements_txt("# web\nflask==3.0  # pinned\n-r base.txt\nnumpy\n")), ["flask@3.0", "numpy"]);
+
+        let go = "module example.com/app\n\ngo 1.22\n\nrequire github.com/gin-gonic/gin v1.9.1\nrequire (\n\
+                  \tgithub.com/labstack/echo/v4 v4.11.0\n\tgolang.org/x/text v0.14.0 // indirect\n)\n";
+        assert_eq!(names(go_mod(go)), ["github.com/gin-gonic/gin@v1.9.1", "github.com/labstack/echo/v4@v4.11.0", "golang.org/x/text@v0.14.0"]);
+
+        let pom = "<project xmlns=\"http://maven.apache.org/POM/4.0.0\"><parent><groupId>org.springframework.boot</groupId>\
+                   <artifactId>spring-boot-starter-parent</artifactId><version>3.2.0</version></parent><dependencies><dependency>\
+                   <groupId>junit</groupId><artifactId>junit</artifactId></dependency></dependencies></project>";
+        assert_eq!(names(pom_xml(pom)), ["org.springframework.boot:spring-boot-starter-parent@3.2.0", "junit:junit"]);
+        assert!(cargo_toml("not = [toml").is_none());
+    }
+
+    #[test]
+    fn test_analyze_project() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::write(
+            root.join("package.json"),
+            r#"{"engines": {"vscode": "^1.80.0"}, "dependencies": {"react": "^18.2.0", "next": "14.0.0"}, "devDependencies": {"react": "^18.2.0"}}"#,
+        )
+        .unwrap();
+        std::fs::create_dir_all(root.join("src/components")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules/react")).unwrap();
+        std::fs::write(root.join(".ignore"), "node_modules/\n").unwrap();
+        std::fs::write(root.join("src/index.tsx"), "").unwrap();
+        std::fs::write(root.join("src/components/App.tsx"), "").unwrap();
+        std::fs::write(root.join("node_modules/react/index.js"), "").unwrap();
+
+        let context = analyze(root);
+        assert_eq!(context.framework, "Next.js");
+        assert_eq!(context.frameworks, ["Next.js", "React", "VS Code extension"]);
+        assert_eq!(context.manifests, ["package.json"]);
+        assert_eq!(context.dependencies, ["next@14.0.0", "react@^18.2.0"]);
+        assert_eq!(context.structure_summary, "3 files in 2 folders\nLanguages: typescriptreact (2), json (1)\nTop level: src/ (2 files), package.json");
+    }
+
+    #[test]
+    fn test_project_cache_invalidation() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let cache = ProjectCache::default();
+        assert_eq!(cache.get(root).unwrap().framework, "");
+
+        // Only manifest changes invalidate the cached analysis
+        std::fs::write(root.join("main.go"), "package main\n").unwrap();
+        assert_eq!(cache.get(root).unwrap().structure_summary, "0 files in 0 folders");
+        std::fs::write(root.join("go.mod"), "module app\n\nrequire github.com/gofiber/fiber/v2 v2.52.0\n").unwrap();
+        let context = cache.get(root).unwrap();
+        assert_eq!(context.framework, "Fiber");
+        assert!(context.structure_summary.starts_with("2 files in 0 folders"));
+
+        let missing = cache.get(&root.join("missing"));
+        assert!(matches!(missing, Err(e) if e.code() == crate::error::ErrorCode::FileNotFound));
+    }
+
+    #[test]
+    fn test_project_cache_drops_least_recently_used() {
+        let temp_dirs: Vec<TempDir> = (0..=MAX_CACHED_PROJECTS).map(|_| TempDir::new().unwrap()).collect();
+        let cache = ProjectCache::default();
+        for temp_dir in &temp_dirs {
+            cache.get(temp_dir.path()).unwrap();
+            // Keep the first project in use
+            cache.get(temp_dirs[0].path()).unwrap();
+        }
+        let projects = cache.projects.lock();
+        assert_eq!(projects.len(), MAX_CACHED_PROJECTS);
+        assert!(projects.contains_key(temp_dirs[0].path()));
+        assert!(!projects.contains_key(temp_dirs[1].path()));
+    }
+}
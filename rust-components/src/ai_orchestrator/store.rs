@@ -0,0 +1,261 @@
+use super::{Context, FileContext, ProjectContext, SymbolContext};
+use crate::performance_monitor::PERF_MONITOR;
+use napi_derive::napi;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_CONTEXT_TTL_MS: u32 = 5 * 60_000;
+pub const DEFAULT_CONTEXT_MAX_BYTES: f64 = 32.0 * 1024.0 * 1024.0;
+
+/// Bookkeeping cost charged per entry on top of its strings
+const ENTRY_OVERHEAD_BYTES: usize = 256;
+
+#[napi(object)]
+#[derive(Default)]
+pub struct ContextCacheOptions {
+    /// Entries older than this are dropped unless cached with their own TTL (default: 5 min)
+    pub ttl_ms: Option<u32>,
+    /// Memory budget for all entries; least recently used entries are evicted first (default: 32 MiB)
+    pub max_bytes: Option<f64>,
+}
+
+struct Entry {
+    context: Context,
+    /// The files the context was built from, so editing one drops it
+    files: Vec<PathBuf>,
+    expires: Instant,
+    last_used: u64,
+    cost: usize,
+}
+
+/// Bounded LRU cache of prepared contexts keyed by caller-chosen strings
+pub struct ContextStore {
+    entries: HashMap<String, Entry>,
+    /// Last-use tick to key, oldest first
+    lru: BTreeMap<u64, String>,
+    tick: u64,
+    bytes: usize,
+    ttl: Duration,
+    max_bytes: usize,
+}
+
+impl ContextStore {
+    pub fn new() -> Self {
+        ContextStore {
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            ttl: Duration::from_millis(DEFAULT_CONTEXT_TTL_MS as u64),
+            max_bytes: DEFAULT_CONTEXT_MAX_BYTES as usize,
+        }
+    }
+
+    pub fn configure(&mut self, options: &ContextCacheOptions) {
+        self.ttl = Duration::from_millis(options.ttl_ms.unwrap_or(DEFAULT_CONTEXT_TTL_MS) as u64);
+        self.max_bytes = options.max_bytes.unwrap_or(DEFAULT_CONTEXT_MAX_BYTES).max(0.0) as usize;
+        self.evict_to(self.max_bytes);
+    }
+
+    /// Remember `context` under `key` for `ttl`, or the configured TTL
+    pub fn cache(&mut self, key: String, context: Context, ttl: Option<Duration>) {
+        self.remove(&key);
+        let cost = key.len() + context_bytes(&context) + ENTRY_OVERHEAD_BYTES;
+        if cost > self.max_bytes {
+            return;
+        }
+        self.tick += 1;
+        self.bytes += cost;
+        self.lru.insert(self.tick, key.clone());
+        self.entries.insert(key, Entry {
+            files: included_files(&context),
+            context,
+            expires: Instant::now() + ttl.unwrap_or(self.ttl),
+            last_used: self.tick,
+            cost,
+        });
+        self.evict_to(self.max_bytes);
+    }
+
+    /// The context cached under `key` unless it has expired, which makes it
+    /// the most recently used
+    pub fn get(&mut self, key: &str) -> Option<Context> {
+        if self.entries.get(key).is_some_and(|entry| entry.expires <= Instant::now()) {
+            self.remove(key);
+        }
+
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                self.lru.remove(&entry.last_used);
+                entry.last_used = tick;
+                self.lru.insert(tick, key.to_string());
+                PERF_MONITOR.record_context_cache_hit();
+                Some(entry.context.clone())
+            }
+            None => {
+                PERF_MONITOR.record_context_cache_miss();
+                None
+            }
+        }
+    }
+
+    /// Drop every context built from `path`, or from a file below it.
+    /// Returns how many were dropped.
+    pub fn invalidate(&mut self, path: &Path) -> usize {
+        let stale: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.files.iter().any(|file| file.starts_with(path)))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &stale {
+            self.remove(key);
+        }
+        stale.len()
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.last_used);
+            self.bytes -= entry.cost;
+        }
+    }
+
+    fn evict_to(&mut self, max_bytes: usize) {
+        // Expired entries go before any live one, however recently used
+        if self.bytes > max_bytes {
+            let now = Instant::now();
+            let expired: Vec<String> = self.entries.iter().filter(|(_, entry)| entry.expires <= now).map(|(key, _)| key.clone()).collect();
+            for key in &expired {
+                self.remove(key);
+            }
+        }
+        while self.bytes > max_bytes {
+            let Some((_, oldest)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.bytes -= entry.cost;
+            }
+        }
+    }
+}
+
+impl Default for ContextStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The file itself, the project manifests and the files quoted in its symbols
+fn included_files(context: &Context) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    if !context.file.path.is_empty() {
+        files.push(PathBuf::from(&context.file.path));
+    }
+    let project_root = Path::new(&context.project.root_path);
+    files.extend(context.project.manifests.iter().map(|manifest| project_root.join(manifest)));
+
+    // Symbol locations are relative to the project, and there are none without one
+    let symbols = &context.symbols;
+    for location in symbols.definitions.iter().chain(&symbols.references).chain(&symbols.types) {
+        // `path:line:column kind name: source line`
+        let Some((position, _)) = location.split_once(' ') else { continue };
+        if let Some(path) = position.rsplitn(3, ':').nth(2) {
+            files.push(project_root.join(path));
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Roughly the heap memory held by the strings of a context
+fn context_bytes(context: &Context) -> usize {
+    let strings = |list: &[String]| list.iter().map(String::len).sum::<usize>();
+    let FileContext { path, content_preview, language, imports, exports, .. } = &context.file;
+    let ProjectContext { root_path, framework, frameworks, manifests, dependencies, structure_summary } = &context.project;
+    let SymbolContext { definitions, references, types } = &context.symbols;
+    path.len()
+        + content_preview.len()
+        + language.len()
+        + strings(imports)
+        + strings(exports)
+        + root_path.len()
+        + framework.len()
+        + strings(frameworks)
+        + strings(manifests)
+        + strings(dependencies)
+        + structure_summary.len()
+        + strings(definitions)
+        + strings(references)
+        + strings(types)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ContextMetadata;
+    use super::*;
+
+    fn context(path: &str, preview_len: usize) -> Context {
+        Context {
+            file: FileContext { path: path.to_string(), content_preview: "x".repeat(preview_len), ..Default::default() },
+            project: ProjectContext { root_path: "/p".to_string(), manifests: vec!["package.json".to_string()], ..Default::default() },
+            symbols: SymbolContext { references: vec!["src/b.ts:3:1 call load: load();".to_string()], ..Default::default() },
+            metadata: ContextMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn test_context_store_evicts_least_recently_used() {
+        let mut store = ContextStore::new();
+        store.configure(&ContextCacheOptions { ttl_ms: None, max_bytes: Some(3000.0) });
+        store.cache("a".to_string(), context("/p/a.ts", 800), None);
+        store.cache("b".to_string(), context("/p/b.ts", 800), None);
+        // Reading `a` makes `b` the least recently used
+        assert!(store.get("a").is_some());
+        store.cache("c".to_string(), context("/p/c.ts", 800), None);
+        assert!(store.get("b").is_none());
+        assert!(store.get("a").is_some() && store.get("c").is_some());
+
+        // Contexts bigger than the whole budget are not cached at all
+        store.cache("huge".to_string(), context("/p/huge.ts", 10_000), None);
+        assert!(store.get("huge").is_none());
+        assert!(store.get("a").is_some());
+
+        // Expired contexts are evicted before live ones, even more recently used ones
+        let mut store = ContextStore::new();
+        store.configure(&ContextCacheOptions { ttl_ms: None, max_bytes: Some(3000.0) });
+        store.cache("a".to_string(), context("/p/a.ts", 800), None);
+        store.cache("stale".to_string(), context("/p/stale.ts", 800), Some(Duration::from_millis(1)));
+        std::thread::sleep(Duration::from_millis(5));
+        store.cache("c".to_string(), context("/p/c.ts", 800), None);
+        assert!(!store.entries.contains_key("stale"));
+        assert!(store.get("a").is_some() && store.get("c").is_some());
+    }
+
+    #[test]
+    fn test_context_store_ttl_and_invalidation() {
+        let mut store = ContextStore::new();
+        store.cache("expired".to_string(), context("/p/a.ts", 10), Some(Duration::ZERO));
+        assert!(store.get("expired").is_none());
+        assert_eq!(store.bytes, 0);
+
+        store.cache("a".to_string(), context("/p/a.ts", 10), None);
+        store.cache("c".to_string(), context("/p/c.ts", 10), None);
+        let mut other = context("/q/other.ts", 10);
+        other.project.root_path = "/q".to_string();
+        store.cache("other".to_string(), other, Some(Duration::from_secs(60)));
+        // Both quote `/p/src/b.ts` in their symbols
+        assert_eq!(store.invalidate(Path::new("/p/src/b.ts")), 2);
+        assert!(store.get("a").is_none() && store.get("c").is_none());
+        assert_eq!(store.invalidate(Path::new("/p/package.json")), 0);
+        // Invalidating a folder drops the contexts of every file below it
+        assert_eq!(store.invalidate(Path::new("/q")), 1);
+        assert!(store.entries.is_empty() && store.lru.is_empty());
+        assert_eq!(store.bytes, 0);
+    }
+}
@@ -0,0 +1,428 @@
+use super::SymbolContext;
+use crate::cancellation::CancelSignal;
+use crate::error::{CoreError, CoreResult};
+use crate::file_watcher::{FileChangeEvent, FileChangeType};
+use crate::search_engine::grammar::Grammar;
+use crate::search_engine::index::{canonical_root, relative, walk, IGNORE_FILES};
+use dashmap::DashMap;
+use parking_lot::RwLock;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tree_sitter::{Point, Query, QueryCursor, StreamingIterator};
+
+/// Larger files are not tagged; they are mostly generated or minified
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// Definitions and references listed for the symbol at the cursor
+const MAX_LOCATIONS: usize = 50;
+/// Type definitions listed for the names a file uses
+const MAX_TYPES: usize = 20;
+/// Definition kinds from the tags queries that describe types
+const TYPE_KINDS: [&str; 3] = ["class", "interface", "type"];
+
+/// A definition or reference found by a grammar's tags query
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Tag {
+    name: String,
+    /// The tags query's kind, e.g. `function` or `call`
+    kind: String,
+    is_definition: bool,
+    /// 0-based line and byte column of the name
+    line: usize,
+    column: usize,
+}
+
+/// The tags of every file below a root that the search walk would visit,
+/// with the files each name is defined and referenced in
+pub struct SymbolIndex {
+    root: PathBuf,
+    /// By path relative to the root, with `/` separators, sorted so the
+    /// files below a folder are next to each other
+    files: BTreeMap<String, Vec<Tag>>,
+    definitions: HashMap<String, BTreeSet<String>>,
+    references: HashMap<String, BTreeSet<String>>,
+    /// Folders the walk descended into, so new files can be checked against them
+    dirs: HashSet<String>,
+}
+
+impl SymbolIndex {
+    fn build(root: PathBuf, signal: &CancelSignal) -> CoreResult<Self> {
+        let mut index = SymbolIndex { root, files: BTreeMap::new(), definitions: HashMap::new(), references: HashMap::new(), dirs: HashSet::new() };
+        let mut paths = Vec::new();
+        for entry in walk(&index.root, &index.root, None, signal)? {
+            if entry.is_dir {
+                index.dirs.insert(entry.path);
+            } else {
+                paths.push(entry.path);
+            }
+        }
+        let tagged: Vec<(String, Vec<Tag>)> = paths
+            .into_par_iter()
+            .filter_map(|path| {
+                if signal.is_cancelled() {
+                    return None;
+                }
+                let tags = tag_file(&index.root.join(&path))?;
+                Some((path, tags))
+            })
+            .collect();
+        signal.check()?;
+        for (path, tags) in tagged {
+            index.insert(path, tags);
+        }
+        Ok(index)
+    }
+
+    /// Apply file change events, re-tagging only the files they touch
+    fn apply_changes(&mut self, changes: &[FileChangeEvent], signal: &CancelSignal) -> CoreResult<()> {
+        for change in changes {
+            let path = Path::new(&change.path);
+            let Some(rel) = relative(&self.root, path) else { continue };
+            if path.file_name().is_some_and(|name| IGNORE_FILES.iter().any(|f| name == *f)) {
+                *self = Self::build(self.root.clone(), signal)?;
+                return Ok(());
+            }
+            let parent = rel.rsplit_once('/').map_or("", |(parent, _)| parent);
+
+            self.remove(&rel);
+            let Ok(meta) = std::fs::metadata(path) else { continue };
+            if change.change_type == FileChangeType::Deleted || !self.dirs.contains(parent) {
+                continue;
+            }
+            // Walking from the parent applies the ignore rules of every folder above
+            let (from, max_depth) = if meta.is_dir() { (rel.as_str(), None) } else { (parent, Some(1)) };
+            for entry in walk(&self.root, &self.root.join(from), max_depth, signal)? {
+                if entry.is_dir {
+                    self.dirs.insert(entry.path);
+                } else if meta.is_dir() || entry.path == rel {
+                    if let Some(tags) = tag_file(&self.root.join(&entry.path)) {
+                        self.insert(entry.path, tags);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-tag an indexed file from disk, for the file being edited
+    fn refresh(&mut self, rel: &str) {
+        if self.files.contains_key(rel) {
+            self.remove(rel);
+            if let Some(tags) = tag_file(&self.root.join(rel)) {
+                self.insert(rel.to_string(), tags);
+            }
+        }
+    }
+
+    fn insert(&mut self, path: String, tags: Vec<Tag>) {
+        for tag in &tags {
+            let names = if tag.is_definition { &mut self.definitions } else { &mut self.references };
+            names.entry(tag.name.clone()).or_default().insert(path.clone());
+        }
+        self.files.insert(path, tags);
+    }
+
+    /// Remove a file, or a folder and everything below it
+    fn remove(&mut self, rel: &str) {
+        let prefix = format!("{}/", rel);
+        let below = self
+            .files
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .take_while(|(path, _)| path.starts_with(&prefix))
+            .map(|(path, _)| path.clone());
+        let removed: Vec<String> = self.files.contains_key(rel).then(|| rel.to_string()).into_iter().chain(below).collect();
+        for path in removed {
+            for tag in self.files.remove(&path).unwrap_or_default() {
+                let names = if tag.is_definition { &mut self.definitions } else { &mut self.references };
+                if let Some(paths) = names.get_mut(&tag.name) {
+                    paths.remove(&path);
+                    if paths.is_empty() {
+                        names.remove(&tag.name);
+                    }
+                }
+            }
+        }
+        if self.dirs.remove(rel) {
+            self.dirs.retain(|d| !d.starts_with(&prefix));
+        }
+    }
+
+    /// Where the symbol at the cursor in `rel` is defined and referenced, and
+    /// the types the file uses. Without a symbol at the cursor, the
+    /// definitions are those of the file itself.
+    fn context(&self, rel: &str, cursor: Option<(usize, usize)>) -> SymbolContext {
+        let symbol = cursor.and_then(|(line, column)| symbol_at(&self.root.join(rel), line, column));
+        let mut lines = SourceLines::default();
+        let mut located = |paths: Vec<(&String, &Tag)>| -> Vec<String> {
+            paths.into_iter().take(MAX_LOCATIONS).map(|(path, tag)| lines.describe(&self.root, path, tag)).collect()
+        };
+
+        let (definitions, references) = match &symbol {
+            Some(name) => (located(self.tags_of(name, true)), located(self.tags_of(name, false))),
+            None => match self.files.get_key_value(rel) {
+                Some((path, tags)) => (located(tags.iter().filter(|tag| tag.is_definition).map(|tag| (path, tag)).collect()), Vec::new()),
+                None => (Vec::new(), Vec::new()),
+            },
+        };
+
+        // Type definitions for the names the file refers to, once each
+        let mut used: Vec<&str> = self.files.get(rel).into_iter().flatten().filter(|tag| !tag.is_definition).map(|tag| tag.name.as_str()).collect();
+        used.sort_unstable();
+        used.dedup();
+        let types = used
+            .into_iter()
+            .filter(|name| Some(*name) != symbol.as_deref())
+            .flat_map(|name| self.tags_of(name, true))
+            .filter(|(_, tag)| TYPE_KINDS.contains(&tag.kind.as_str()))
+            .take(MAX_TYPES)
+            .map(|(path, tag)| lines.describe(&self.root, path, tag))
+            .collect();
+
+        SymbolContext { definitions, references, types }
+    }
+
+    /// Every definition or reference of `name`, by path and then position
+    fn tags_of(&self, name: &str, definitions: bool) -> Vec<(&String, &Tag)> {
+        let names = if definitions { &self.definitions } else { &self.references };
+        let paths = names.get(name).into_iter().flatten();
+        paths
+            .filter_map(|path| self.files.get_key_value(path))
+            .flat_map(|(path, tags)| tags.iter().filter(move |tag| tag.name == name && tag.is_definition == definitions).map(move |tag| (path, tag)))
+            .collect()
+    }
+}
+
+/// Lines of the files a context quotes, read once per file
+#[derive(Default)]
+struct SourceLines {
+    files: HashMap<String, Vec<String>>,
+}
+
+impl SourceLines {
+    /// `path:line:column kind name: source line`, with 1-based positions
+    fn describe(&mut self, root: &Path, path: &str, tag: &Tag) -> String {
+        let lines = self.files.entry(path.to_string()).or_insert_with(|| {
+            let text = std::fs::read(root.join(path)).map(|bytes| String::from_utf8_lossy(&bytes).into_owned()).unwrap_or_default();
+            text.lines().map(str::to_string).collect()
+        });
+        let source = lines.get(tag.line).map_or("", |line| line.trim());
+        format!("{}:{}:{} {} {}: {}", path, tag.line + 1, tag.column + 1, tag.kind, tag.name, source)
+    }
+}
+
+/// Tag a file whose language has a tags query
+fn tag_file(path: &Path) -> Option<Vec<Tag>> {
+    let grammar = Grammar::for_path(path)?;
+    if std::fs::metadata(path).ok()?.len() > MAX_FILE_SIZE {
+        return None;
+    }
+    let source = std::fs::read(path).ok()?;
+    Some(tag(grammar, &source))
+}
+
+/// The tags query of a grammar; TypeScript's extends JavaScript's
+fn tags_query(grammar: Grammar) -> Option<&'static Query> {
+    static QUERIES: OnceLock<HashMap<Grammar, Query>> = OnceLock::new();
+    let queries = QUERIES.get_or_init(|| {
+        let typescript = format!("{}\n{}", tree_sitter_javascript::TAGS_QUERY, tree_sitter_typescript::TAGS_QUERY);
+        let sources = [
+            (Grammar::TypeScript, typescript.as_str()),
+            (Grammar::Tsx, typescript.as_str()),
+            (Grammar::JavaScript, tree_sitter_javascript::TAGS_QUERY),
+            (Grammar::Rust, tree_sitter_rust::TAGS_QUERY),
+            (Grammar::Python, tree_sitter_python::TAGS_QUERY),
+            (Grammar::Go, tree_sitter_go::TAGS_QUERY),
+        ];
+        sources
+            .into_iter()
+            .filter_map(|(grammar, source)| match Query::new(&grammar.language(), source) {
+                Ok(query) => Some((grammar, query)),
+                Err(e) => {
+                    tracing::warn!("Invalid tags query for {:?}: {}", grammar, e);
+                    None
+                }
+            })
+            .collect()
+    });
+    queries.get(&grammar)
+}
+
+fn tag(grammar: Grammar, source: &[u8]) -> Vec<Tag> {
+    let (Some(query), Some(tree)) = (tags_query(grammar), grammar.parse(source)) else { return Vec::new() };
+    let mut tags: Vec<Tag> = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(query, tree.root_node(), source);
+    while let Some(found) = matches.next() {
+        let mut name = None;
+        let mut role = None;
+        for capture in found.captures {
+            match query.capture_names()[capture.index as usize] {
+                "name" => name = Some(capture.node),
+                capture_name => {
+                    if let Some(kind) = capture_name.strip_prefix("definition.") {
+                        role = Some((true, kind));
+                    } else if let Some(kind) = capture_name.strip_prefix("reference.") {
+                        role = Some((false, kind));
+                    }
+                }
+            }
+        }
+        // Some patterns only capture names, e.g. Go's package clause
+        let (Some(name), Some((is_definition, kind))) = (name, role) else { continue };
+        let Ok(text) = name.utf8_text(source) else { continue };
+        let position = name.start_position();
+        // Several patterns can match one name, e.g. a Rust method is also a function
+        if tags.iter().any(|t| (t.line, t.column) == (position.row, position.column) && (t.is_definition || !is_definition)) {
+            continue;
+        }
+        // A definition outranks a reference to the same name, e.g. Go's `type_identifier`
+        tags.retain(|t| !(is_definition && !t.is_definition && (t.line, t.column) == (position.row, position.column)));
+        tags.push(Tag { name: text.to_string(), kind: kind.to_string(), is_definition, line: position.row, column: position.column });
+    }
+    tags
+}
+
+/// The identifier at a 0-based line and UTF-16 column, or just before it
+/// when the cursor sits at the end of a name
+fn symbol_at(path: &Path, line: usize, column: usize) -> Option<String> {
+    let grammar = Grammar::for_path(path)?;
+    let source = std::fs::read(path).ok()?;
+    let tree = grammar.parse(&source)?;
+    let text = String::from_utf8_lossy(&source);
+    let line_text = text.lines().nth(line)?;
+    let mut units = 0;
+    let byte = line_text.char_indices().find(|(_, c)| {
+        units += c.len_utf16();
+        units > column
+    });
+    let byte = byte.map_or(line_text.len(), |(i, _)| i);
+
+    [byte, byte.saturating_sub(1)].into_iter().find_map(|column| {
+        let point = Point::new(line, column);
+        let node = tree.root_node().named_descendant_for_point_range(point, point)?;
+        if node.kind().ends_with("identifier") {
+            node.utf8_text(&source).ok().map(str::to_string)
+        } else {
+            None
+        }
+    })
+}
+
+/// Symbol indexes by canonical root, built on first use
+#[derive(Default)]
+pub struct SymbolIndexes {
+    indexes: DashMap<PathBuf, Arc<RwLock<SymbolIndex>>>,
+}
+
+impl SymbolIndexes {
+    /// The symbol context of `file` below `root`, indexing `root` on first use
+    pub fn context(&self, root: &str, file: &str, cursor: Option<(usize, usize)>, signal: &CancelSignal) -> CoreResult<SymbolContext> {
+        let root = canonical_root(root)?;
+        let file = Path::new(file).canonicalize().map_err(|e| CoreError::io("Failed to resolve file", file, e))?;
+        let Some(rel) = relative(&root, &file) else { return Ok(SymbolContext::default()) };
+
+        let index = match self.indexes.get(&root) {
+            Some(index) => Arc::clone(index.value()),
+            None => {
+                let index = Arc::new(RwLock::new(SymbolIndex::build(root.clone(), signal)?));
+                Arc::clone(self.indexes.entry(root).or_insert(index).value())
+            }
+        };
+        // The file at the cursor may have unsaved-then-saved edits no watcher event has reported yet
+        index.write().refresh(&rel);
+        let context = index.read().context(&rel, cursor);
+        Ok(context)
+    }
+
+    /// Apply file watcher events to the index of `root`, if it has one.
+    /// Returns the number of files indexed afterwards.
+    pub fn update(&self, root: &str, changes: &[FileChangeEvent], signal: &CancelSignal) -> CoreResult<u32> {
+        let Some(index) = self.indexes.get(&canonical_root(root)?).map(|index| Arc::clone(index.value())) else {
+            return Ok(0);
+        };
+        let mut index = index.write();
+        index.apply_changes(changes, signal)?;
+        Ok(index.files.len() as u32)
+    }
+
+    pub fn forget(&self, root: &str) -> CoreResult<bool> {
+        Ok(self.indexes.remove(&canonical_root(root)?).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn names(tags: &[Tag], is_definition: bool) -> Vec<String> {
+        tags.iter().filter(|t| t.is_definition == is_definition).map(|t| format!("{} {}", t.kind, t.name)).collect()
+    }
+
+    #[test]
+    fn test_tags_queries() {
+        let rust = tag(Grammar::Rust, b"struct Config;\nimpl Config {\n    fn load() -> Self { parse() }\n}\nfn parse() -> Config { Config }\n");
+        assert_eq!(names(&rust, true), ["class Config", "method load", "function parse"]);
+        assert_eq!(names(&rust, false), ["implementation Config", "call parse"]);
+
+        let typescript = tag(Grammar::TypeScript, b"interface Props { id: number }\nfunction render(p: Props) { return new View(p); }\n");
+        assert_eq!(names(&typescript, true), ["interface Props", "function render"]);
+        assert_eq!(names(&typescript, false), ["type Props", "class View"]);
+
+        let python = tag(Grammar::Python, b"class User:\n    def save(self):\n        validate(self)\n");
+        assert_eq!(names(&python, true), ["class User", "function save"]);
+        assert_eq!(names(&python, false), ["call validate"]);
+
+        let go = tag(Grammar::Go, b"package main\n\ntype Server struct{}\n\nfunc (s *Server) Start() { run(s) }\n");
+        assert_eq!(names(&go, true), ["type Server", "method Start"]);
+        assert_eq!(names(&go, false), ["type Server", "call run"]);
+    }
+
+    #[test]
+    fn test_symbol_context() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/config.ts"), "export interface Config { port: number }\nexport function load(): Config { return { port: 80 }; }\n").unwrap();
+        std::fs::write(root.join("src/main.ts"), "import { load } from './config';\nconst c: Config = load();\nload();\n").unwrap();
+        let root_str = root.to_string_lossy().to_string();
+        let main = root.join("src/main.ts").to_string_lossy().to_string();
+        let indexes = SymbolIndexes::default();
+        let signal = CancelSignal::default();
+
+        // The cursor on `load` in `const c: Config = load();`
+        let context = indexes.context(&root_str, &main, Some((1, 21)), &signal).unwrap();
+        assert_eq!(context.definitions, ["src/config.ts:2:17 function load: export function load(): Config { return { port: 80 }; }"]);
+        assert_eq!(context.references, ["src/main.ts:2:19 call load: const c: Config = load();", "src/main.ts:3:1 call load: load();"]);
+        assert_eq!(context.types, ["src/config.ts:1:18 interface Config: export interface Config { port: number }"]);
+
+        // Without a symbol at the cursor the file's own definitions are listed
+        let config = root.join("src/config.ts").to_string_lossy().to_string();
+        let context = indexes.context(&root_str, &config, None, &signal).unwrap();
+        assert_eq!(context.definitions.len(), 2);
+        assert!(context.references.is_empty());
+
+        // Watcher events update the index incrementally
+        std::fs::write(root.join("src/extra.ts"), "load();\n").unwrap();
+        std::fs::remove_file(root.join("src/main.ts")).unwrap();
+        let changes = [
+            FileChangeEvent { path: root.join("src/extra.ts").to_string_lossy().to_string(), change_type: FileChangeType::Added },
+            FileChangeEvent { path: main.clone(), change_type: FileChangeType::Deleted },
+        ];
+        assert_eq!(indexes.update(&root_str, &changes, &signal).unwrap(), 2);
+        let extra = root.join("src/extra.ts").to_string_lossy().to_string();
+        let context = indexes.context(&root_str, &extra, Some((0, 2)), &signal).unwrap();
+        assert_eq!(context.references, ["src/extra.ts:1:1 call load: load();"]);
+
+        // Deleting a folder drops every file below it, and only those
+        std::fs::write(root.join("src.ts"), "load();\n").unwrap();
+        let changes = [FileChangeEvent { path: root.join("src.ts").to_string_lossy().to_string(), change_type: FileChangeType::Added }];
+        assert_eq!(indexes.update(&root_str, &changes, &signal).unwrap(), 3);
+        std::fs::remove_dir_all(root.join("src")).unwrap();
+        let changes = [FileChangeEvent { path: root.join("src").to_string_lossy().to_string(), change_type: FileChangeType::Deleted }];
+        assert_eq!(indexes.update(&root_str, &changes, &signal).unwrap(), 1);
+        assert!(indexes.forget(&root_str).unwrap());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::error::{CoreError, CoreResult};
+use base64::Engine;
+use fancy_regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// How `cl100k_base` splits text into pieces before merging bytes
+const CL100K_PATTERN: &str =
+    r"'(?i:[sdmt]|ll|ve|re)|[^\r\n\p{L}\p{N}]?+\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]++[\r\n]*|\s*[\r\n]|\s+(?!\S)|\s+";
+/// How `o200k_base` splits text into pieces before merging bytes
+const O200K_PATTERN: &str = concat!(
+    r"[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]*[\p{Ll}\p{Lm}\p{Lo}\p{M}]+(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|[^\r\n\p{L}\p{N}]?[\p{Lu}\p{Lt}\p{Lm}\p{Lo}\p{M}]+[\p{Ll}\p{Lm}\p{Lo}\p{M}]*(?i:'s|'t|'re|'ve|'m|'ll|'d)?",
+    r"|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n/]*|\s*[\r\n]+|\s+(?!\S)|\s+",
+);
+/// Without a loaded tokenizer, counts assume this many bytes per token
+const BYTES_PER_TOKEN: usize = 4;
+
+/// A byte-pair encoder reading tiktoken's `.tiktoken` rank files, which hold
+/// one base64 token and its rank per line
+pub struct Tokenizer {
+    ranks: HashMap<Vec<u8>, u32>,
+    pattern: Regex,
+}
+
+impl Tokenizer {
+    /// Load the ranks at `path` for the `cl100k_base` or `o200k_base`
+    /// encoding, guessed from the file name when `encoding` is not given
+    pub fn load(path: &Path, encoding: Option<&str>) -> CoreResult<Tokenizer> {
+        let encoding = encoding.map(str::to_string).unwrap_or_else(|| {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            if name.contains("o200k") { "o200k_base" } else { "cl100k_base" }.to_string()
+        });
+        let pattern = match encoding.as_str() {
+            "cl100k_base" => CL100K_PATTERN,
+            "o200k_base" => O200K_PATTERN,
+            _ => return Err(CoreError::InvalidArgument(format!("Unsupported tokenizer encoding: {}", encoding))),
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| CoreError::io("Failed to read tokenizer", path, e))?;
+        Tokenizer::new(&text, pattern)
+    }
+
+    fn new(ranks_file: &str, pattern: &str) -> CoreResult<Tokenizer> {
+        let invalid = |line: usize| CoreError::InvalidArgument(format!("Invalid tokenizer rank on line {}", line + 1));
+        let mut ranks = HashMap::new();
+        for (number, line) in ranks_file.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let (token, rank) = line.split_once(' ').ok_or_else(|| invalid(number))?;
+            let token = base64::engine::general_purpose::STANDARD.decode(token).map_err(|_| invalid(number))?;
+            ranks.insert(token, rank.trim().parse().map_err(|_| invalid(number))?);
+        }
+        // Every byte needs a rank or some text could not be encoded at all
+        if (0..=u8::MAX).any(|byte| !ranks.contains_key([byte].as_slice())) {
+            return Err(CoreError::InvalidArgument("Tokenizer ranks do not cover every byte".to_string()));
+        }
+        let pattern = Regex::new(pattern).map_err(|e| CoreError::InvalidArgument(format!("Invalid tokenizer pattern: {}", e)))?;
+        Ok(Tokenizer { ranks, pattern })
+    }
+
+    /// The token ids of `text`, treating special tokens as ordinary text
+    pub fn encode(&self, text: &str) -> Vec<u32> {
+        let mut tokens = Vec::new();
+        for piece in self.pieces(text) {
+            let piece = match piece {
+                Ok(piece) => piece,
+                // Every byte has a rank of its own
+                Err(rest) => {
+                    tokens.extend(rest.bytes().map(|byte| self.ranks[[byte].as_slice()]));
+                    continue;
+                }
+            };
+            match self.ranks.get(piece) {
+                Some(&rank) => tokens.push(rank),
+                None => {
+                    let parts = self.merge(piece);
+                    tokens.extend(parts.windows(2).map(|w| self.ranks[&piece[w[0].0..w[1].0]]));
+                }
+            }
+        }
+        tokens
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        self.pieces(text)
+            .map(|piece| match piece {
+                Ok(piece) if self.ranks.contains_key(piece) => 1,
+                Ok(piece) => self.merge(piece).len() - 1,
+                Err(rest) => count(None, rest),
+            })
+            .sum()
+    }
+
+    /// The pieces of `text` that are merged separately. fancy-regex gives up
+    /// on a match that backtracks too much, and the text left from there on
+    /// comes last as `Err`.
+    fn pieces<'t>(&'t self, text: &'t str) -> impl Iterator<Item = Result<&'t [u8], &'t str>> + 't {
+        let mut matches = self.pattern.find_iter(text);
+        let mut end = 0;
+        std::iter::from_fn(move || match matches.next()? {
+            Ok(m) => {
+                end = m.end();
+                Some(Ok(m.as_str().as_bytes()))
+            }
+            Err(e) => {
+                tracing::debug!("Tokenizer pattern failed at byte {}: {}", end, e);
+                Some(Err(&text[end..]))
+            }
+        })
+    }
+
+    /// Split `piece` into token boundaries by repeatedly merging the adjacent
+    /// pair with the lowest rank, as tiktoken does
+    fn merge(&self, piece: &[u8]) -> Vec<(usize, u32)> {
+        let rank_of = |parts: &[(usize, u32)], i: usize| match parts.get(i + 3) {
+            Some(&(end, _)) => self.ranks.get(&piece[parts[i].0..end]).copied().unwrap_or(u32::MAX),
+            None => u32::MAX,
+        };
+        let lowest = |parts: &[(usize, u32)]| {
+            parts[..parts.len() - 1].iter().enumerate().min_by_key(|(i, (_, rank))| (*rank, *i)).map(|(i, &(_, rank))| (rank, i))
+        };
+
+        // Each part starts at a byte offset and carries the rank of merging it with the next
+        let mut parts: Vec<(usize, u32)> = (0..piece.len() - 1)
+            .map(|i| (i, self.ranks.get(&piece[i..i + 2]).copied().unwrap_or(u32::MAX)))
+            .collect();
+        parts.push((piece.len() - 1, u32::MAX));
+        parts.push((piece.len(), u32::MAX));
+
+        while let Some((_, i)) = lowest(&parts).filter(|&(rank, _)| rank != u32::MAX) {
+            if i > 0 {
+                parts[i - 1].1 = rank_of(&parts, i - 1);
+            }
+            parts[i].1 = rank_of(&parts, i);
+            parts.remove(i + 1);
+        }
+        parts
+    }
+}
+
+/// Count the tokens in `text` with `tokenizer`, or estimate them from its length
+pub fn count(tokenizer: Option<&Tokenizer>, text: &str) -> usize {
+    match tokenizer {
+        Some(tokenizer) => tokenizer.count(text),
+        None => text.len().div_ceil(BYTES_PER_TOKEN),
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Every byte, then the merges `he`, `ll`, `llo` and `hello`
+    pub fn test_tokenizer() -> Tokenizer {
+        let mut tokens: Vec<Vec<u8>> = (0..=u8::MAX).map(|byte| vec![byte]).collect();
+        tokens.extend(["he", "ll", "llo", "hello"].map(|t| t.as_bytes().to_vec()));
+        let file: String = tokens
+            .iter()
+            .enumerate()
+            .map(|(rank, token)| format!("{} {}\n", base64::engine::general_purpose::STANDARD.encode(token), rank))
+            .collect();
+        Tokenizer::new(&file, CL100K_PATTERN).unwrap()
+    }
+
+    #[test]
+    fn test_encode() {
+        let tokenizer = test_tokenizer();
+        // ` hello` has no rank of its own so it merges as ` ` + `he` + `llo` -> ` ` + `hello`
+        assert_eq!(tokenizer.encode("hello hello"), [259, 32, 259]);
+        assert_eq!(tokenizer.encode("hell"), [256, 257]);
+        assert_eq!(tokenizer.count("hello hello\n"), 4);
+        assert_eq!(tokenizer.encode(""), Vec::<u32>::new());
+        assert_eq!(count(None, "hello"), 2);
+
+        assert!(Tokenizer::new("aGU= 0\n", CL100K_PATTERN).is_err());
+        assert!(Tokenizer::new("not base64 at all\n", CL100K_PATTERN).is_err());
+    }
+
+    #[test]
+    fn test_backtrack_limit() {
+        let mut tokenizer = test_tokenizer();
+        tokenizer.pattern = fancy_regex::RegexBuilder::new(CL100K_PATTERN).backtrack_limit(1).build().unwrap();
+        // The match of the trailing spaces gives up, and they fall back to single bytes
+        let text = "hello hello      ";
+        assert_eq!(tokenizer.encode(text), [259, 32, 259, 32, 32, 32, 32, 32, 32]);
+        assert_eq!(tokenizer.count(text), 3 + count(None, "      "));
+    }
+
+    #[test]
+    fn test_pretokenizer_patterns() {
+        let pieces = |pattern| {
+            let regex = Regex::new(pattern).unwrap();
+            let text = "I'll fetch 12345 items, don't  wait!\n";
+            regex.find_iter(text).map(|m| m.unwrap().as_str().to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(pieces(CL100K_PATTERN), ["I", "'ll", " fetch", " ", "123", "45", " items", ",", " don", "'t", " ", " wait", "!\n"]);
+        assert_eq!(pieces(O200K_PATTERN), ["I'll", " fetch", " ", "123", "45", " items", ",", " don't", " ", " wait", "!\n"]);
+    }
+}
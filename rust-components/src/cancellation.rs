@@ -0,0 +1,113 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use crate::error::{CoreError, CoreResult};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use std::future::Future;
+
+/// Cancels native operations from JS. One token may be passed to several calls
+/// to cancel them together; a cancelled token stays cancelled.
+#[napi]
+#[derive(Default)]
+pub struct CancellationToken {
+    inner: tokio_util::sync::CancellationToken,
+}
+
+#[napi]
+impl CancellationToken {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reject every pending operation this token was passed to with a `Cancelled` error
+    #[napi]
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    #[napi(getter)]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+}
+
+/// The state of a `CancellationToken` argument, owned by the operation it was
+/// passed to. Operations called without a token use the default, which is
+/// never cancelled.
+#[derive(Clone, Default)]
+pub struct CancelSignal(tokio_util::sync::CancellationToken);
+
+impl CancelSignal {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.is_cancelled()
+    }
+
+    /// Fail with `Cancelled` once the token has been cancelled
+    pub fn check(&self) -> CoreResult<()> {
+        if self.is_cancelled() {
+            Err(CoreError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Drive `future` until it completes or the token is cancelled. Blocking work
+    /// behind the future is abandoned, so it should poll `is_cancelled` to stop early.
+    pub async fn run<T>(&self, future: impl Future<Output = CoreResult<T>>) -> CoreResult<T> {
+        self.check()?;
+        tokio::select! {
+            result = future => result,
+            _ = self.0.cancelled() => Err(CoreError::Cancelled),
+        }
+    }
+}
+
+impl From<&CancellationToken> for CancelSignal {
+    fn from(token: &CancellationToken) -> Self {
+        CancelSignal(token.inner.clone())
+    }
+}
+
+impl TypeName for CancelSignal {
+    fn type_name() -> &'static str {
+        "CancellationToken"
+    }
+
+    fn value_type() -> ValueType {
+        ValueType::Object
+    }
+}
+
+impl FromNapiValue for CancelSignal {
+    unsafe fn from_napi_value(env: sys::napi_env, napi_val: sys::napi_value) -> Result<Self> {
+        let token = <CancellationToken as FromNapiRef>::from_napi_ref(env, napi_val)?;
+        Ok(CancelSignal::from(token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_rejects_pending_future() {
+        let token = CancellationToken::new();
+        let signal = CancelSignal::from(&token);
+
+        let pending = tokio::spawn({
+            let signal = signal.clone();
+            async move { signal.run(std::future::pending::<CoreResult<()>>()).await }
+        });
+        token.cancel();
+
+        let err = pending.await.unwrap().unwrap_err();
+        assert!(matches!(err, CoreError::Cancelled));
+        assert!(signal.is_cancelled());
+        assert!(signal.check().is_err());
+        assert!(CancelSignal::default().check().is_ok());
+    }
+}
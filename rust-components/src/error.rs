@@ -0,0 +1,231 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use napi::bindgen_prelude::*;
+use napi::{Env, JsObject};
+use napi_derive::napi;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Stable error codes exposed to JS as `error.code`
+#[napi(string_enum)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    FileNotFound,
+    FileExists,
+    NoPermissions,
+    IsADirectory,
+    NotADirectory,
+    Unavailable,
+    Cancelled,
+    InvalidArgument,
+    /// The file changed on disk after it was read for a preview
+    FileModified,
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn from_io(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => ErrorCode::FileNotFound,
+            io::ErrorKind::AlreadyExists => ErrorCode::FileExists,
+            io::ErrorKind::PermissionDenied => ErrorCode::NoPermissions,
+            io::ErrorKind::IsADirectory => ErrorCode::IsADirectory,
+            io::ErrorKind::NotADirectory => ErrorCode::NotADirectory,
+            io::ErrorKind::InvalidInput => ErrorCode::InvalidArgument,
+            io::ErrorKind::Unsupported => ErrorCode::Unavailable,
+            _ => ErrorCode::Unknown,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::FileNotFound => "FileNotFound",
+            ErrorCode::FileExists => "FileExists",
+            ErrorCode::NoPermissions => "NoPermissions",
+            ErrorCode::IsADirectory => "IsADirectory",
+            ErrorCode::NotADirectory => "NotADirectory",
+            ErrorCode::Unavailable => "Unavailable",
+            ErrorCode::Cancelled => "Cancelled",
+            ErrorCode::InvalidArgument => "InvalidArgument",
+            ErrorCode::FileModified => "FileModified",
+            ErrorCode::Unknown => "Unknown",
+        }
+    }
+
+    /// The matching VS Code `FileSystemProviderErrorCode`, if there is one
+    fn provider_code(self) -> Option<&'static str> {
+        match self {
+            ErrorCode::FileNotFound => Some("EntryNotFound"),
+            ErrorCode::FileExists => Some("EntryExists"),
+            ErrorCode::NoPermissions => Some("NoPermissions"),
+            ErrorCode::IsADirectory => Some("EntryIsADirectory"),
+            ErrorCode::NotADirectory => Some("EntryNotADirectory"),
+            ErrorCode::Unavailable => Some("Unavailable"),
+            ErrorCode::Unknown => Some("Unknown"),
+            ErrorCode::Cancelled | ErrorCode::InvalidArgument | ErrorCode::FileModified => None,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CoreError {
+    #[error("{message}: {source}")]
+    Io {
+        message: String,
+        path: Option<PathBuf>,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{0}")]
+    InvalidArgument(String),
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("Operation cancelled")]
+    Cancelled,
+    #[error("{} has changed on disk", path.display())]
+    Modified { path: PathBuf },
+}
+
+pub type CoreResult<T> = std::result::Result<T, CoreError>;
+
+impl CoreError {
+    /// An I/O failure on `path`, e.g. `CoreError::io("Failed to read file", path, err)`
+    pub fn io(message: impl Into<String>, path: impl AsRef<Path>, source: io::Error) -> Self {
+        CoreError::Io {
+            message: message.into(),
+            path: Some(path.as_ref().to_path_buf()),
+            source,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            CoreError::Io { source, .. } => ErrorCode::from_io(source),
+            CoreError::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            CoreError::Unavailable(_) => ErrorCode::Unavailable,
+            CoreError::Cancelled => ErrorCode::Cancelled,
+            CoreError::Modified { .. } => ErrorCode::FileModified,
+        }
+    }
+
+    /// The raw OS error number, when the failure came from a system call
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            CoreError::Io { source, .. } => source.raw_os_error(),
+            _ => None,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            CoreError::Io { path, .. } => path.as_deref(),
+            CoreError::Modified { path } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Build the JS `Error` carrying `code`, `errno` and `path`. VS Code's
+    /// `FileSystemProviderError` naming is applied so `toFileSystemProviderErrorCode`
+    /// recognises the error without further mapping.
+    fn to_js_error(&self, env: &Env) -> Result<JsObject> {
+        let code = self.code();
+        let mut error = env.create_error(Error::new(Status::GenericFailure, self.to_string()))?;
+        error.set_named_property("code", env.create_string(code.as_str())?)?;
+        if let Some(provider_code) = code.provider_code() {
+            error.set_named_property("name", env.create_string(&format!("{} (FileSystemError)", provider_code))?)?;
+        }
+        if let Some(errno) = self.errno() {
+            error.set_named_property("errno", env.create_int32(errno)?)?;
+        }
+        if let Some(path) = self.path() {
+            error.set_named_property("path", env.create_string(&path.to_string_lossy())?)?;
+        }
+        Ok(error)
+    }
+}
+
+impl From<tokio::task::JoinError> for CoreError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        CoreError::Unavailable(format!("Background task failed: {}", err))
+    }
+}
+
+impl From<CoreError> for Error {
+    fn from(err: CoreError) -> Self {
+        Error::new(Status::GenericFailure, format!("{}: {}", err.code().as_str(), err))
+    }
+}
+
+/// Return type of N-API functions that fail with a [`CoreError`].
+///
+/// napi-rs can only reject promises with its own `Error`, whose `code` is the
+/// N-API status. On conversion this builds a proper JS `Error` instead and hands
+/// it back as a JS-backed `Error`, which napi-rs then rejects or throws as is.
+pub struct JsResult<T>(CoreResult<T>);
+
+impl<T> JsResult<T> {
+    pub fn into_result(self) -> CoreResult<T> {
+        self.0
+    }
+}
+
+impl<T> From<CoreResult<T>> for JsResult<T> {
+    fn from(result: CoreResult<T>) -> Self {
+        JsResult(result)
+    }
+}
+
+impl<T: TypeName> TypeName for JsResult<T> {
+    fn type_name() -> &'static str {
+        T::type_name()
+    }
+
+    fn value_type() -> ValueType {
+        T::value_type()
+    }
+}
+
+impl<T: ToNapiValue> ToNapiValue for JsResult<T> {
+    unsafe fn to_napi_value(env: sys::napi_env, val: Self) -> Result<sys::napi_value> {
+        match val.0 {
+            Ok(value) => T::to_napi_value(env, value),
+            Err(err) => {
+                let error = err.to_js_error(&Env::from_raw(env))?;
+                Err(Error::from(error.into_unknown()))
+            }
+        }
+    }
+}
+
+/// Run blocking filesystem work on the tokio blocking pool
+pub async fn blocking<T, F>(work: F) -> CoreResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> CoreResult<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error_codes() {
+        let missing = CoreError::io("Failed to read file", "/tmp/missing", io::Error::from_raw_os_error(2));
+        assert_eq!(missing.code(), ErrorCode::FileNotFound);
+        assert_eq!(missing.errno(), Some(2));
+        assert_eq!(missing.path(), Some(Path::new("/tmp/missing")));
+        assert!(missing.to_string().starts_with("Failed to read file: "));
+
+        let exists = CoreError::io("Failed to create", "/a", io::Error::from(io::ErrorKind::AlreadyExists));
+        assert_eq!(exists.code(), ErrorCode::FileExists);
+        assert_eq!(exists.errno(), None);
+
+        assert_eq!(CoreError::Cancelled.code(), ErrorCode::Cancelled);
+        assert_eq!(CoreError::InvalidArgument("bad".into()).path(), None);
+    }
+}
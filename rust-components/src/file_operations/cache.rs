@@ -0,0 +1,396 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use super::stat::FileStats;
+use crate::performance_monitor::PERF_MONITOR;
+use napi_derive::napi;
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+pub const DEFAULT_CACHE_TTL_MS: u32 = 30_000;
+pub const DEFAULT_CACHE_MAX_BYTES: f64 = 64.0 * 1024.0 * 1024.0;
+pub const DEFAULT_CACHE_MAX_ENTRY_BYTES: f64 = 1024.0 * 1024.0;
+
+/// Bookkeeping cost charged per entry on top of its contents
+const ENTRY_OVERHEAD_BYTES: usize = 256;
+
+#[napi(object)]
+#[derive(Default)]
+pub struct CacheOptions {
+    /// Cache file contents and stats (default: true)
+    pub enabled: Option<bool>,
+    /// Entries older than this are dropped even if the file looks unchanged (default: 30s)
+    pub ttl_ms: Option<u32>,
+    /// Memory budget for all entries; least recently used entries are evicted first (default: 64 MiB)
+    pub max_bytes: Option<f64>,
+    /// Larger files are never cached (default: 1 MiB)
+    pub max_entry_bytes: Option<f64>,
+    /// Also answer `stat` from the cache. Stats cannot be revalidated without a
+    /// syscall, so changes made by other processes show up after at most `ttlMs`
+    /// unless `invalidateCache` is called (default: false)
+    pub cache_stats: Option<bool>,
+}
+
+/// Identity of a file version. A changed mtime, size or inode means the cached
+/// contents are stale.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Fingerprint {
+    modified: Option<SystemTime>,
+    size: u64,
+    ino: u64,
+}
+
+impl Fingerprint {
+    fn of(meta: &Metadata) -> Self {
+        Self {
+            modified: meta.modified().ok(),
+            size: meta.len(),
+            ino: inode(meta),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn inode(meta: &Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &Metadata) -> u64 {
+    0
+}
+
+struct CacheEntry {
+    contents: Option<(Fingerprint, Vec<u8>)>,
+    stats: Option<FileStats>,
+    inserted: Instant,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn cost(&self, path: &Path) -> usize {
+        let contents = self.contents.as_ref().map_or(0, |(_, data)| data.len());
+        contents + path.as_os_str().len() + ENTRY_OVERHEAD_BYTES
+    }
+}
+
+struct CacheConfig {
+    enabled: bool,
+    ttl: Duration,
+    max_bytes: usize,
+    max_entry_bytes: usize,
+    cache_stats: bool,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<PathBuf, CacheEntry>,
+    /// Last-use tick to path, oldest first
+    lru: BTreeMap<u64, PathBuf>,
+    tick: u64,
+    bytes: usize,
+}
+
+/// Bounded LRU cache of file contents and stats keyed by path.
+///
+/// Contents are revalidated against the file's mtime, size and inode on every
+/// lookup, so a hit never returns data that differs from what is on disk.
+pub struct ReadCache {
+    config: Mutex<CacheConfig>,
+    state: Mutex<CacheState>,
+}
+
+impl ReadCache {
+    pub fn new() -> Self {
+        Self {
+            config: Mutex::new(CacheConfig {
+                enabled: true,
+                ttl: Duration::from_millis(DEFAULT_CACHE_TTL_MS as u64),
+                max_bytes: DEFAULT_CACHE_MAX_BYTES as usize,
+                max_entry_bytes: DEFAULT_CACHE_MAX_ENTRY_BYTES as usize,
+                cache_stats: false,
+            }),
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub fn configure(&self, options: &CacheOptions) {
+        {
+            let mut config = self.config.lock();
+            config.enabled = options.enabled.unwrap_or(true);
+            config.ttl = Duration::from_millis(options.ttl_ms.unwrap_or(DEFAULT_CACHE_TTL_MS) as u64);
+            config.max_bytes = options.max_bytes.unwrap_or(DEFAULT_CACHE_MAX_BYTES).max(0.0) as usize;
+            config.max_entry_bytes = options.max_entry_bytes.unwrap_or(DEFAULT_CACHE_MAX_ENTRY_BYTES).max(0.0) as usize;
+            config.cache_stats = options.cache_stats.unwrap_or(false);
+        }
+
+        if !self.is_enabled() {
+            self.clear();
+        } else {
+            let max_bytes = self.config.lock().max_bytes;
+            let mut state = self.state.lock();
+            state.evict_to(max_bytes);
+            report_size(&state);
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.lock().enabled
+    }
+
+    /// Whether contents of this size are worth caching
+    pub fn accepts(&self, len: u64) -> bool {
+        let config = self.config.lock();
+        config.enabled && len <= config.max_entry_bytes as u64
+    }
+
+    /// Cached contents of `path` if they still match `meta`
+    pub fn get_contents(&self, path: &Path, meta: &Metadata) -> Option<Vec<u8>> {
+        let ttl = self.config.lock().ttl;
+        let mut state = self.state.lock();
+        let fingerprint = Fingerprint::of(meta);
+
+        let fresh = state.entries.get(path).and_then(|entry| {
+            let (cached, data) = entry.contents.as_ref()?;
+            if *cached == fingerprint && entry.inserted.elapsed() < ttl {
+                Some(data.clone())
+            } else {
+                None
+            }
+        });
+
+        match fresh {
+            Some(data) => {
+                state.touch(path);
+                PERF_MONITOR.record_cache_hit();
+                Some(data)
+            }
+            None => {
+                if state.entries.get(path).is_some_and(|e| e.contents.is_some()) {
+                    state.remove(path);
+                    report_size(&state);
+                }
+                PERF_MONITOR.record_cache_miss();
+                None
+            }
+        }
+    }
+
+    /// Remember `data` as the contents of `path` as described by `meta`
+    pub fn insert_contents(&self, path: &Path, meta: &Metadata, data: &[u8]) {
+        if !self.accepts(data.len() as u64) {
+            return;
+        }
+        let max_bytes = self.config.lock().max_bytes;
+        let mut state = self.state.lock();
+        // Stats cached earlier are not revalidated here, so they go rather than get a fresh TTL
+        state.insert(path, CacheEntry {
+            contents: Some((Fingerprint::of(meta), data.to_vec())),
+            stats: None,
+            inserted: Instant::now(),
+            last_used: 0,
+        });
+        state.evict_to(max_bytes);
+        report_size(&state);
+    }
+
+    /// Cached stats of `path`, if stat caching is enabled and the entry has not expired
+    pub fn get_stats(&self, path: &Path) -> Option<FileStats> {
+        let (enabled, ttl) = {
+            let config = self.config.lock();
+            (config.enabled && config.cache_stats, config.ttl)
+        };
+        if !enabled {
+            return None;
+        }
+
+        let mut state = self.state.lock();
+        let stats = state.entries.get(path)
+            .filter(|entry| entry.inserted.elapsed() < ttl)
+            .and_then(|entry| entry.stats.clone());
+
+        if stats.is_some() {
+            state.touch(path);
+            PERF_MONITOR.record_cache_hit();
+        } else {
+            PERF_MONITOR.record_cache_miss();
+        }
+        stats
+    }
+
+    pub fn insert_stats(&self, path: &Path, stats: &FileStats) {
+        let (enabled, max_bytes) = {
+            let config = self.config.lock();
+            (config.enabled && config.cache_stats, config.max_bytes)
+        };
+        if !enabled {
+            return;
+        }
+
+        let mut state = self.state.lock();
+        let contents = state.entries.get_mut(path).and_then(|e| e.contents.take());
+        state.insert(path, CacheEntry {
+            contents,
+            stats: Some(stats.clone()),
+            inserted: Instant::now(),
+            last_used: 0,
+        });
+        state.evict_to(max_bytes);
+        report_size(&state);
+    }
+
+    /// Drop `path` and everything cached below it
+    pub fn invalidate(&self, path: &Path) {
+        let mut state = self.state.lock();
+        if state.entries.is_empty() {
+            return;
+        }
+
+        let stale: Vec<PathBuf> = state.entries.keys()
+            .filter(|cached| cached.starts_with(path))
+            .cloned()
+            .collect();
+        for cached in stale {
+            state.remove(&cached);
+        }
+        report_size(&state);
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock();
+        *state = CacheState::default();
+        report_size(&state);
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.state.lock().bytes
+    }
+}
+
+impl Default for ReadCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CacheState {
+    fn insert(&mut self, path: &Path, mut entry: CacheEntry) {
+        self.remove(path);
+        self.tick += 1;
+        entry.last_used = self.tick;
+        self.bytes += entry.cost(path);
+        self.lru.insert(self.tick, path.to_path_buf());
+        self.entries.insert(path.to_path_buf(), entry);
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(entry) = self.entries.remove(path) {
+            self.lru.remove(&entry.last_used);
+            self.bytes -= entry.cost(path);
+        }
+    }
+
+    fn touch(&mut self, path: &Path) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(path) {
+            self.lru.remove(&entry.last_used);
+            entry.last_used = tick;
+            self.lru.insert(tick, path.to_path_buf());
+        }
+    }
+
+    fn evict_to(&mut self, max_bytes: usize) {
+        while self.bytes > max_bytes {
+            let Some((_, oldest)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.bytes -= entry.cost(&oldest);
+            }
+        }
+    }
+}
+
+fn report_size(state: &CacheState) {
+    PERF_MONITOR.update_cache_size(state.bytes.min(u32::MAX as usize) as u32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(dir: &TempDir, name: &str, data: &[u8]) -> (PathBuf, Metadata) {
+        let path = dir.path().join(name);
+        std::fs::write(&path, data).unwrap();
+        let meta = std::fs::metadata(&path).unwrap();
+        (path, meta)
+    }
+
+    #[test]
+    fn test_cache_hit_and_revalidation() {
+        let dir = TempDir::new().unwrap();
+        let cache = ReadCache::new();
+        let (path, meta) = write(&dir, "a.txt", b"first");
+
+        assert!(cache.get_contents(&path, &meta).is_none());
+        cache.insert_contents(&path, &meta, b"first");
+        assert_eq!(cache.get_contents(&path, &meta).unwrap(), b"first");
+
+        // A different size invalidates the entry
+        let (_, changed) = write(&dir, "a.txt", b"second!");
+        assert!(cache.get_contents(&path, &changed).is_none());
+        assert_eq!(cache.size_bytes(), 0);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let dir = TempDir::new().unwrap();
+        let cache = ReadCache::new();
+        let entry_cost = 1000 + ENTRY_OVERHEAD_BYTES + dir.path().join("a.txt").as_os_str().len();
+        cache.configure(&CacheOptions { max_bytes: Some((entry_cost * 2) as f64), ..Default::default() });
+
+        let data = vec![b'x'; 1000];
+        let (a, meta_a) = write(&dir, "a.txt", &data);
+        let (b, meta_b) = write(&dir, "b.txt", &data);
+        let (c, meta_c) = write(&dir, "c.txt", &data);
+
+        cache.insert_contents(&a, &meta_a, &data);
+        cache.insert_contents(&b, &meta_b, &data);
+        assert!(cache.get_contents(&a, &meta_a).is_some());
+        cache.insert_contents(&c, &meta_c, &data);
+
+        assert!(cache.get_contents(&b, &meta_b).is_none());
+        assert!(cache.get_contents(&a, &meta_a).is_some());
+        assert!(cache.get_contents(&c, &meta_c).is_some());
+        assert!(cache.size_bytes() <= entry_cost * 2);
+    }
+
+    #[test]
+    fn test_cache_ttl_and_invalidation() {
+        let dir = TempDir::new().unwrap();
+        let cache = ReadCache::new();
+        let (path, meta) = write(&dir, "a.txt", b"data");
+
+        cache.configure(&CacheOptions { ttl_ms: Some(0), ..Default::default() });
+        cache.insert_contents(&path, &meta, b"data");
+        assert!(cache.get_contents(&path, &meta).is_none());
+
+        cache.configure(&CacheOptions::default());
+        cache.insert_contents(&path, &meta, b"data");
+        cache.invalidate(dir.path());
+        assert!(cache.get_contents(&path, &meta).is_none());
+
+        // Oversized files are not cached
+        cache.configure(&CacheOptions { max_entry_bytes: Some(2.0), ..Default::default() });
+        cache.insert_contents(&path, &meta, b"data");
+        assert_eq!(cache.size_bytes(), 0);
+    }
+}
@@ -0,0 +1,308 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use encoding_rs::{DecoderResult, Encoding, BIG5, EUC_JP, EUC_KR, GBK, SHIFT_JIS, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use napi_derive::napi;
+
+/// How much of a file is looked at to guess its encoding
+pub const SNIFF_LEN: usize = 64 * 1024;
+
+/// UTF-16 without a BOM is recognized by this share of ASCII code units, i.e.
+/// pairs with exactly one zero byte
+const UTF16_ASCII_RATIO: f64 = 0.3;
+/// A legacy CJK encoding is only guessed when this share of its characters
+/// are among the frequently used ones
+const CJK_COMMON_RATIO: f64 = 0.5;
+/// ... and this share of the decoded characters are in its scripts
+const CJK_SCRIPT_RATIO: f64 = 0.9;
+
+/// Options for `read_text_file`, mirroring VS Code's `IReadTextFileOptions`
+#[napi(object)]
+#[derive(Default)]
+pub struct ReadTextFileOptions {
+    /// VS Code encoding id (e.g. `utf16le`, `shiftjis`) or WHATWG label used when
+    /// the file has no BOM (default: `utf8`)
+    pub encoding: Option<String>,
+    /// Guess the encoding of files without a BOM instead of assuming `encoding`
+    pub auto_guess: Option<bool>,
+}
+
+#[napi(object)]
+pub struct TextFileContents {
+    pub value: String,
+    /// VS Code encoding id the file was decoded with, `utf8bom` for UTF-8 with a BOM
+    pub encoding: String,
+}
+
+/// Resolve a VS Code encoding id or a WHATWG label
+pub fn for_label(label: &str) -> Option<&'static Encoding> {
+    let encoding = match label.to_ascii_lowercase().as_str() {
+        "utf8" | "utf8bom" => UTF_8,
+        "utf16le" => UTF_16LE,
+        "utf16be" => UTF_16BE,
+        "shiftjis" => SHIFT_JIS,
+        "eucjp" => EUC_JP,
+        "euckr" => EUC_KR,
+        "gb2312" | "cp936" => GBK,
+        "big5hkscs" | "cp950" => BIG5,
+        "iso88591" => WINDOWS_1252,
+        other => return Encoding::for_label(other.as_bytes()).or_else(|| windows_code_page(other)),
+    };
+    Some(encoding)
+}
+
+/// VS Code ids of the form `windows1252` or `iso88592` lack the WHATWG dash
+fn windows_code_page(label: &str) -> Option<&'static Encoding> {
+    let (prefix, number) = ["windows", "iso8859"].iter().find_map(|p| Some((*p, label.strip_prefix(p)?)))?;
+    let label = if prefix == "windows" { format!("windows-{}", number) } else { format!("iso-8859-{}", number) };
+    Encoding::for_label(label.as_bytes())
+}
+
+/// The VS Code id of an encoding, e.g. `shiftjis` for Shift_JIS
+pub fn id(encoding: &'static Encoding, bom: bool) -> String {
+    if encoding == UTF_8 {
+        return if bom { "utf8bom" } else { "utf8" }.to_string();
+    }
+    encoding.name().to_ascii_lowercase().replace(['-', '_'], "")
+}
+
+/// The encoding announced by a BOM at the start of `bytes`, and the BOM length
+pub fn sniff_bom(bytes: &[u8]) -> Option<(&'static Encoding, usize)> {
+    Encoding::for_bom(bytes)
+}
+
+/// Guess the encoding of text starting with `sample`, or `None` if it looks
+/// binary. `complete` tells whether the sample is the whole text, so that a
+/// multi-byte sequence cut off at its end counts as malformed.
+///
+/// In order: a BOM, BOM-less UTF-16 (recognized by the zero bytes of ASCII
+/// code units), UTF-8, the legacy CJK encoding whose frequently used
+/// characters best explain the sample, and finally windows-1252 (Latin-1).
+pub fn detect(sample: &[u8], complete: bool) -> Option<&'static Encoding> {
+    if let Some((encoding, _)) = sniff_bom(sample) {
+        return Some(encoding);
+    }
+    if let Some(encoding) = detect_utf16(sample) {
+        return Some(encoding);
+    }
+    if sample.contains(&0) {
+        return None;
+    }
+    if is_utf8(sample, complete) {
+        return Some(UTF_8);
+    }
+    Some(detect_cjk(sample, complete).unwrap_or(WINDOWS_1252))
+}
+
+/// The encoding search reads a file in, from its first `SNIFF_LEN` bytes:
+/// the one announced by its BOM, then `configured`, then a guess. `None`
+/// means the file looks binary.
+pub fn detect_file(head: &[u8], complete: bool, configured: Option<&'static Encoding>) -> Option<&'static Encoding> {
+    match sniff_bom(head) {
+        Some((bom, _)) => Some(bom),
+        None => configured.or_else(|| detect(head, complete)),
+    }
+}
+
+/// Decode `bytes`, letting a BOM override `encoding`. Malformed sequences
+/// become U+FFFD. Returns the text and the VS Code id of the encoding used.
+pub fn decode(bytes: &[u8], encoding: &'static Encoding) -> (String, String) {
+    let (encoding, bom_len) = sniff_bom(bytes).unwrap_or((encoding, 0));
+    let text = encoding.decode_without_bom_handling(&bytes[bom_len..]).0.into_owned();
+    (text, id(encoding, bom_len > 0))
+}
+
+fn detect_utf16(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = &sample[..sample.len().min(SNIFF_LEN) & !1];
+    if pairs.is_empty() {
+        return None;
+    }
+    let (mut le, mut be) = (0usize, 0usize);
+    for pair in pairs.chunks_exact(2) {
+        match (pair[0], pair[1]) {
+            (0, 0) => return None,
+            (_, 0) => le += 1,
+            (0, _) => be += 1,
+            _ => {}
+        }
+    }
+    let units = (pairs.len() / 2) as f64;
+    match (le as f64 / units >= UTF16_ASCII_RATIO, be as f64 / units >= UTF16_ASCII_RATIO) {
+        (true, false) => Some(UTF_16LE),
+        (false, true) => Some(UTF_16BE),
+        _ => None,
+    }
+}
+
+fn is_utf8(sample: &[u8], complete: bool) -> bool {
+    match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        // A sequence cut off by the end of the sample is fine
+        Err(e) => !complete && e.error_len().is_none(),
+    }
+}
+
+fn detect_cjk(sample: &[u8], complete: bool) -> Option<&'static Encoding> {
+    let mut best: Option<(&'static Encoding, f64)> = None;
+    for encoding in [SHIFT_JIS, EUC_JP, EUC_KR, GBK, BIG5] {
+        let Some(text) = decode_strict(encoding, sample, complete) else { continue };
+        let (mut non_ascii, mut in_script, mut kana) = (0usize, 0usize, 0usize);
+        for c in text.chars().filter(|c| !c.is_ascii()) {
+            non_ascii += 1;
+            in_script += in_scripts(encoding, c) as usize;
+            kana += is_kana(c) as usize;
+        }
+        if non_ascii == 0 || (in_script as f64) < non_ascii as f64 * CJK_SCRIPT_RATIO {
+            continue;
+        }
+        // Hangul and hanzi decode to kanji in the Japanese encodings, but
+        // Japanese text is hardly ever written without kana
+        if (encoding == SHIFT_JIS || encoding == EUC_JP) && kana == 0 {
+            continue;
+        }
+        let score = common_ratio(encoding, sample);
+        // Ties go to the earlier, more specific encoding
+        if score >= CJK_COMMON_RATIO && best.is_none_or(|(_, s)| score > s) {
+            best = Some((encoding, score));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+/// Decode without replacement, or `None` if `sample` is malformed
+fn decode_strict(encoding: &'static Encoding, sample: &[u8], complete: bool) -> Option<String> {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut text = String::with_capacity(decoder.max_utf8_buffer_length_without_replacement(sample.len())?);
+    match decoder.decode_to_string_without_replacement(sample, &mut text, complete).0 {
+        DecoderResult::InputEmpty => Some(text),
+        _ => None,
+    }
+}
+
+/// Whether `c` belongs to the scripts written in `encoding`. Half-width
+/// katakana are left out on purpose: they are what Latin-1 and EUC text
+/// usually turn into when misread as Shift_JIS.
+fn in_scripts(encoding: &'static Encoding, c: char) -> bool {
+    let punctuation = matches!(c, '\u{3000}'..='\u{303F}' | '\u{FF01}'..='\u{FF60}' | '\u{2010}'..='\u{203F}');
+    let han = matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}');
+    let hangul = matches!(c, '\u{AC00}'..='\u{D7A3}');
+    punctuation
+        || if encoding == SHIFT_JIS || encoding == EUC_JP {
+            han || is_kana(c)
+        } else if encoding == EUC_KR {
+            hangul
+        } else {
+            han
+        }
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}')
+}
+
+/// The share of multi-byte characters whose lead byte is in the block of
+/// frequently used characters: kana and level 1 kanji for Japanese, KS X 1001
+/// hangul for Korean, GB2312 level 1 hanzi and Big5's frequently used hanzi
+fn common_ratio(encoding: &'static Encoding, sample: &[u8]) -> f64 {
+    let (mut total, mut common) = (0usize, 0usize);
+    let mut i = 0;
+    while i < sample.len() {
+        let lead = sample[i];
+        let len = if lead < 0x80 || (encoding == SHIFT_JIS && (0xA1..=0xDF).contains(&lead)) {
+            1
+        } else if encoding == EUC_JP && lead == 0x8F {
+            3
+        } else {
+            2
+        };
+        if len > 1 {
+            total += 1;
+            common += if encoding == SHIFT_JIS {
+                matches!(lead, 0x82 | 0x83 | 0x88..=0x98)
+            } else if encoding == EUC_JP {
+                matches!(lead, 0xA4 | 0xA5 | 0xB0..=0xCF)
+            } else if encoding == EUC_KR {
+                matches!(lead, 0xB0..=0xC8)
+            } else if encoding == GBK {
+                matches!(lead, 0xB0..=0xD7)
+            } else {
+                matches!(lead, 0xA4..=0xC6)
+            } as usize;
+        }
+        i += len;
+    }
+    if total == 0 {
+        0.0
+    } else {
+        common as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(text: &str, encoding: &'static Encoding) -> Vec<u8> {
+        if encoding == UTF_16LE || encoding == UTF_16BE {
+            let units = text.encode_utf16();
+            return if encoding == UTF_16LE {
+                units.flat_map(u16::to_le_bytes).collect()
+            } else {
+                units.flat_map(u16::to_be_bytes).collect()
+            };
+        }
+        let (bytes, _, unmappable) = encoding.encode(text);
+        assert!(!unmappable, "{} cannot encode {}", encoding.name(), text);
+        bytes.into_owned()
+    }
+
+    #[test]
+    fn test_detect() {
+        let japanese = "// 設定ファイルを読み込みます。見つからない場合は既定値を使います。\nfn main() {}\n";
+        let simplified = "// 读取配置文件，如果找不到就使用默认值。这是我们的项目说明。\n";
+        let traditional = "// 讀取設定檔案，如果找不到就使用預設值。這是我們的專案說明。\n";
+        let korean = "// 설정 파일을 읽습니다. 찾을 수 없으면 기본값을 사용합니다.\n";
+        let latin = "Café, naïve résumé: déjà vu über alles\n";
+
+        for (text, encoding) in [
+            (japanese, SHIFT_JIS),
+            (japanese, EUC_JP),
+            (simplified, GBK),
+            (traditional, BIG5),
+            (korean, EUC_KR),
+            (latin, WINDOWS_1252),
+            (japanese, UTF_16LE),
+            (korean, UTF_16BE),
+            (latin, UTF_8),
+        ] {
+            let bytes = encode(text, encoding);
+            assert_eq!(detect(&bytes, true).map(Encoding::name), Some(encoding.name()), "{}", text);
+            assert_eq!(decode(&bytes, encoding).0, text);
+        }
+
+        // A BOM wins over the requested encoding and is not part of the text
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(encode("hi", UTF_16LE));
+        assert_eq!(decode(&bytes, UTF_8), ("hi".to_string(), "utf16le".to_string()));
+        assert_eq!(decode(b"\xEF\xBB\xBFhi", WINDOWS_1252), ("hi".to_string(), "utf8bom".to_string()));
+
+        // A character cut off by the end of a partial sample is still UTF-8
+        assert_eq!(detect(&"é".as_bytes()[..1], false), Some(UTF_8));
+        assert_eq!(detect(b"PK\x03\x04\x00\x00", true), None);
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(for_label("shiftjis"), Some(SHIFT_JIS));
+        assert_eq!(for_label("Shift_JIS"), Some(SHIFT_JIS));
+        assert_eq!(for_label("windows1252"), Some(WINDOWS_1252));
+        assert_eq!(for_label("iso88592").map(Encoding::name), Some("ISO-8859-2"));
+        assert_eq!(for_label("utf8bom"), Some(UTF_8));
+        assert_eq!(for_label("klingon"), None);
+        assert_eq!(id(SHIFT_JIS, false), "shiftjis");
+        assert_eq!(id(UTF_16BE, false), "utf16be");
+        assert_eq!(id(WINDOWS_1252, false), "windows1252");
+    }
+}
@@ -0,0 +1,113 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use dashmap::DashMap;
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// An open file registered in a `FileHandleTable`
+#[derive(Clone)]
+pub struct OpenFile {
+    pub file: Arc<File>,
+    pub path: PathBuf,
+    pub writable: bool,
+}
+
+/// Maps the numeric handles given to JS onto open files.
+///
+/// Handles are never reused while the table lives, so a stale handle from JS
+/// cannot accidentally address a file that was opened later.
+pub struct FileHandleTable {
+    next_handle: AtomicU32,
+    files: DashMap<u32, OpenFile>,
+}
+
+impl FileHandleTable {
+    pub fn new() -> Self {
+        Self {
+            next_handle: AtomicU32::new(1),
+            files: DashMap::new(),
+        }
+    }
+
+    pub fn insert(&self, file: File, path: PathBuf, writable: bool) -> u32 {
+        let handle = self.next_handle.fetch_add(1, Ordering::Relaxed);
+        self.files.insert(handle, OpenFile { file: Arc::new(file), path, writable });
+        handle
+    }
+
+    pub fn get(&self, handle: u32) -> io::Result<OpenFile> {
+        self.files.get(&handle)
+            .map(|entry| entry.clone())
+            .ok_or_else(|| bad_handle(handle))
+    }
+
+    pub fn remove(&self, handle: u32) -> io::Result<OpenFile> {
+        self.files.remove(&handle)
+            .map(|(_, entry)| entry)
+            .ok_or_else(|| bad_handle(handle))
+    }
+}
+
+impl Default for FileHandleTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bad_handle(handle: u32) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid file handle {}", handle))
+}
+
+/// Read up to `buf.len()` bytes at `pos` without moving a shared cursor
+pub fn read_at(file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match positional_read(file, &mut buf[filled..], pos + filled as u64) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Write all of `buf` at `pos` without moving a shared cursor
+pub fn write_all_at(file: &File, buf: &[u8], pos: u64) -> io::Result<()> {
+    let mut written = 0;
+    while written < buf.len() {
+        match positional_write(file, &buf[written..], pos + written as u64) {
+            Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "Failed to write whole buffer")),
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn positional_read(file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, pos)
+}
+
+#[cfg(windows)]
+fn positional_read(file: &File, buf: &mut [u8], pos: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, pos)
+}
+
+#[cfg(unix)]
+fn positional_write(file: &File, buf: &[u8], pos: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::write_at(file, buf, pos)
+}
+
+#[cfg(windows)]
+fn positional_write(file: &File, buf: &[u8], pos: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_write(file, buf, pos)
+}
@@ -0,0 +1,131 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use memmap2::{Mmap, MmapOptions};
+use napi::bindgen_prelude::*;
+use std::ffi::c_void;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Deref;
+use std::ptr;
+
+/// Files at or above this size are read through a memory map by default
+pub const DEFAULT_MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// File data handed to JS, either as an owned copy or as a read-only memory map.
+///
+/// Mapped contents are exposed as an external `Buffer` that keeps the map alive
+/// until V8 collects it, so large files never get copied onto the heap.
+pub enum FileContents {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl FileContents {
+    /// Read `len` bytes of `file` starting at `offset`, through a memory map
+    /// when `len` reaches `mmap_threshold` and the file may be mapped safely.
+    ///
+    /// Touching a mapped page after another process truncated the file raises
+    /// SIGBUS and takes the whole extension host down. Neither a private
+    /// mapping nor a size check up front prevents that, so by policy only
+    /// read-only files are mapped and everything else is copied.
+    pub fn read(file: &mut File, offset: u64, len: usize, mmap_threshold: u64) -> io::Result<Self> {
+        let metadata = file.metadata()?;
+        let in_bounds = offset.checked_add(len as u64).is_some_and(|end| end <= metadata.len());
+        if len > 0 && len as u64 >= mmap_threshold && in_bounds && metadata.permissions().readonly() {
+            // Safety: not guaranteed. The range lies within the current size
+            // and the file is read-only, so a well-behaved writer cannot
+            // truncate it; its owner changing the permissions or root
+            // ignoring them still can. We accept that risk for read-only files.
+            let mmap = unsafe { MmapOptions::new().offset(offset).len(len).map(&*file)? };
+            return Ok(FileContents::Mapped(mmap));
+        }
+
+        let mut buffer = vec![0u8; len];
+        file.seek(SeekFrom::Start(offset))?;
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match file.read(&mut buffer[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        buffer.truncate(filled);
+        Ok(FileContents::Owned(buffer))
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self, FileContents::Mapped(_))
+    }
+}
+
+impl Deref for FileContents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileContents::Owned(data) => data,
+            FileContents::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+impl AsRef<[u8]> for FileContents {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl TypeName for FileContents {
+    fn type_name() -> &'static str {
+        "Buffer"
+    }
+
+    fn value_type() -> ValueType {
+        ValueType::Object
+    }
+}
+
+impl ToNapiValue for FileContents {
+    unsafe fn to_napi_value(env: sys::napi_env, val: Self) -> Result<sys::napi_value> {
+        let mmap = match val {
+            FileContents::Owned(data) => return Buffer::to_napi_value(env, Buffer::from(data)),
+            FileContents::Mapped(mmap) if mmap.is_empty() => {
+                return Buffer::to_napi_value(env, Buffer::from(Vec::new()));
+            }
+            FileContents::Mapped(mmap) => mmap,
+        };
+
+        let len = mmap.len();
+        let data = mmap.as_ptr() as *mut c_void;
+        let hint = Box::into_raw(Box::new(mmap));
+        let mut result = ptr::null_mut();
+
+        let mut status = sys::napi_create_external_buffer(
+            env,
+            len,
+            data,
+            Some(drop_mmap),
+            hint as *mut c_void,
+            &mut result,
+        );
+
+        // Electron's V8 sandbox forbids external buffers, fall back to a copy there
+        if status == sys::Status::napi_no_external_buffers_allowed {
+            let mmap = Box::from_raw(hint);
+            status = sys::napi_create_buffer_copy(env, len, mmap.as_ptr() as *const c_void, ptr::null_mut(), &mut result);
+        }
+
+        if status != sys::Status::napi_ok {
+            return Err(Error::new(Status::from(status), "Failed to create buffer for mapped file".to_string()));
+        }
+
+        Ok(result)
+    }
+}
+
+unsafe extern "C" fn drop_mmap(_env: sys::napi_env, _data: *mut c_void, hint: *mut c_void) {
+    drop(Box::from_raw(hint as *mut Mmap));
+}
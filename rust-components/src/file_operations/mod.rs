@@ -5,80 +5,692 @@
 
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
+use crate::cancellation::CancelSignal;
+use crate::error::{blocking, CoreError, CoreResult, JsResult};
+use crate::performance_monitor::{OperationType, PERF_MONITOR};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio::fs;
 
+mod cache;
+pub(crate) mod encoding;
+mod handles;
+mod mmap;
+mod mutation;
+mod scan;
+mod stat;
+use cache::ReadCache;
+use handles::FileHandleTable;
+pub use cache::CacheOptions;
+pub use encoding::{ReadTextFileOptions, TextFileContents};
+pub use mmap::{FileContents, DEFAULT_MMAP_THRESHOLD};
+pub use scan::{DirEntryInfo, ScanEntry, ScanOptions};
+pub use stat::FileStats;
+
+/// Most bytes a single `read` takes from a handle without a known size, e.g. a device
+const UNSIZED_READ_LIMIT: u64 = 1024 * 1024;
+
 #[napi]
-pub struct RustFileOperations;
+pub struct RustFileOperations {
+    mmap_threshold: AtomicU64,
+    handles: Arc<FileHandleTable>,
+    cache: Arc<ReadCache>,
+}
 
 #[napi]
 impl RustFileOperations {
     #[napi(constructor)]
     pub fn new() -> Self {
-        Self
+        Self {
+            mmap_threshold: AtomicU64::new(DEFAULT_MMAP_THRESHOLD),
+            handles: Arc::new(FileHandleTable::new()),
+            cache: Arc::new(ReadCache::new()),
+        }
+    }
+
+    /// Set the file size in bytes at which reads of read-only files switch to
+    /// memory-mapped buffers
+    #[napi]
+    pub fn set_mmap_threshold(&self, bytes: f64) {
+        self.mmap_threshold.store(bytes.max(0.0) as u64, Ordering::Relaxed);
+    }
+
+    /// Configure the read cache; omitted options fall back to their defaults
+    #[napi]
+    pub fn configure_cache(&self, options: Option<CacheOptions>) {
+        self.cache.configure(&options.unwrap_or_default());
+    }
+
+    /// Drop cached contents and stats of `path` and everything below it, e.g.
+    /// when a file watcher reports a change made by another process
+    #[napi]
+    pub fn invalidate_cache(&self, path: String) {
+        self.cache.invalidate(Path::new(&path));
+    }
+
+    #[napi]
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    /// Read a whole file.
+    ///
+    /// Files of at least the mmap threshold are mapped rather than copied only
+    /// when they are read-only, on the assumption that nobody truncates such a
+    /// file while it is mapped. That is a deliberate trade-off, not a guarantee:
+    /// the owner can make the file writable again and root ignores permissions,
+    /// and truncating a mapped file crashes the extension host on next access.
+    /// Writable files are always copied.
+    #[napi]
+    pub async fn read_file(&self, path: String, token: Option<CancelSignal>) -> JsResult<FileContents> {
+        let threshold = self.mmap_threshold.load(Ordering::Relaxed);
+        let signal = token.unwrap_or_default();
+        signal.run(read_file_async(path, threshold, Arc::clone(&self.cache))).await.into()
     }
 
+    /// Read a file as text, decoding it from the encoding announced by its BOM,
+    /// the requested one or, with `autoGuess`, the one it looks like
     #[napi]
-    pub async fn read_file(&self, path: String) -> Result<Buffer> {
-        let contents = fs::read(&path).await
-            .map_err(|e| Error::new(Status::GenericFailure, format!("Failed to read file: {}", e)))?;
-        Ok(Buffer::from(contents))
+    pub async fn read_text_file(&self, path: String, options: Option<ReadTextFileOptions>, token: Option<CancelSignal>) -> JsResult<TextFileContents> {
+        let opts = options.unwrap_or_default();
+        let requested = match opts.encoding.as_deref() {
+            Some(label) => match encoding::for_label(label) {
+                Some(encoding) => encoding,
+                None => return Err(CoreError::InvalidArgument(format!("Unsupported encoding: {}", label))).into(),
+            },
+            None => encoding_rs::UTF_8,
+        };
+
+        let threshold = self.mmap_threshold.load(Ordering::Relaxed);
+        let signal = token.unwrap_or_default();
+        let cache = Arc::clone(&self.cache);
+        signal.run(async move {
+            let contents = read_file_async(path, threshold, cache).await?;
+            blocking(move || {
+                let guessed = if opts.auto_guess.unwrap_or(false) {
+                    let sample = &contents[..contents.len().min(encoding::SNIFF_LEN)];
+                    encoding::detect(sample, sample.len() == contents.len())
+                } else {
+                    None
+                };
+                let (value, encoding) = encoding::decode(&contents, guessed.unwrap_or(requested));
+                Ok(TextFileContents { value, encoding })
+            })
+            .await
+        })
+        .await
+        .into()
     }
 
+    /// Read `length` bytes starting at `offset`; the result is shorter if the file ends first.
+    ///
+    /// Ranges of at least the mmap threshold are mapped under the same read-only
+    /// policy as `read_file`; writable files are always copied.
     #[napi]
-    pub async fn write_file(&self, path: String, data: Buffer) -> Result<()> {
-        fs::write(&path, data.as_ref()).await
-            .map_err(|e| Error::new(Status::GenericFailure, format!("Failed to write file: {}", e)))?;
-        Ok(())
+    pub async fn read_file_range(&self, path: String, offset: f64, length: f64, token: Option<CancelSignal>) -> JsResult<FileContents> {
+        if offset < 0.0 || length < 0.0 {
+            return Err(CoreError::InvalidArgument(format!("Invalid range: offset {} length {}", offset, length))).into();
+        }
+
+        let threshold = self.mmap_threshold.load(Ordering::Relaxed);
+        let (offset, length) = (offset as u64, length as u64);
+        let signal = token.unwrap_or_default();
+
+        signal.run(blocking(move || {
+            read_range_sync(Path::new(&path), offset, length, threshold)
+                .map_err(|e| CoreError::io("Failed to read file range", &path, e))
+        }))
+        .await
+        .into()
     }
 
     #[napi]
-    pub async fn read_dir(&self, path: String) -> Result<Vec<String>> {
-        let mut entries = fs::read_dir(&path).await
-            .map_err(|e| Error::new(Status::GenericFailure, format!("Failed to read directory: {}", e)))?;
-
-        let mut result = Vec::new();
-        while let Some(entry) = entries.next_entry().await
-            .map_err(|e| Error::new(Status::GenericFailure, format!("Failed to read directory entry: {}", e)))? {
-            if let Some(name) = entry.file_name().to_str() {
-                result.push(name.to_string());
+    pub async fn write_file(&self, path: String, data: Buffer, options: Option<WriteFileOptions>) -> JsResult<()> {
+        let opts = options.unwrap_or_default();
+        let cache = Arc::clone(&self.cache);
+        blocking(move || {
+            let result = write_file_sync(Path::new(&path), data.as_ref(), &opts);
+            cache.invalidate(Path::new(&path));
+            result.map_err(|e| CoreError::io("Failed to write file", &path, e))
+        })
+        .await
+        .into()
+    }
+
+    /// List the names in a directory. Names that are not valid UTF-8 come back
+    /// as a `Buffer` of their bytes, as Node's `readdir` does with `encoding: 'buffer'`.
+    #[napi]
+    pub async fn read_dir(&self, path: String, token: Option<CancelSignal>) -> JsResult<Vec<Either<String, Buffer>>> {
+        token.unwrap_or_default().run(read_dir_async(path)).await.into()
+    }
+
+    /// List a directory with VS Code file types, symlink flags, sizes and mtimes in one call
+    #[napi]
+    pub async fn read_dir_with_types(&self, path: String, token: Option<CancelSignal>) -> JsResult<Vec<DirEntryInfo>> {
+        let signal = token.unwrap_or_default();
+        signal.run(blocking(move || {
+            scan::read_dir_with_types(Path::new(&path))
+                .map_err(|e| CoreError::io("Failed to read directory", &path, e))
+        }))
+        .await
+        .into()
+    }
+
+    /// Describe every entry below `root` using a parallel, gitignore-aware walk
+    #[napi]
+    pub async fn scan_tree(&self, root: String, options: Option<ScanOptions>, token: Option<CancelSignal>) -> JsResult<Vec<ScanEntry>> {
+        let opts = options.unwrap_or_default();
+        let start = std::time::Instant::now();
+        let signal = token.unwrap_or_default();
+        let walk_signal = signal.clone();
+
+        signal.run(blocking(move || {
+            let metadata = std::fs::metadata(&root).map_err(|e| CoreError::io("Failed to scan directory", &root, e))?;
+            if !metadata.is_dir() {
+                let err = io::Error::new(io::ErrorKind::NotADirectory, format!("{} is not a directory", root));
+                return Err(CoreError::io("Failed to scan directory", &root, err));
             }
+
+            let entries = scan::scan_tree(Path::new(&root), &opts, &walk_signal);
+            walk_signal.check()?;
+            tracing::debug!("Tree scan completed in {:?}, found {} entries", start.elapsed(), entries.len());
+            Ok(entries)
+        }))
+        .await
+        .into()
+    }
+
+    /// Stat a path, following symlinks but flagging them via `isSymlink`
+    #[napi]
+    pub async fn stat(&self, path: String) -> JsResult<FileStats> {
+        if let Some(stats) = self.cache.get_stats(Path::new(&path)) {
+            return Ok(stats).into();
         }
 
-        Ok(result)
+        let cache = Arc::clone(&self.cache);
+        blocking(move || {
+            let stats = stat::stat(Path::new(&path)).map_err(|e| CoreError::io("Failed to get file stats", &path, e))?;
+            cache.insert_stats(Path::new(&path), &stats);
+            Ok(stats)
+        })
+        .await
+        .into()
+    }
+
+    /// Stat a path without following a final symlink
+    #[napi]
+    pub async fn lstat(&self, path: String) -> JsResult<FileStats> {
+        blocking(move || stat::lstat(Path::new(&path)).map_err(|e| CoreError::io("Failed to get file stats", &path, e)))
+            .await
+            .into()
+    }
+
+    /// Resolve symlinks and `.`/`..` components to an absolute path
+    #[napi]
+    pub async fn realpath(&self, path: String) -> JsResult<String> {
+        blocking(move || {
+            stat::realpath(Path::new(&path))
+                .map(|p| p.to_string_lossy().to_string())
+                .map_err(|e| CoreError::io("Failed to resolve path", &path, e))
+        })
+        .await
+        .into()
     }
 
+    /// Delete a file or folder; non-empty folders need `recursive`
     #[napi]
-    pub async fn stat(&self, path: String) -> Result<FileStats> {
-        let metadata = fs::metadata(&path).await
-            .map_err(|e| Error::new(Status::GenericFailure, format!("Failed to get file stats: {}", e)))?;
-
-        Ok(FileStats {
-            size: metadata.len() as f64,
-            is_file: metadata.is_file(),
-            is_directory: metadata.is_dir(),
-            modified: metadata.modified()
-                .map_err(|e| Error::new(Status::GenericFailure, format!("Failed to get modified time: {}", e)))?
-                .duration_since(std::time::UNIX_EPOCH)
-                .map_err(|e| Error::new(Status::GenericFailure, format!("Invalid modified time: {}", e)))?
-                .as_millis() as f64,
+    pub async fn delete(&self, path: String, options: Option<DeleteOptions>) -> JsResult<()> {
+        let opts = options.unwrap_or_default();
+        let recursive = opts.recursive.unwrap_or(false);
+        let use_trash = opts.use_trash.unwrap_or(false);
+        let cache = Arc::clone(&self.cache);
+
+        blocking(move || {
+            cache.invalidate(Path::new(&path));
+            let operation = PERF_MONITOR.start_operation(OperationType::Delete);
+            let result = mutation::delete(Path::new(&path), recursive, use_trash);
+            operation.complete();
+            result.map_err(|e| CoreError::io(format!("Failed to delete {}", path), &path, e))
+        })
+        .await
+        .into()
+    }
+
+    #[napi]
+    pub async fn rename(&self, from: String, to: String, options: Option<FileOverwriteOptions>) -> JsResult<()> {
+        let overwrite = options.and_then(|o| o.overwrite).unwrap_or(false);
+        let cache = Arc::clone(&self.cache);
+
+        blocking(move || {
+            cache.invalidate(Path::new(&from));
+            cache.invalidate(Path::new(&to));
+            let operation = PERF_MONITOR.start_operation(OperationType::Rename);
+            let result = mutation::rename(Path::new(&from), Path::new(&to), overwrite);
+            operation.complete();
+            result.map_err(|e| CoreError::io(format!("Failed to rename {} to {}", from, to), failed_path(&e, &from, &to), e))
+        })
+        .await
+        .into()
+    }
+
+    /// Copy a file or folder tree, cloning file contents where the filesystem supports it
+    #[napi]
+    pub async fn copy(&self, from: String, to: String, options: Option<FileOverwriteOptions>) -> JsResult<()> {
+        let overwrite = options.and_then(|o| o.overwrite).unwrap_or(false);
+        let cache = Arc::clone(&self.cache);
+
+        blocking(move || {
+            cache.invalidate(Path::new(&to));
+            let operation = PERF_MONITOR.start_operation(OperationType::Copy);
+            let result = mutation::copy(Path::new(&from), Path::new(&to), overwrite);
+            operation.complete();
+            result.map_err(|e| CoreError::io(format!("Failed to copy {} to {}", from, to), failed_path(&e, &from, &to), e))
+        })
+        .await
+        .into()
+    }
+
+    /// Create a folder and any missing parents
+    #[napi]
+    pub async fn mkdirp(&self, path: String) -> JsResult<()> {
+        blocking(move || {
+            mutation::mkdirp(Path::new(&path)).map_err(|e| CoreError::io(format!("Failed to create directory {}", path), &path, e))
+        })
+        .await
+        .into()
+    }
+
+    /// Open a file and return a handle for `read`/`write`/`ftruncate`/`close`.
+    /// With `create` the file is opened for writing and truncated, otherwise read-only.
+    #[napi]
+    pub async fn open(&self, path: String, options: Option<OpenFileOptions>) -> JsResult<u32> {
+        let opts = options.unwrap_or_default();
+        let create = opts.create.unwrap_or(false);
+        let unlock = opts.unlock.unwrap_or(false);
+        let handles = Arc::clone(&self.handles);
+        let cache = Arc::clone(&self.cache);
+
+        blocking(move || {
+            if create {
+                cache.invalidate(Path::new(&path));
+            }
+            open_sync(&path, create, unlock)
+                .map(|file| handles.insert(file, PathBuf::from(&path), create))
+                .map_err(|e| CoreError::io("Failed to open file", &path, e))
+        })
+        .await
+        .into()
+    }
+
+    /// Read up to `length` bytes at `position`; a shorter buffer means end of file
+    #[napi]
+    pub async fn read(&self, fd: u32, position: f64, length: u32, token: Option<CancelSignal>) -> JsResult<Buffer> {
+        let open_file = match self.handles.get(fd) {
+            Ok(open_file) => open_file,
+            Err(e) => return Err(handle_error("Failed to read file", e)).into(),
+        };
+        let signal = token.unwrap_or_default();
+
+        signal.run(blocking(move || {
+            let position = position.max(0.0) as u64;
+            // Size the buffer by what the file holds, not by what was asked for
+            let metadata = open_file.file.metadata().map_err(|e| handle_error("Failed to read file", e))?;
+            let available = match metadata.is_file() {
+                true => metadata.len().saturating_sub(position),
+                false => UNSIZED_READ_LIMIT,
+            };
+            let mut buffer = vec![0u8; (length as u64).min(available) as usize];
+            let read = handles::read_at(&open_file.file, &mut buffer, position)
+                .map_err(|e| handle_error("Failed to read file", e))?;
+            buffer.truncate(read);
+            Ok(Buffer::from(buffer))
+        }))
+        .await
+        .into()
+    }
+
+    /// Write the whole buffer at `position` and return the number of bytes written
+    #[napi]
+    pub async fn write(&self, fd: u32, position: f64, data: Buffer) -> JsResult<u32> {
+        let open_file = match self.handles.get(fd) {
+            Ok(open_file) => open_file,
+            Err(e) => return Err(handle_error("Failed to write file", e)).into(),
+        };
+        self.cache.invalidate(&open_file.path);
+
+        blocking(move || {
+            handles::write_all_at(&open_file.file, data.as_ref(), position.max(0.0) as u64)
+                .map_err(|e| handle_error("Failed to write file", e))?;
+            Ok(data.len() as u32)
+        })
+        .await
+        .into()
+    }
+
+    #[napi]
+    pub async fn ftruncate(&self, fd: u32, length: f64) -> JsResult<()> {
+        let open_file = match self.handles.get(fd) {
+            Ok(open_file) => open_file,
+            Err(e) => return Err(handle_error("Failed to truncate file", e)).into(),
+        };
+        self.cache.invalidate(&open_file.path);
+
+        blocking(move || {
+            open_file.file.set_len(length.max(0.0) as u64).map_err(|e| handle_error("Failed to truncate file", e))
+        })
+        .await
+        .into()
+    }
+
+    /// Close a handle, flushing written data to disk first
+    #[napi]
+    pub async fn close(&self, fd: u32) -> JsResult<()> {
+        let open_file = match self.handles.remove(fd) {
+            Ok(open_file) => open_file,
+            Err(e) => return Err(handle_error("Failed to close file", e)).into(),
+        };
+
+        if !open_file.writable {
+            return Ok(()).into();
+        }
+
+        blocking(move || open_file.file.sync_data().map_err(|e| handle_error("Failed to close file", e)))
+            .await
+            .into()
+    }
+}
+
+async fn read_file_async(path: String, mmap_threshold: u64, cache: Arc<ReadCache>) -> CoreResult<FileContents> {
+    let metadata = fs::metadata(&path).await.map_err(|e| CoreError::io("Failed to read file", &path, e))?;
+
+    if metadata.is_file() && metadata.len() >= mmap_threshold {
+        return blocking(move || {
+            let read = || {
+                let mut file = std::fs::File::open(&path)?;
+                let len = file.metadata()?.len();
+                FileContents::read(&mut file, 0, len as usize, mmap_threshold)
+            };
+            read().map_err(|e| CoreError::io("Failed to read file", &path, e))
         })
+        .await;
     }
+
+    let cacheable = metadata.is_file() && cache.accepts(metadata.len());
+    if cacheable {
+        if let Some(contents) = cache.get_contents(Path::new(&path), &metadata) {
+            return Ok(FileContents::Owned(contents));
+        }
+    }
+
+    let contents = fs::read(&path).await.map_err(|e| CoreError::io("Failed to read file", &path, e))?;
+    // A file that changed while being read keeps the old fingerprint and is revalidated next time
+    if cacheable && contents.len() as u64 == metadata.len() {
+        cache.insert_contents(Path::new(&path), &metadata, &contents);
+    }
+    Ok(FileContents::Owned(contents))
+}
+
+async fn read_dir_async(path: String) -> CoreResult<Vec<Either<String, Buffer>>> {
+    let mut entries = fs::read_dir(&path).await.map_err(|e| CoreError::io("Failed to read directory", &path, e))?;
+
+    let mut result = Vec::new();
+    while let Some(entry) = entries.next_entry().await
+        .map_err(|e| CoreError::io("Failed to read directory entry", &path, e))? {
+        let name = entry.file_name();
+        result.push(match scan::raw_name(&name) {
+            Some(raw) => Either::B(raw),
+            None => Either::A(name.to_string_lossy().to_string()),
+        });
+    }
+
+    Ok(result)
+}
+
+fn open_sync(path: &str, create: bool, unlock: bool) -> io::Result<std::fs::File> {
+    if create && unlock {
+        if let Ok(meta) = std::fs::metadata(path) {
+            if meta.permissions().readonly() {
+                std::fs::set_permissions(path, writable_permissions(&meta))?;
+            }
+        }
+    }
+
+    if create {
+        OpenOptions::new().write(true).create(true).truncate(true).read(true).open(path)
+    } else {
+        std::fs::File::open(path)
+    }
+}
+
+/// Errors on open handles have no path to report
+fn handle_error(message: &str, source: io::Error) -> CoreError {
+    CoreError::Io { message: message.to_string(), path: None, source }
+}
+
+/// The path a rename or copy failed on: the target when it is in the way
+fn failed_path<'p>(e: &io::Error, from: &'p str, to: &'p str) -> &'p str {
+    if e.kind() == io::ErrorKind::AlreadyExists { to } else { from }
 }
 
+fn read_range_sync(path: &Path, offset: u64, length: u64, mmap_threshold: u64) -> io::Result<FileContents> {
+    let mut file = std::fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let len = length.min(size.saturating_sub(offset));
+
+    if len == 0 {
+        return Ok(FileContents::Owned(Vec::new()));
+    }
+
+    FileContents::read(&mut file, offset, len as usize, mmap_threshold)
+}
+
+/// Options for `delete`, mirroring VS Code's `IFileDeleteOptions`
+#[napi(object)]
+#[derive(Default)]
+pub struct DeleteOptions {
+    pub recursive: Option<bool>,
+    /// Move to the platform trash instead of deleting permanently
+    pub use_trash: Option<bool>,
+}
+
+/// Options for `rename` and `copy`, mirroring VS Code's `IFileOverwriteOptions`
+#[napi(object)]
+#[derive(Default)]
+pub struct FileOverwriteOptions {
+    pub overwrite: Option<bool>,
+}
+
+/// Options for `open`, mirroring VS Code's `IFileOpenOptions`
+#[napi(object)]
+#[derive(Default)]
+pub struct OpenFileOptions {
+    /// Open for writing, creating and truncating the file
+    pub create: Option<bool>,
+    /// Clear the readonly flag of an existing file before opening it for writing
+    pub unlock: Option<bool>,
+}
+
+/// Options for `write_file`, mirroring VS Code's `IFileWriteOptions`
 #[napi(object)]
-pub struct FileStats {
-    pub size: f64,
-    pub is_file: bool,
-    pub is_directory: bool,
-    pub modified: f64,
+#[derive(Default)]
+pub struct WriteFileOptions {
+    /// Create the file if it does not exist (default: true)
+    pub create: Option<bool>,
+    /// Replace the file if it already exists (default: true)
+    pub overwrite: Option<bool>,
+    /// Clear the readonly flag of an existing file before writing
+    pub unlock: Option<bool>,
+    /// Write through a temp file that is renamed over the target
+    pub atomic: Option<bool>,
+    /// Suffix of the temp file used for atomic writes (default: ".vsctmp")
+    pub atomic_postfix: Option<String>,
+}
+
+pub(crate) const DEFAULT_ATOMIC_POSTFIX: &str = ".vsctmp";
+
+/// Resolve the symlink at `path`, including one whose target does not exist
+/// yet, which `canonicalize` refuses
+fn resolve_link(path: &Path) -> io::Result<PathBuf> {
+    let mut link = path.to_path_buf();
+    // The same hop limit as Linux' MAXSYMLINKS
+    for _ in 0..40 {
+        match std::fs::canonicalize(&link) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            resolved => return resolved,
+        }
+        let target = std::fs::read_link(&link)?;
+        link = match link.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+        match std::fs::symlink_metadata(&link) {
+            Ok(meta) if meta.file_type().is_symlink() => {}
+            _ => return Ok(link),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Too many levels of symbolic links at {}", path.display())))
+}
+
+fn write_file_sync(path: &Path, data: &[u8], opts: &WriteFileOptions) -> io::Result<()> {
+    // Write through symlinks instead of replacing the link itself
+    let target = match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => resolve_link(path)?,
+        _ => path.to_path_buf(),
+    };
+
+    let existing = match std::fs::metadata(&target) {
+        Ok(meta) => Some(meta),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    match existing {
+        None if !opts.create.unwrap_or(true) => {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", target.display())));
+        }
+        Some(_) if !opts.overwrite.unwrap_or(true) => {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", target.display())));
+        }
+        Some(ref meta) if meta.is_dir() => {
+            return Err(io::Error::new(io::ErrorKind::IsADirectory, format!("{} is a directory", target.display())));
+        }
+        _ => {}
+    }
+
+    let existing = match existing {
+        Some(meta) if opts.unlock.unwrap_or(false) && meta.permissions().readonly() => {
+            std::fs::set_permissions(&target, writable_permissions(&meta))?;
+            Some(std::fs::metadata(&target)?)
+        }
+        other => other,
+    };
+
+    if opts.atomic.unwrap_or(false) {
+        let postfix = opts.atomic_postfix.as_deref().unwrap_or(DEFAULT_ATOMIC_POSTFIX);
+        write_atomic(&target, data, existing.as_ref(), postfix)
+    } else {
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(&target)?;
+        file.write_all(data)
+    }
+}
+
+/// Write `data` to a sibling temp file, fsync it and rename it over `target`,
+/// so a crash leaves either the old or the new content but never a partial file
+pub(crate) fn write_atomic(target: &Path, data: &[u8], existing: Option<&std::fs::Metadata>, postfix: &str) -> io::Result<()> {
+    let parent = match target.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = target.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no file name", target.display())))?;
+
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(format!(".{}{}", &uuid::Uuid::new_v4().simple().to_string()[..8], postfix));
+    let temp_path = parent.join(temp_name);
+
+    let result = (|| {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&temp_path)?;
+        file.write_all(data)?;
+
+        if let Some(meta) = existing {
+            file.set_permissions(meta.permissions())?;
+            preserve_ownership(&file, meta)?;
+        }
+
+        file.sync_all()?;
+        drop(file);
+
+        std::fs::rename(&temp_path, target)?;
+        sync_dir(&parent)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn writable_permissions(meta: &std::fs::Metadata) -> std::fs::Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::Permissions::from_mode(meta.permissions().mode() | 0o200)
+}
+
+#[cfg(not(unix))]
+fn writable_permissions(meta: &std::fs::Metadata) -> std::fs::Permissions {
+    let mut perms = meta.permissions();
+    perms.set_readonly(false);
+    perms
+}
+
+#[cfg(unix)]
+fn preserve_ownership(file: &std::fs::File, meta: &std::fs::Metadata) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    match std::os::unix::fs::fchown(file, Some(meta.uid()), Some(meta.gid())) {
+        // Only privileged processes may give files away; keep our own ownership then
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(not(unix))]
+fn preserve_ownership(_file: &std::fs::File, _meta: &std::fs::Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    std::fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ErrorCode;
     use tempfile::TempDir;
     use std::fs as std_fs;
     use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn utf8_names(entries: Vec<Either<String, Buffer>>) -> Vec<String> {
+        entries.into_iter().map(|entry| match entry {
+            Either::A(name) => name,
+            Either::B(_) => panic!("expected a UTF-8 name"),
+        }).collect()
+    }
+
+    fn set_readonly(path: &Path) {
+        let mut perms = std_fs::metadata(path).unwrap().permissions();
+        perms.set_readonly(true);
+        std_fs::set_permissions(path, perms).unwrap();
+    }
     
     #[tokio::test]
     async fn test_read_file() {
@@ -88,7 +700,7 @@ mod tests {
         std_fs::write(&file_path, content).unwrap();
         
         let ops = RustFileOperations::new();
-        let result = ops.read_file(file_path.to_str().unwrap().to_string()).await.unwrap();
+        let result = ops.read_file(file_path.to_str().unwrap().to_string(), None).await.into_result().unwrap();
         
         assert_eq!(result.as_ref(), content);
     }
@@ -102,8 +714,9 @@ mod tests {
         let ops = RustFileOperations::new();
         ops.write_file(
             file_path.to_str().unwrap().to_string(),
-            content.clone()
-        ).await.unwrap();
+            content.clone(),
+            None
+        ).await.into_result().unwrap();
         
         let result = std_fs::read(&file_path).unwrap();
         assert_eq!(result, b"Test write content");
@@ -125,7 +738,7 @@ mod tests {
         std_fs::create_dir(&sub_dir).unwrap();
         
         let ops = RustFileOperations::new();
-        let mut entries = ops.read_dir(temp_dir.path().to_str().unwrap().to_string()).await.unwrap();
+        let mut entries = utf8_names(ops.read_dir(temp_dir.path().to_str().unwrap().to_string(), None).await.into_result().unwrap());
         entries.sort(); // Sort for consistent testing
         
         assert_eq!(entries.len(), 4);
@@ -143,7 +756,7 @@ mod tests {
         std_fs::write(&file_path, content).unwrap();
         
         let ops = RustFileOperations::new();
-        let stats = ops.stat(file_path.to_str().unwrap().to_string()).await.unwrap();
+        let stats = ops.stat(file_path.to_str().unwrap().to_string()).await.into_result().unwrap();
         
         assert_eq!(stats.size, content.len() as f64);
         assert!(stats.is_file);
@@ -163,7 +776,7 @@ mod tests {
         std_fs::create_dir(&dir_path).unwrap();
         
         let ops = RustFileOperations::new();
-        let stats = ops.stat(dir_path.to_str().unwrap().to_string()).await.unwrap();
+        let stats = ops.stat(dir_path.to_str().unwrap().to_string()).await.into_result().unwrap();
         
         assert!(!stats.is_file);
         assert!(stats.is_directory);
@@ -173,21 +786,25 @@ mod tests {
     #[tokio::test]
     async fn test_error_file_not_found() {
         let ops = RustFileOperations::new();
-        let result = ops.read_file("/non/existent/file.txt".to_string()).await;
+        let result = ops.read_file("/non/existent/file.txt".to_string(), None).await.into_result();
         
         assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(err.reason.contains("Failed to read file"));
+        let err = result.err().unwrap();
+        assert!(err.to_string().contains("Failed to read file"));
+        assert_eq!(err.code(), ErrorCode::FileNotFound);
+        assert_eq!(err.path(), Some(Path::new("/non/existent/file.txt")));
+        assert!(err.errno().is_some());
     }
     
     #[tokio::test]
     async fn test_error_invalid_directory() {
         let ops = RustFileOperations::new();
-        let result = ops.read_dir("/non/existent/directory".to_string()).await;
+        let result = ops.read_dir("/non/existent/directory".to_string(), None).await.into_result();
         
         assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(err.reason.contains("Failed to read directory"));
+        let err = result.err().unwrap();
+        assert!(err.to_string().contains("Failed to read directory"));
+        assert_eq!(err.code(), ErrorCode::FileNotFound);
     }
     
     #[tokio::test]
@@ -204,7 +821,7 @@ mod tests {
             
             let handle = tokio::spawn(async move {
                 let ops = RustFileOperations::new();
-                ops.write_file(path_str, content).await
+                ops.write_file(path_str, content, None).await.into_result()
             });
             handles.push(handle);
         }
@@ -215,7 +832,7 @@ mod tests {
         }
         
         // Verify all files exist
-        let entries = ops.read_dir(temp_dir.path().to_str().unwrap().to_string()).await.unwrap();
+        let entries = ops.read_dir(temp_dir.path().to_str().unwrap().to_string(), None).await.into_result().unwrap();
         assert_eq!(entries.len(), 10);
         
         // Read all files concurrently
@@ -226,7 +843,7 @@ mod tests {
             
             let handle = tokio::spawn(async move {
                 let ops = RustFileOperations::new();
-                ops.read_file(path_str).await
+                ops.read_file(path_str, None).await.into_result()
             });
             read_handles.push((i, handle));
         }
@@ -249,12 +866,410 @@ mod tests {
         std_fs::write(&file_path, &large_content).unwrap();
         
         let ops = RustFileOperations::new();
-        let result = ops.read_file(file_path.to_str().unwrap().to_string()).await.unwrap();
+        let result = ops.read_file(file_path.to_str().unwrap().to_string(), None).await.into_result().unwrap();
         
         assert_eq!(result.len(), large_content.len());
         assert_eq!(result.as_ref(), large_content.as_slice());
     }
     
+    #[tokio::test]
+    async fn test_mmap_read_above_threshold() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("mapped.dat");
+        let content: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        std_fs::write(&file_path, &content).unwrap();
+
+        let ops = RustFileOperations::new();
+        ops.set_mmap_threshold(4096.0);
+        // Writable files could be truncated under the map, so they are copied
+        let copied = ops.read_file(file_path.to_str().unwrap().to_string(), None).await.into_result().unwrap();
+        assert!(!copied.is_mapped());
+        assert_eq!(copied.as_ref(), content.as_slice());
+
+        set_readonly(&file_path);
+        let result = ops.read_file(file_path.to_str().unwrap().to_string(), None).await.into_result().unwrap();
+        assert!(result.is_mapped());
+        assert_eq!(result.as_ref(), content.as_slice());
+
+        // Small files are still copied
+        let small_path = temp_dir.path().join("small.dat");
+        std_fs::write(&small_path, b"small").unwrap();
+        let small = ops.read_file(small_path.to_str().unwrap().to_string(), None).await.into_result().unwrap();
+        assert!(!small.is_mapped());
+    }
+
+    #[tokio::test]
+    async fn test_read_file_range() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("range.txt");
+        std_fs::write(&file_path, b"0123456789").unwrap();
+        let path_str = file_path.to_str().unwrap().to_string();
+
+        let ops = RustFileOperations::new();
+        let middle = ops.read_file_range(path_str.clone(), 2.0, 5.0, None).await.into_result().unwrap();
+        assert_eq!(middle.as_ref(), b"23456");
+
+        // Ranges past the end are clamped
+        let tail = ops.read_file_range(path_str.clone(), 8.0, 100.0, None).await.into_result().unwrap();
+        assert_eq!(tail.as_ref(), b"89");
+        let past_end = ops.read_file_range(path_str.clone(), 50.0, 10.0, None).await.into_result().unwrap();
+        assert_eq!(past_end.len(), 0);
+
+        // Mapped ranges need not start on a page boundary
+        ops.set_mmap_threshold(1.0);
+        assert!(!ops.read_file_range(path_str.clone(), 3.0, 4.0, None).await.into_result().unwrap().is_mapped());
+        set_readonly(&file_path);
+        let mapped = ops.read_file_range(path_str.clone(), 3.0, 4.0, None).await.into_result().unwrap();
+        assert!(mapped.is_mapped());
+        assert_eq!(mapped.as_ref(), b"3456");
+
+        let err = ops.read_file_range(path_str, -1.0, 4.0, None).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_read_text_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("sjis.txt");
+        let text = "設定ファイルを読み込みます。見つからない場合は既定値を使います。\n";
+        std_fs::write(&file_path, encoding_rs::SHIFT_JIS.encode(text).0).unwrap();
+        let path_str = file_path.to_str().unwrap().to_string();
+        let ops = RustFileOperations::new();
+
+        let options = |encoding: Option<&str>, auto_guess| Some(ReadTextFileOptions { encoding: encoding.map(str::to_string), auto_guess: Some(auto_guess) });
+        let guessed = ops.read_text_file(path_str.clone(), options(None, true), None).await.into_result().unwrap();
+        assert_eq!((guessed.value.as_str(), guessed.encoding.as_str()), (text, "shiftjis"));
+        let explicit = ops.read_text_file(path_str.clone(), options(Some("shiftjis"), false), None).await.into_result().unwrap();
+        assert_eq!(explicit.value, text);
+
+        // Without guessing the file is read as UTF-8
+        let plain = ops.read_text_file(path_str.clone(), None, None).await.into_result().unwrap();
+        assert_eq!(plain.encoding, "utf8");
+        assert!(plain.value.contains('\u{FFFD}'));
+
+        // A BOM overrides the requested encoding
+        let mut utf16 = vec![0xFE, 0xFF];
+        utf16.extend("hello".encode_utf16().flat_map(u16::to_be_bytes));
+        std_fs::write(&file_path, utf16).unwrap();
+        let bom = ops.read_text_file(path_str.clone(), options(Some("windows1252"), false), None).await.into_result().unwrap();
+        assert_eq!((bom.value.as_str(), bom.encoding.as_str()), ("hello", "utf16be"));
+
+        let err = ops.read_text_file(path_str, options(Some("klingon"), false), None).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_read_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("cached.txt");
+        let path_str = file_path.to_str().unwrap().to_string();
+        std_fs::write(&file_path, b"cached").unwrap();
+
+        let ops = RustFileOperations::new();
+        assert_eq!(ops.read_file(path_str.clone(), None).await.into_result().unwrap().as_ref(), b"cached");
+        assert!(ops.cache.size_bytes() > 0);
+        assert_eq!(ops.read_file(path_str.clone(), None).await.into_result().unwrap().as_ref(), b"cached");
+
+        // Writes through the API and changes on disk are both seen
+        ops.write_file(path_str.clone(), Buffer::from(b"rewritten".to_vec()), None).await.into_result().unwrap();
+        assert_eq!(ops.read_file(path_str.clone(), None).await.into_result().unwrap().as_ref(), b"rewritten");
+        std_fs::write(&file_path, b"external change").unwrap();
+        assert_eq!(ops.read_file(path_str.clone(), None).await.into_result().unwrap().as_ref(), b"external change");
+
+        // Cached stats stay until invalidated
+        ops.configure_cache(Some(CacheOptions { cache_stats: Some(true), ..Default::default() }));
+        assert_eq!(ops.stat(path_str.clone()).await.into_result().unwrap().size, 15.0);
+        std_fs::write(&file_path, b"short").unwrap();
+        assert_eq!(ops.stat(path_str.clone()).await.into_result().unwrap().size, 15.0);
+        ops.invalidate_cache(temp_dir.path().to_str().unwrap().to_string());
+        assert_eq!(ops.stat(path_str.clone()).await.into_result().unwrap().size, 5.0);
+
+        ops.configure_cache(Some(CacheOptions { enabled: Some(false), ..Default::default() }));
+        ops.read_file(path_str, None).await.into_result().unwrap();
+        assert_eq!(ops.cache.size_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_file_handles() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("handles.txt");
+        let path_str = file_path.to_str().unwrap().to_string();
+        let ops = RustFileOperations::new();
+
+        let fd = ops.open(path_str.clone(), Some(OpenFileOptions { create: Some(true), unlock: None })).await.into_result().unwrap();
+        assert_eq!(ops.write(fd, 0.0, Buffer::from(b"Hello".to_vec())).await.into_result().unwrap(), 5);
+        assert_eq!(ops.write(fd, 5.0, Buffer::from(b", handles!".to_vec())).await.into_result().unwrap(), 10);
+        ops.ftruncate(fd, 12.0).await.into_result().unwrap();
+        ops.close(fd).await.into_result().unwrap();
+        assert_eq!(std_fs::read(&file_path).unwrap(), b"Hello, handl");
+
+        let fd = ops.open(path_str, None).await.into_result().unwrap();
+        let head = ops.read(fd, 0.0, 5, None).await.into_result().unwrap();
+        assert_eq!(head.as_ref(), b"Hello");
+        let tail = ops.read(fd, 7.0, 100, None).await.into_result().unwrap();
+        assert_eq!(tail.as_ref(), b"handl");
+        // Huge lengths are clamped to the file instead of allocated up front
+        assert_eq!(ops.read(fd, 10.0, u32::MAX, None).await.into_result().unwrap().as_ref(), b"dl");
+        assert!(ops.read(fd, 50.0, u32::MAX, None).await.into_result().unwrap().is_empty());
+
+        // Read-only handles reject writes
+        assert!(ops.write(fd, 0.0, Buffer::from(b"x".to_vec())).await.into_result().is_err());
+        ops.close(fd).await.into_result().unwrap();
+
+        // Closed handles are gone
+        let err = ops.read(fd, 0.0, 1, None).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidArgument);
+        assert_eq!(err.path(), None);
+        assert!(ops.close(fd).await.into_result().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_dir_with_types() {
+        let temp_dir = TempDir::new().unwrap();
+        std_fs::write(temp_dir.path().join("file.txt"), b"12345").unwrap();
+        std_fs::create_dir(temp_dir.path().join("folder")).unwrap();
+
+        let ops = RustFileOperations::new();
+        let mut entries = ops.read_dir_with_types(temp_dir.path().to_str().unwrap().to_string(), None).await.into_result().unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "file.txt");
+        assert_eq!(entries[0].file_type, scan::FILE_TYPE_FILE);
+        assert_eq!(entries[0].size, 5.0);
+        assert!(entries[0].modified > 0.0);
+        assert_eq!(entries[1].name, "folder");
+        assert_eq!(entries[1].file_type, scan::FILE_TYPE_DIRECTORY);
+        assert!(!entries[1].is_symlink);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_read_dir_with_types_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        std_fs::create_dir(temp_dir.path().join("folder")).unwrap();
+        std::os::unix::fs::symlink(temp_dir.path().join("folder"), temp_dir.path().join("dir_link")).unwrap();
+        std::os::unix::fs::symlink(temp_dir.path().join("missing"), temp_dir.path().join("broken_link")).unwrap();
+
+        let ops = RustFileOperations::new();
+        let entries = ops.read_dir_with_types(temp_dir.path().to_str().unwrap().to_string(), None).await.into_result().unwrap();
+        let find = |name: &str| entries.iter().find(|e| e.name == name).unwrap();
+
+        assert_eq!(find("dir_link").file_type, scan::FILE_TYPE_DIRECTORY | scan::FILE_TYPE_SYMBOLIC_LINK);
+        assert!(find("dir_link").is_symlink);
+        assert_eq!(find("broken_link").file_type, scan::FILE_TYPE_UNKNOWN | scan::FILE_TYPE_SYMBOLIC_LINK);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_read_dir_keeps_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let name = std::ffi::OsStr::from_bytes(b"latin1-\xe9.txt");
+        std_fs::write(temp_dir.path().join(name), b"x").unwrap();
+
+        let ops = RustFileOperations::new();
+        let dir = temp_dir.path().to_str().unwrap().to_string();
+        let entries = ops.read_dir(dir.clone(), None).await.into_result().unwrap();
+        // The raw bytes address the file; a lossy string would not exist on disk
+        match entries.as_slice() {
+            [Either::B(raw)] => assert_eq!(raw.as_ref(), name.as_bytes()),
+            _ => panic!("expected the raw name"),
+        }
+
+        let entries = ops.read_dir_with_types(dir.clone(), None).await.into_result().unwrap();
+        assert_eq!(entries[0].name, "latin1-\u{fffd}.txt");
+        let raw = entries[0].raw_name.as_ref().unwrap();
+        assert!(std_fs::metadata(temp_dir.path().join(std::ffi::OsStr::from_bytes(raw))).is_ok());
+
+        let entries = ops.scan_tree(dir, None, None).await.into_result().unwrap();
+        assert_eq!(entries[0].raw_path.as_ref().unwrap().as_ref(), name.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_scan_tree_respects_gitignore() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std_fs::create_dir(root.join(".git")).unwrap();
+        std_fs::write(root.join(".gitignore"), b"build/\n").unwrap();
+        std_fs::create_dir_all(root.join("src/nested")).unwrap();
+        std_fs::write(root.join("src/main.rs"), b"fn main() {}").unwrap();
+        std_fs::write(root.join("src/nested/lib.rs"), b"").unwrap();
+        std_fs::create_dir(root.join("build")).unwrap();
+        std_fs::write(root.join("build/out.o"), b"").unwrap();
+
+        let ops = RustFileOperations::new();
+        let entries = ops.scan_tree(root.to_str().unwrap().to_string(), None, None).await.into_result().unwrap();
+        let mut paths: Vec<String> = entries.iter().map(|e| e.path.replace('\\', "/")).collect();
+        paths.sort();
+
+        assert_eq!(paths, vec!["src", "src/main.rs", "src/nested", "src/nested/lib.rs"]);
+
+        let unfiltered = ops.scan_tree(
+            root.to_str().unwrap().to_string(),
+            Some(ScanOptions { respect_gitignore: Some(false), ..Default::default() })
+        , None).await.into_result().unwrap();
+        assert!(unfiltered.iter().any(|e| e.path.ends_with("out.o")));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_operations() {
+        use crate::cancellation::CancellationToken;
+
+        let temp_dir = TempDir::new().unwrap();
+        std_fs::write(temp_dir.path().join("file.txt"), b"data").unwrap();
+        let root = temp_dir.path().to_str().unwrap().to_string();
+        let ops = RustFileOperations::new();
+
+        let token = CancellationToken::new();
+        let signal = CancelSignal::from(&token);
+        assert_eq!(ops.scan_tree(root.clone(), None, Some(signal.clone())).await.into_result().unwrap().len(), 1);
+
+        token.cancel();
+        let err = ops.scan_tree(root.clone(), None, Some(signal.clone())).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::Cancelled);
+        let file = temp_dir.path().join("file.txt").to_str().unwrap().to_string();
+        let err = ops.read_file(file, Some(signal)).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std_fs::write(root.join("file.txt"), b"x").unwrap();
+        std_fs::create_dir_all(root.join("folder/nested")).unwrap();
+        std_fs::write(root.join("folder/nested/inner.txt"), b"x").unwrap();
+        let ops = RustFileOperations::new();
+
+        ops.delete(root.join("file.txt").to_str().unwrap().to_string(), None).await.into_result().unwrap();
+        assert!(!root.join("file.txt").exists());
+
+        let folder = root.join("folder").to_str().unwrap().to_string();
+        assert!(ops.delete(folder.clone(), None).await.into_result().is_err());
+        ops.delete(folder, Some(DeleteOptions { recursive: Some(true), use_trash: None })).await.into_result().unwrap();
+        assert!(!root.join("folder").exists());
+
+        let err = ops.delete(root.join("missing").to_str().unwrap().to_string(), None).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::FileNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_rename() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std_fs::write(root.join("a.txt"), b"A").unwrap();
+        std_fs::write(root.join("b.txt"), b"B").unwrap();
+        let path = |name: &str| root.join(name).to_str().unwrap().to_string();
+        let ops = RustFileOperations::new();
+
+        let err = ops.rename(path("a.txt"), path("b.txt"), None).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::FileExists);
+        assert_eq!(std_fs::read(root.join("b.txt")).unwrap(), b"B");
+
+        ops.rename(path("a.txt"), path("b.txt"), Some(FileOverwriteOptions { overwrite: Some(true) })).await.into_result().unwrap();
+        assert!(!root.join("a.txt").exists());
+        assert_eq!(std_fs::read(root.join("b.txt")).unwrap(), b"A");
+
+        std_fs::create_dir(root.join("dir")).unwrap();
+        assert!(ops.rename(path("dir"), path("dir/sub"), None).await.into_result().is_err());
+        ops.rename(path("dir"), path("moved"), None).await.into_result().unwrap();
+        assert!(root.join("moved").is_dir());
+
+        // Folders and files replace each other, and non-empty folders are replaced whole
+        std_fs::write(root.join("moved/inner.txt"), b"inner").unwrap();
+        ops.rename(path("b.txt"), path("moved"), Some(FileOverwriteOptions { overwrite: Some(true) })).await.into_result().unwrap();
+        assert_eq!(std_fs::read(root.join("moved")).unwrap(), b"A");
+        std_fs::create_dir_all(root.join("tree/sub")).unwrap();
+        ops.rename(path("tree"), path("moved"), Some(FileOverwriteOptions { overwrite: Some(true) })).await.into_result().unwrap();
+        assert!(root.join("moved/sub").is_dir());
+        // Only the renamed entries are left, no staged or set-aside siblings
+        assert_eq!(std_fs::read_dir(root).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_rename_symlink_onto_its_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std_fs::write(root.join("target.txt"), b"data").unwrap();
+        std::os::unix::fs::symlink(root.join("target.txt"), root.join("link")).unwrap();
+        let path = |name: &str| root.join(name).to_str().unwrap().to_string();
+        let ops = RustFileOperations::new();
+
+        // The link and its target are different entries, so the target is in the way
+        let err = ops.rename(path("link"), path("target.txt"), None).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::FileExists);
+        assert_eq!(err.path(), Some(root.join("target.txt").as_path()));
+        assert_eq!(std_fs::read(root.join("target.txt")).unwrap(), b"data");
+        assert!(std_fs::symlink_metadata(root.join("link")).unwrap().file_type().is_symlink());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failed_overwrite_keeps_target() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std_fs::create_dir_all(root.join("target")).unwrap();
+        std_fs::write(root.join("target/keep.txt"), b"keep me").unwrap();
+        std_fs::create_dir(root.join("src")).unwrap();
+        std_fs::write(root.join("src/a.txt"), b"a").unwrap();
+        // Sockets cannot be opened for reading, so copying the folder fails halfway
+        let _socket = std::os::unix::net::UnixListener::bind(root.join("src/socket")).unwrap();
+        let path = |name: &str| root.join(name).to_str().unwrap().to_string();
+        let ops = RustFileOperations::new();
+
+        let overwrite = Some(FileOverwriteOptions { overwrite: Some(true) });
+        assert!(ops.copy(path("src"), path("target"), overwrite).await.into_result().is_err());
+        assert_eq!(std_fs::read(root.join("target/keep.txt")).unwrap(), b"keep me");
+        assert_eq!(std_fs::read_dir(root).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_copy_tree() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std_fs::create_dir_all(root.join("src/nested")).unwrap();
+        std_fs::write(root.join("src/top.txt"), b"top").unwrap();
+        std_fs::write(root.join("src/nested/deep.txt"), b"deep").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("top.txt", root.join("src/link.txt")).unwrap();
+        let path = |name: &str| root.join(name).to_str().unwrap().to_string();
+        let ops = RustFileOperations::new();
+
+        ops.copy(path("src"), path("dst"), None).await.into_result().unwrap();
+        assert_eq!(std_fs::read(root.join("dst/top.txt")).unwrap(), b"top");
+        assert_eq!(std_fs::read(root.join("dst/nested/deep.txt")).unwrap(), b"deep");
+        assert_eq!(std_fs::read(root.join("src/top.txt")).unwrap(), b"top");
+        #[cfg(unix)]
+        assert_eq!(std_fs::read_link(root.join("dst/link.txt")).unwrap(), PathBuf::from("top.txt"));
+
+        assert!(ops.copy(path("src/top.txt"), path("dst/top.txt"), None).await.into_result().is_err());
+        let overwrite = Some(FileOverwriteOptions { overwrite: Some(true) });
+        let err = ops.copy(path("src/top.txt"), path("src/top.txt"), overwrite).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidArgument);
+        ops.copy(path("src/nested/deep.txt"), path("dst/top.txt"), Some(FileOverwriteOptions { overwrite: Some(true) })).await.into_result().unwrap();
+        assert_eq!(std_fs::read(root.join("dst/top.txt")).unwrap(), b"deep");
+
+        assert!(ops.copy(path("src"), path("src/nested/copy"), None).await.into_result().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mkdirp() {
+        let temp_dir = TempDir::new().unwrap();
+        let deep = temp_dir.path().join("a/b/c");
+        let ops = RustFileOperations::new();
+
+        ops.mkdirp(deep.to_str().unwrap().to_string()).await.into_result().unwrap();
+        assert!(deep.is_dir());
+        ops.mkdirp(deep.to_str().unwrap().to_string()).await.into_result().unwrap();
+
+        std_fs::write(temp_dir.path().join("file"), b"x").unwrap();
+        let err = ops.mkdirp(temp_dir.path().join("file/sub").to_str().unwrap().to_string()).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::NotADirectory);
+    }
+
     #[tokio::test]
     async fn test_unicode_filenames() {
         let temp_dir = TempDir::new().unwrap();
@@ -267,7 +1282,7 @@ mod tests {
         }
         
         let ops = RustFileOperations::new();
-        let entries = ops.read_dir(temp_dir.path().to_str().unwrap().to_string()).await.unwrap();
+        let entries = utf8_names(ops.read_dir(temp_dir.path().to_str().unwrap().to_string(), None).await.into_result().unwrap());
         
         assert_eq!(entries.len(), unicode_names.len());
         for name in &unicode_names {
@@ -282,11 +1297,11 @@ mod tests {
         std_fs::write(&file_path, b"").unwrap();
         
         let ops = RustFileOperations::new();
-        let result = ops.read_file(file_path.to_str().unwrap().to_string()).await.unwrap();
+        let result = ops.read_file(file_path.to_str().unwrap().to_string(), None).await.into_result().unwrap();
         
         assert_eq!(result.len(), 0);
         
-        let stats = ops.stat(file_path.to_str().unwrap().to_string()).await.unwrap();
+        let stats = ops.stat(file_path.to_str().unwrap().to_string()).await.into_result().unwrap();
         assert_eq!(stats.size, 0.0);
     }
     
@@ -298,17 +1313,117 @@ mod tests {
         // Write initial content
         let ops = RustFileOperations::new();
         let initial_content = Buffer::from(b"Initial content".to_vec());
-        ops.write_file(file_path.to_str().unwrap().to_string(), initial_content).await.unwrap();
+        ops.write_file(file_path.to_str().unwrap().to_string(), initial_content, None).await.into_result().unwrap();
         
         // Overwrite with new content
         let new_content = Buffer::from(b"New content".to_vec());
-        ops.write_file(file_path.to_str().unwrap().to_string(), new_content).await.unwrap();
+        ops.write_file(file_path.to_str().unwrap().to_string(), new_content, None).await.into_result().unwrap();
         
         // Verify new content
         let result = std_fs::read(&file_path).unwrap();
         assert_eq!(result, b"New content");
     }
     
+    #[tokio::test]
+    async fn test_atomic_write_replaces_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("atomic.txt");
+        std_fs::write(&file_path, b"Old content").unwrap();
+
+        let ops = RustFileOperations::new();
+        let options = WriteFileOptions { atomic: Some(true), ..Default::default() };
+        ops.write_file(
+            file_path.to_str().unwrap().to_string(),
+            Buffer::from(b"New atomic content".to_vec()),
+            Some(options)
+        ).await.into_result().unwrap();
+
+        assert_eq!(std_fs::read(&file_path).unwrap(), b"New atomic content");
+
+        // No temp files should be left behind
+        let entries = std_fs::read_dir(temp_dir.path()).unwrap().count();
+        assert_eq!(entries, 1);
+    }
+
+    #[tokio::test]
+    async fn test_write_create_and_overwrite_flags() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("flags.txt");
+        let path_str = file_path.to_str().unwrap().to_string();
+        let ops = RustFileOperations::new();
+
+        let no_create = WriteFileOptions { create: Some(false), ..Default::default() };
+        let result = ops.write_file(path_str.clone(), Buffer::from(b"x".to_vec()), Some(no_create)).await.into_result();
+        assert_eq!(result.err().unwrap().code(), ErrorCode::FileNotFound);
+        assert!(!file_path.exists());
+
+        std_fs::write(&file_path, b"Original").unwrap();
+        let no_overwrite = WriteFileOptions { overwrite: Some(false), atomic: Some(true), ..Default::default() };
+        let result = ops.write_file(path_str, Buffer::from(b"x".to_vec()), Some(no_overwrite)).await.into_result();
+        assert_eq!(result.err().unwrap().code(), ErrorCode::FileExists);
+        assert_eq!(std_fs::read(&file_path).unwrap(), b"Original");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_atomic_write_preserves_permissions_and_unlocks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("readonly.txt");
+        std_fs::write(&file_path, b"Locked").unwrap();
+        std_fs::set_permissions(&file_path, std_fs::Permissions::from_mode(0o444)).unwrap();
+
+        let ops = RustFileOperations::new();
+        let options = WriteFileOptions { atomic: Some(true), unlock: Some(true), ..Default::default() };
+        ops.write_file(
+            file_path.to_str().unwrap().to_string(),
+            Buffer::from(b"Unlocked".to_vec()),
+            Some(options)
+        ).await.into_result().unwrap();
+
+        assert_eq!(std_fs::read(&file_path).unwrap(), b"Unlocked");
+        let mode = std_fs::metadata(&file_path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o644);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_atomic_write_through_symlink() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target.txt");
+        let link = temp_dir.path().join("link.txt");
+        std_fs::write(&target, b"Before").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let ops = RustFileOperations::new();
+        let options = WriteFileOptions { atomic: Some(true), ..Default::default() };
+        ops.write_file(link.to_str().unwrap().to_string(), Buffer::from(b"After".to_vec()), Some(options)).await.into_result().unwrap();
+
+        assert!(std_fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(std_fs::read(&target).unwrap(), b"After");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_write_through_dangling_symlink() {
+        let temp_dir = TempDir::new().unwrap();
+        std_fs::create_dir(temp_dir.path().join("sub")).unwrap();
+        let link = temp_dir.path().join("link.txt");
+        // Relative targets resolve against the folder holding the link
+        std::os::unix::fs::symlink("sub/target.txt", &link).unwrap();
+
+        let ops = RustFileOperations::new();
+        let link_path = link.to_str().unwrap().to_string();
+        let options = WriteFileOptions { create: Some(false), ..Default::default() };
+        let err = ops.write_file(link_path.clone(), Buffer::from(b"x".to_vec()), Some(options)).await.into_result().err().unwrap();
+        assert_eq!(err.code(), ErrorCode::FileNotFound);
+
+        ops.write_file(link_path, Buffer::from(b"Created".to_vec()), None).await.into_result().unwrap();
+        assert!(std_fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(std_fs::read(temp_dir.path().join("sub/target.txt")).unwrap(), b"Created");
+    }
+
     #[cfg(unix)]
     #[tokio::test]
     async fn test_permissions() {
@@ -324,9 +1439,71 @@ mod tests {
         std_fs::set_permissions(&file_path, perms).unwrap();
         
         let ops = RustFileOperations::new();
-        let stats = ops.stat(file_path.to_str().unwrap().to_string()).await.unwrap();
+        let stats = ops.stat(file_path.to_str().unwrap().to_string()).await.into_result().unwrap();
         
         assert!(stats.is_file);
-        // Further permission checks would require platform-specific code
+        assert_eq!(stats.mode & 0o777, 0o644);
+        assert!(!stats.readonly);
+
+        perms = std_fs::metadata(&file_path).unwrap().permissions();
+        perms.set_mode(0o444);
+        std_fs::set_permissions(&file_path, perms).unwrap();
+
+        let stats = ops.stat(file_path.to_str().unwrap().to_string()).await.into_result().unwrap();
+        assert!(stats.readonly);
+    }
+
+    #[tokio::test]
+    async fn test_stat_extended_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("extended.txt");
+        std_fs::write(&file_path, b"Extended").unwrap();
+
+        let ops = RustFileOperations::new();
+        let stats = ops.stat(file_path.to_str().unwrap().to_string()).await.into_result().unwrap();
+
+        assert_eq!(stats.file_type, scan::FILE_TYPE_FILE);
+        assert!(!stats.is_symlink);
+        assert!(stats.ctime > 0.0);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let meta = std_fs::metadata(&file_path).unwrap();
+            assert_eq!(stats.ino, meta.ino() as f64);
+            assert_eq!(stats.dev, meta.dev() as f64);
+            assert_eq!(stats.uid, meta.uid());
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stat_lstat_and_realpath_on_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let target = temp_dir.path().join("target.txt");
+        let link = temp_dir.path().join("link.txt");
+        std_fs::write(&target, b"Target content").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let link_str = link.to_str().unwrap().to_string();
+
+        let ops = RustFileOperations::new();
+        let stats = ops.stat(link_str.clone()).await.into_result().unwrap();
+        assert!(stats.is_file);
+        assert!(stats.is_symlink);
+        assert_eq!(stats.size, 14.0);
+        assert_eq!(stats.file_type, scan::FILE_TYPE_FILE | scan::FILE_TYPE_SYMBOLIC_LINK);
+
+        let lstats = ops.lstat(link_str.clone()).await.into_result().unwrap();
+        assert!(lstats.is_symlink);
+        assert!(!lstats.is_file);
+
+        let real = ops.realpath(link_str).await.into_result().unwrap();
+        assert_eq!(PathBuf::from(real), std_fs::canonicalize(&target).unwrap());
+
+        // Dangling links can still be stat-ed
+        std_fs::remove_file(&target).unwrap();
+        let dangling = ops.stat(link.to_str().unwrap().to_string()).await.into_result().unwrap();
+        assert!(dangling.is_symlink);
+        assert!(ops.realpath(link.to_str().unwrap().to_string()).await.into_result().is_err());
     }
 }
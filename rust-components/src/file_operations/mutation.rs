@@ -0,0 +1,249 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use super::DEFAULT_ATOMIC_POSTFIX;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Delete a file, symlink or folder. Non-empty folders need `recursive`.
+pub fn delete(path: &Path, recursive: bool, use_trash: bool) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+
+    if use_trash {
+        return trash::delete(path).map_err(|e| io::Error::other(format!("Failed to move to trash: {}", e)));
+    }
+
+    if meta.is_dir() {
+        if recursive {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_dir(path)
+        }
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Rename `from` to `to`, falling back to copy and delete across devices.
+/// An overwritten target is only removed once `from` has taken its place.
+pub fn rename(from: &Path, to: &Path, overwrite: bool) -> io::Result<()> {
+    let source = fs::symlink_metadata(from)?;
+    ensure_not_into_itself(from, to, &source)?;
+
+    // A case-only rename on a case-insensitive filesystem finds the source as its own target
+    if is_same_entry(from, to) {
+        return fs::rename(from, to);
+    }
+    check_target(to, overwrite)?;
+
+    match replace(from, to) {
+        Err(e) if is_cross_device(&e) => {
+            copy_staged(from, to)?;
+            delete(from, true, false)
+        }
+        result => result,
+    }
+}
+
+/// Copy a file or folder tree. Symlinks are copied as links, files are cloned
+/// where the filesystem supports it. The copy is made next to `to` and only
+/// replaces an overwritten target once it is complete.
+pub fn copy(from: &Path, to: &Path, overwrite: bool) -> io::Result<()> {
+    let source = fs::symlink_metadata(from)?;
+
+    if is_same_entry(from, to) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Cannot copy {} onto itself", from.display()),
+        ));
+    }
+    ensure_not_into_itself(from, to, &source)?;
+    check_target(to, overwrite)?;
+
+    copy_staged(from, to)
+}
+
+pub fn mkdirp(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)
+}
+
+fn check_target(to: &Path, overwrite: bool) -> io::Result<()> {
+    match fs::symlink_metadata(to) {
+        Ok(_) if !overwrite => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        )),
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Copy `from` to a temporary sibling of `to` and move it into place
+fn copy_staged(from: &Path, to: &Path) -> io::Result<()> {
+    let staged = sibling(to)?;
+    let result = copy_entry(from, &staged).and_then(|()| replace(&staged, to));
+    if result.is_err() && fs::symlink_metadata(&staged).is_ok() {
+        let _ = delete(&staged, true, false);
+    }
+    result
+}
+
+/// Move `from` over `to`. Files and links replace the target atomically.
+/// Folders cannot be renamed over, or onto, a non-folder or non-empty folder,
+/// so such a target is moved aside first and deleted once `from` is in place.
+fn replace(from: &Path, to: &Path) -> io::Result<()> {
+    let target = match fs::symlink_metadata(to) {
+        Ok(target) => target,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return fs::rename(from, to),
+        Err(e) => return Err(e),
+    };
+    if !target.is_dir() && !fs::symlink_metadata(from)?.is_dir() {
+        return fs::rename(from, to);
+    }
+
+    let aside = sibling(to)?;
+    fs::rename(to, &aside)?;
+    if let Err(e) = fs::rename(from, to) {
+        let _ = fs::rename(&aside, to);
+        return Err(e);
+    }
+    // The new data is in place; a leftover old target is only wasted space
+    if let Err(e) = delete(&aside, true, false) {
+        tracing::warn!("Failed to remove replaced {}: {}", aside.display(), e);
+    }
+    Ok(())
+}
+
+/// An unused path next to `path`, named like the temp files of atomic writes
+fn sibling(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no file name", path.display())))?;
+    let mut temp_name = name.to_os_string();
+    temp_name.push(format!(".{}{}", &uuid::Uuid::new_v4().simple().to_string()[..8], DEFAULT_ATOMIC_POSTFIX));
+    Ok(path.with_file_name(temp_name))
+}
+
+fn ensure_not_into_itself(from: &Path, to: &Path, source: &fs::Metadata) -> io::Result<()> {
+    if !source.is_dir() {
+        return Ok(());
+    }
+
+    let from = fs::canonicalize(from)?;
+    let to_parent = to.parent().and_then(|p| fs::canonicalize(p).ok());
+    match to_parent {
+        Some(parent) if parent.starts_with(&from) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Cannot move or copy {} into itself", from.display()),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `a` and `b` name the same directory entry, as two spellings of
+/// one name on a case-insensitive filesystem do. Links are not followed, so
+/// a symlink and its target, or two hard links, are different entries.
+fn is_same_entry(a: &Path, b: &Path) -> bool {
+    let (Ok(a_meta), Ok(b_meta)) = (fs::symlink_metadata(a), fs::symlink_metadata(b)) else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if (a_meta.dev(), a_meta.ino()) != (b_meta.dev(), b_meta.ino()) {
+            return false;
+        }
+    }
+    #[cfg(not(unix))]
+    if a_meta.file_type() != b_meta.file_type() {
+        return false;
+    }
+
+    let same_name = match (a.file_name(), b.file_name()) {
+        (Some(a), Some(b)) => a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase(),
+        _ => false,
+    };
+    let parent = |path: &Path| path.parent().and_then(|parent| fs::canonicalize(parent).ok());
+    same_name && parent(a).is_some() && parent(a) == parent(b)
+}
+
+fn is_cross_device(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        err.raw_os_error() == Some(libc::EXDEV)
+    }
+    #[cfg(windows)]
+    {
+        // ERROR_NOT_SAME_DEVICE
+        err.raw_os_error() == Some(17)
+    }
+}
+
+fn copy_entry(from: &Path, to: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(from)?;
+
+    if meta.file_type().is_symlink() {
+        return copy_symlink(from, to);
+    }
+
+    if meta.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_entry(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        return fs::set_permissions(to, meta.permissions());
+    }
+
+    copy_file(from, to)
+}
+
+/// Clone the file's extents when the filesystem allows it (btrfs, xfs, ...),
+/// otherwise let `std::fs::copy` use `copy_file_range`/`fclonefile`
+fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        if try_reflink(from, to)? {
+            return Ok(());
+        }
+    }
+
+    fs::copy(from, to).map(|_| ())
+}
+
+#[cfg(target_os = "linux")]
+fn try_reflink(from: &Path, to: &Path) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let source = fs::File::open(from)?;
+    let permissions = source.metadata()?.permissions();
+    let target = fs::OpenOptions::new().write(true).create_new(true).open(to)?;
+
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } == 0 {
+        target.set_permissions(permissions)?;
+        return Ok(true);
+    }
+
+    // Not supported here; remove the empty target so the regular copy can create it
+    drop(target);
+    fs::remove_file(to)?;
+    Ok(false)
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(windows)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    let target = fs::read_link(from)?;
+    if fs::metadata(from).map(|m| m.is_dir()).unwrap_or(false) {
+        std::os::windows::fs::symlink_dir(target, to)
+    } else {
+        std::os::windows::fs::symlink_file(target, to)
+    }
+}
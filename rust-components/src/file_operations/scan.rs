@@ -0,0 +1,163 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use crate::cancellation::CancelSignal;
+use ignore::{WalkBuilder, WalkState};
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+use rayon::prelude::*;
+use super::stat::mtime_ms;
+use std::ffi::OsStr;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::Path;
+use std::sync::mpsc;
+
+/// VS Code `FileType` bits used in `file_type`
+pub const FILE_TYPE_UNKNOWN: u32 = 0;
+pub const FILE_TYPE_FILE: u32 = 1;
+pub const FILE_TYPE_DIRECTORY: u32 = 2;
+pub const FILE_TYPE_SYMBOLIC_LINK: u32 = 64;
+
+#[napi(object)]
+pub struct DirEntryInfo {
+    pub name: String,
+    /// The name's bytes when it is not valid UTF-8. `name` then holds a lossy
+    /// copy that does not exist on disk, so use these to address the entry.
+    pub raw_name: Option<Buffer>,
+    /// VS Code `FileType` bitmask; symlinks carry the type of their target plus `SymbolicLink`
+    pub file_type: u32,
+    pub is_symlink: bool,
+    pub size: f64,
+    pub modified: f64,
+}
+
+#[napi(object)]
+pub struct ScanEntry {
+    /// Path relative to the scanned root
+    pub path: String,
+    /// The path's bytes when it is not valid UTF-8, as with `DirEntryInfo::raw_name`
+    pub raw_path: Option<Buffer>,
+    pub file_type: u32,
+    pub is_symlink: bool,
+    pub size: f64,
+    pub modified: f64,
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct ScanOptions {
+    pub include_hidden: Option<bool>,
+    /// Honour .gitignore, .ignore and global git excludes (default: true)
+    pub respect_gitignore: Option<bool>,
+    pub max_depth: Option<i32>,
+    /// Descend into symlinked folders (default: false). Links back into a
+    /// folder that is already being walked are skipped.
+    pub follow_symlinks: Option<bool>,
+}
+
+/// The bytes of `name` when it cannot be passed to JS as a string unchanged
+pub fn raw_name(name: &OsStr) -> Option<Buffer> {
+    match name.to_str() {
+        Some(_) => None,
+        None => Some(name.as_encoded_bytes().to_vec().into()),
+    }
+}
+
+/// Type, size and mtime of an entry, resolving symlinks to their target.
+/// Broken links are reported as `Unknown | SymbolicLink` with the link's own size.
+fn describe(path: &Path, lstat: &Metadata) -> (u32, bool, f64, f64) {
+    let is_symlink = lstat.file_type().is_symlink();
+    let target = if is_symlink { fs::metadata(path).ok() } else { None };
+    let meta = target.as_ref().unwrap_or(lstat);
+
+    let mut file_type = if target.is_none() && is_symlink {
+        FILE_TYPE_UNKNOWN
+    } else if meta.is_file() {
+        FILE_TYPE_FILE
+    } else if meta.is_dir() {
+        FILE_TYPE_DIRECTORY
+    } else {
+        FILE_TYPE_UNKNOWN
+    };
+    if is_symlink {
+        file_type |= FILE_TYPE_SYMBOLIC_LINK;
+    }
+
+    (file_type, is_symlink, meta.len() as f64, mtime_ms(meta))
+}
+
+/// List a directory with types, sizes and mtimes, stat-ing entries in parallel
+pub fn read_dir_with_types(dir: &Path) -> io::Result<Vec<DirEntryInfo>> {
+    let entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+
+    Ok(entries
+        .par_iter()
+        .filter_map(|entry| {
+            // Entries deleted between readdir and stat are skipped
+            let lstat = entry.metadata().ok()?;
+            let (file_type, is_symlink, size, modified) = describe(&entry.path(), &lstat);
+            let name = entry.file_name();
+            Some(DirEntryInfo {
+                name: name.to_string_lossy().to_string(),
+                raw_name: raw_name(&name),
+                file_type,
+                is_symlink,
+                size,
+                modified,
+            })
+        })
+        .collect())
+}
+
+/// Walk `root` with the parallel `ignore` walker and describe every entry below it.
+/// The walk stops early once `signal` is cancelled.
+pub fn scan_tree(root: &Path, opts: &ScanOptions, signal: &CancelSignal) -> Vec<ScanEntry> {
+    let respect_gitignore = opts.respect_gitignore.unwrap_or(true);
+
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(!opts.include_hidden.unwrap_or(false))
+        .ignore(respect_gitignore)
+        .git_ignore(respect_gitignore)
+        .git_global(respect_gitignore)
+        .git_exclude(respect_gitignore)
+        .parents(respect_gitignore)
+        .max_depth(opts.max_depth.map(|d| d as usize))
+        .follow_links(opts.follow_symlinks.unwrap_or(false))
+        .threads(num_cpus::get());
+
+    let (tx, rx) = mpsc::channel();
+
+    builder.build_parallel().run(|| {
+        let tx = tx.clone();
+
+        Box::new(move |result| {
+            if signal.is_cancelled() {
+                return WalkState::Quit;
+            }
+            if let Ok(entry) = result {
+                if entry.depth() > 0 {
+                    if let Ok(lstat) = entry.path().symlink_metadata() {
+                        let (file_type, is_symlink, size, modified) = describe(entry.path(), &lstat);
+                        let rel = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                        let _ = tx.send(ScanEntry {
+                            path: rel.to_string_lossy().to_string(),
+                            raw_path: raw_name(rel.as_os_str()),
+                            file_type,
+                            is_symlink,
+                            size,
+                            modified,
+                        });
+                    }
+                }
+            }
+            WalkState::Continue
+        })
+    });
+
+    drop(tx);
+    rx.into_iter().collect()
+}
@@ -0,0 +1,161 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use super::scan::{FILE_TYPE_DIRECTORY, FILE_TYPE_FILE, FILE_TYPE_SYMBOLIC_LINK, FILE_TYPE_UNKNOWN};
+use napi_derive::napi;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[napi(object)]
+#[derive(Clone)]
+pub struct FileStats {
+    pub size: f64,
+    pub is_file: bool,
+    pub is_directory: bool,
+    /// Last modification time in ms since the epoch
+    pub modified: f64,
+    /// Last status change time in ms since the epoch (creation time on Windows)
+    pub ctime: f64,
+    /// Creation time in ms since the epoch, where the filesystem records it
+    pub birthtime: Option<f64>,
+    pub is_symlink: bool,
+    /// The owner may not write to the file (VS Code `FilePermission.Locked`)
+    pub readonly: bool,
+    /// VS Code `FileType` bitmask
+    pub file_type: u32,
+    /// Unix mode bits including the file type; 0 on Windows
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Inode and device ids identify a file across hard links and renames; 0 on Windows
+    pub ino: f64,
+    pub dev: f64,
+}
+
+/// Stat `path` following symlinks while still reporting whether `path` itself
+/// is a link, like VS Code's `SymlinkSupport.stat`
+pub fn stat(path: &Path) -> io::Result<FileStats> {
+    let lstat = fs::symlink_metadata(path)?;
+    if !lstat.file_type().is_symlink() {
+        return Ok(to_file_stats(&lstat, false));
+    }
+
+    match fs::metadata(path) {
+        Ok(target) => Ok(to_file_stats(&target, true)),
+        // Dangling links still exist; describe the link itself
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(to_file_stats(&lstat, true)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Stat `path` without following a final symlink
+pub fn lstat(path: &Path) -> io::Result<FileStats> {
+    let lstat = fs::symlink_metadata(path)?;
+    let is_symlink = lstat.file_type().is_symlink();
+    Ok(to_file_stats(&lstat, is_symlink))
+}
+
+/// Resolve all symlinks and relative components of `path`
+pub fn realpath(path: &Path) -> io::Result<PathBuf> {
+    let resolved = fs::canonicalize(path)?;
+    Ok(strip_verbatim_prefix(resolved))
+}
+
+fn to_file_stats(meta: &Metadata, is_symlink: bool) -> FileStats {
+    let mut file_type = if meta.is_file() {
+        FILE_TYPE_FILE
+    } else if meta.is_dir() {
+        FILE_TYPE_DIRECTORY
+    } else {
+        FILE_TYPE_UNKNOWN
+    };
+    if is_symlink {
+        file_type |= FILE_TYPE_SYMBOLIC_LINK;
+    }
+
+    let birthtime = meta.created().ok().map(system_time_ms);
+    let ids = platform_ids(meta);
+
+    FileStats {
+        size: meta.len() as f64,
+        is_file: meta.is_file(),
+        is_directory: meta.is_dir(),
+        modified: mtime_ms(meta),
+        ctime: ids.ctime_ms.or(birthtime).unwrap_or(0.0),
+        birthtime,
+        is_symlink,
+        readonly: ids.readonly,
+        file_type,
+        mode: ids.mode,
+        uid: ids.uid,
+        gid: ids.gid,
+        ino: ids.ino as f64,
+        dev: ids.dev as f64,
+    }
+}
+
+pub fn mtime_ms(meta: &Metadata) -> f64 {
+    meta.modified().map_or(0.0, system_time_ms)
+}
+
+fn system_time_ms(time: SystemTime) -> f64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as f64,
+        Err(e) => -(e.duration().as_millis() as f64),
+    }
+}
+
+struct PlatformIds {
+    ctime_ms: Option<f64>,
+    readonly: bool,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    ino: u64,
+    dev: u64,
+}
+
+#[cfg(unix)]
+fn platform_ids(meta: &Metadata) -> PlatformIds {
+    use std::os::unix::fs::MetadataExt;
+    PlatformIds {
+        ctime_ms: Some(meta.ctime() as f64 * 1000.0 + (meta.ctime_nsec() / 1_000_000) as f64),
+        readonly: meta.mode() & 0o200 == 0,
+        mode: meta.mode(),
+        uid: meta.uid(),
+        gid: meta.gid(),
+        ino: meta.ino(),
+        dev: meta.dev(),
+    }
+}
+
+#[cfg(not(unix))]
+fn platform_ids(meta: &Metadata) -> PlatformIds {
+    PlatformIds {
+        ctime_ms: None,
+        readonly: meta.permissions().readonly(),
+        mode: 0,
+        uid: 0,
+        gid: 0,
+        ino: 0,
+        dev: 0,
+    }
+}
+
+#[cfg(windows)]
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    // canonicalize returns `\\?\C:\...`; editors expect plain drive paths
+    match path.to_str().and_then(|s| s.strip_prefix(r"\\?\")) {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => PathBuf::from(rest),
+        _ => path,
+    }
+}
+
+#[cfg(not(windows))]
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    path
+}
@@ -0,0 +1,408 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use super::{EventCoalescer, FileChangeEvent, FileChangeType, WatchConfig};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use napi::{Error, Result, Status};
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the watcher thread wakes up to check for shutdown and due batches
+const POLL_INTERVAL_MS: i32 = 25;
+
+/// A batch is flushed at the latest after this many debounce periods, even if
+/// events keep arriving
+const MAX_BATCH_DELAY_FACTOR: u32 = 10;
+
+fn watch_mask() -> WatchMask {
+    WatchMask::CREATE
+        | WatchMask::DELETE
+        | WatchMask::MODIFY
+        | WatchMask::ATTRIB
+        | WatchMask::CLOSE_WRITE
+        | WatchMask::MOVED_FROM
+        | WatchMask::MOVED_TO
+        | WatchMask::DELETE_SELF
+        | WatchMask::MOVE_SELF
+        | WatchMask::ONLYDIR
+        | WatchMask::DONT_FOLLOW
+}
+
+struct InotifyWatcher {
+    inotify: Inotify,
+    config: WatchConfig,
+    watches: HashMap<WatchDescriptor, PathBuf>,
+    pending: EventCoalescer,
+}
+
+/// Start a thread that registers watches for the whole tree below
+/// `config.root` and forwards debounced batches to `sink` until `stop` is set.
+/// Walking the tree can take long, so only the root is checked up front;
+/// failures while registering, such as an exhausted watch limit, are reported
+/// through `sink`. The thread sets `stop` itself when it ends on its own, e.g.
+/// after the root is deleted.
+pub fn spawn<F>(config: WatchConfig, stop: Arc<AtomicBool>, sink: F) -> io::Result<()>
+where
+    F: Fn(Result<Vec<FileChangeEvent>>) + Send + 'static,
+{
+    if !std::fs::metadata(&config.root)?.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a directory"));
+    }
+
+    let mut watcher = InotifyWatcher {
+        inotify: Inotify::init()?,
+        config,
+        watches: HashMap::new(),
+        pending: EventCoalescer::default(),
+    };
+
+    std::thread::Builder::new()
+        .name("cmdshiftai-watcher".to_string())
+        .spawn(move || {
+            let root = watcher.config.root.clone();
+            if let Err(e) = watcher.add_tree(&root, false) {
+                sink(Err(Error::new(
+                    Status::GenericFailure,
+                    format!("Failed to watch {}: {}", root.display(), e),
+                )));
+            }
+            // Keep serving the folders registered before a partial failure
+            if !watcher.watches.is_empty() {
+                watcher.run(&stop, &sink);
+            }
+            stop.store(true, Ordering::Relaxed);
+        })?;
+
+    Ok(())
+}
+
+impl InotifyWatcher {
+    fn run<F>(&mut self, stop: &AtomicBool, sink: &F)
+    where
+        F: Fn(Result<Vec<FileChangeEvent>>),
+    {
+        let debounce = Duration::from_millis(self.config.debounce_ms as u64);
+        let max_delay = debounce * MAX_BATCH_DELAY_FACTOR;
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut first_event: Option<Instant> = None;
+        let mut last_event = Instant::now();
+
+        while !stop.load(Ordering::Relaxed) {
+            match self.wait_readable() {
+                Ok(true) => match self.read_batch(&mut buffer) {
+                    Ok(()) => {
+                        last_event = Instant::now();
+                        first_event.get_or_insert(last_event);
+                    }
+                    Err(e) => sink(Err(Error::new(Status::GenericFailure, format!("File watcher error: {}", e)))),
+                },
+                Ok(false) => {}
+                Err(e) => {
+                    sink(Err(Error::new(Status::GenericFailure, format!("File watcher stopped: {}", e))));
+                    return;
+                }
+            }
+
+            if let Some(first) = first_event {
+                if last_event.elapsed() >= debounce || first.elapsed() >= max_delay {
+                    first_event = None;
+                    if !self.pending.is_empty() {
+                        let events = self.pending.drain();
+                        if !events.is_empty() {
+                            sink(Ok(events));
+                        }
+                    }
+                }
+            }
+
+            if self.watches.is_empty() {
+                // The root itself is gone; deliver its deletion without waiting for the debounce
+                let events = self.pending.drain();
+                if !events.is_empty() {
+                    sink(Ok(events));
+                }
+                return;
+            }
+        }
+    }
+
+    fn wait_readable(&self) -> io::Result<bool> {
+        let mut fds = libc::pollfd {
+            fd: self.inotify.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut fds, 1, POLL_INTERVAL_MS) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted { Ok(false) } else { Err(err) }
+            }
+            0 => Ok(false),
+            _ => Ok(true),
+        }
+    }
+
+    fn read_batch(&mut self, buffer: &mut [u8]) -> io::Result<()> {
+        let mut raw = Vec::new();
+        loop {
+            match self.inotify.read_events(buffer) {
+                Ok(events) => {
+                    for event in events {
+                        raw.push((event.wd, event.mask, event.name.map(PathBuf::from)));
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Every event is applied even if an earlier one failed; the first error is reported
+        let mut result = Ok(());
+        for (wd, mask, name) in raw {
+            if let Err(e) = self.handle_event(wd, mask, name) {
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+
+    fn handle_event(&mut self, wd: WatchDescriptor, mask: EventMask, name: Option<PathBuf>) -> io::Result<()> {
+        if mask.contains(EventMask::Q_OVERFLOW) {
+            return self.rescan();
+        }
+
+        if mask.contains(EventMask::IGNORED) {
+            self.watches.remove(&wd);
+            return Ok(());
+        }
+
+        let Some(dir) = self.watches.get(&wd).cloned() else {
+            return Ok(());
+        };
+
+        if mask.intersects(EventMask::DELETE_SELF | EventMask::MOVE_SELF) {
+            // Children report their own removal through their parent's watch;
+            // only the root has no parent to tell us
+            if dir == self.config.root {
+                self.pending.push(dir, FileChangeType::Deleted);
+                let _ = self.inotify.watches().remove(wd.clone());
+                self.watches.remove(&wd);
+            }
+            return Ok(());
+        }
+
+        let Some(name) = name else {
+            return Ok(());
+        };
+        let path = dir.join(name);
+        if self.config.is_excluded(&path) {
+            return Ok(());
+        }
+
+        let is_dir = mask.contains(EventMask::ISDIR);
+        if mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+            self.pending.push(path.clone(), FileChangeType::Added);
+            if is_dir {
+                // Entries created before the new watch was in place would be missed otherwise.
+                // A folder that is already gone again reports its own deletion.
+                match self.add_tree(&path, true) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+        } else if mask.intersects(EventMask::DELETE | EventMask::MOVED_FROM) {
+            self.pending.push(path.clone(), FileChangeType::Deleted);
+            if is_dir {
+                self.forget_tree(&path);
+            }
+        } else if !is_dir && mask.intersects(EventMask::MODIFY | EventMask::ATTRIB | EventMask::CLOSE_WRITE) {
+            self.pending.push(path, FileChangeType::Updated);
+        }
+        Ok(())
+    }
+
+    /// Watch `dir` and every folder below it that is not excluded. With
+    /// `report_added`, everything found is queued as added.
+    fn add_tree(&mut self, dir: &Path, report_added: bool) -> io::Result<()> {
+        let config = &self.config;
+        let walker = walkdir::WalkDir::new(dir)
+            .follow_links(false)
+            .into_iter()
+            .filter_entry(|entry| !config.is_excluded(entry.path()));
+
+        let mut found = Vec::new();
+        for entry in walker.flatten() {
+            found.push((entry.path().to_path_buf(), entry.file_type().is_dir()));
+        }
+
+        for (path, is_dir) in found {
+            if is_dir {
+                match self.inotify.watches().add(&path, watch_mask()) {
+                    Ok(wd) => {
+                        self.watches.insert(wd, path.clone());
+                    }
+                    // The watch limit is exhausted; report it instead of silently missing changes
+                    Err(e) if e.raw_os_error() == Some(libc::ENOSPC) => {
+                        return Err(io::Error::new(e.kind(), format!(
+                            "inotify watch limit reached at {} (raise fs.inotify.max_user_watches)",
+                            path.display()
+                        )));
+                    }
+                    Err(e) if path == dir => return Err(e),
+                    Err(_) => {}
+                }
+            }
+            if report_added && path != dir {
+                self.pending.push(path, FileChangeType::Added);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the watches of `dir` and every folder below it. Folders moved
+    /// out of the tree are still watched by the kernel until told otherwise.
+    fn forget_tree(&mut self, dir: &Path) {
+        let removed: Vec<WatchDescriptor> = self.watches.iter()
+            .filter(|(_, path)| path.starts_with(dir))
+            .map(|(wd, _)| wd.clone())
+            .collect();
+        for wd in removed {
+            // Fails for deleted folders, whose watches the kernel already dropped
+            let _ = self.inotify.watches().remove(wd.clone());
+            self.watches.remove(&wd);
+        }
+    }
+
+    /// The kernel queue overflowed and events were lost: re-register every
+    /// folder and report the root as updated so consumers refresh their state
+    fn rescan(&mut self) -> io::Result<()> {
+        tracing::warn!("inotify queue overflow for {}, rescanning", self.config.root.display());
+
+        let known: Vec<PathBuf> = self.watches.values().cloned().collect();
+        let root = self.config.root.clone();
+        let result = self.add_tree(&root, false);
+
+        for dir in known {
+            if !dir.exists() {
+                self.pending.push(dir.clone(), FileChangeType::Deleted);
+                self.forget_tree(&dir);
+            }
+        }
+        self.pending.push(root, FileChangeType::Updated);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use tempfile::TempDir;
+
+    fn start(root: &Path, excludes: &[&str]) -> (Arc<AtomicBool>, mpsc::Receiver<Vec<FileChangeEvent>>) {
+        let patterns: Vec<String> = excludes.iter().map(|s| s.to_string()).collect();
+        let config = WatchConfig {
+            root: root.to_path_buf(),
+            excludes: super::super::build_exclude_set(&patterns).unwrap(),
+            debounce_ms: 20,
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        spawn(config, Arc::clone(&stop), move |batch| {
+            if let Ok(events) = batch {
+                let _ = tx.send(events);
+            }
+        }).unwrap();
+        // Watches are registered on the watcher thread; give it time to walk the tree
+        std::thread::sleep(Duration::from_millis(100));
+        (stop, rx)
+    }
+
+    fn collect(rx: &mpsc::Receiver<Vec<FileChangeEvent>>) -> Vec<(PathBuf, FileChangeType)> {
+        let mut all = Vec::new();
+        while let Ok(batch) = rx.recv_timeout(Duration::from_millis(500)) {
+            all.extend(batch.into_iter().map(|e| (PathBuf::from(e.path), e.change_type)));
+        }
+        all
+    }
+
+    #[test]
+    fn test_watch_recursive_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("existing")).unwrap();
+        std::fs::write(root.join("existing/file.txt"), b"one").unwrap();
+
+        let (stop, rx) = start(root, &[]);
+
+        std::fs::write(root.join("existing/file.txt"), b"two").unwrap();
+        std::fs::create_dir_all(root.join("new/nested")).unwrap();
+        std::fs::write(root.join("new/nested/deep.txt"), b"deep").unwrap();
+        std::fs::write(root.join("gone.txt"), b"x").unwrap();
+        std::fs::remove_file(root.join("gone.txt")).unwrap();
+
+        let events = collect(&rx);
+        stop.store(true, Ordering::Relaxed);
+
+        assert!(events.contains(&(root.join("existing/file.txt"), FileChangeType::Updated)));
+        assert!(events.contains(&(root.join("new"), FileChangeType::Added)));
+        assert!(events.contains(&(root.join("new/nested/deep.txt"), FileChangeType::Added)));
+        assert!(!events.iter().any(|(path, _)| path == &root.join("gone.txt")));
+    }
+
+    #[test]
+    fn test_watch_ends_when_root_is_deleted() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("root");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+
+        let (stop, rx) = start(&root, &[]);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let events = collect(&rx);
+        assert!(events.contains(&(root.clone(), FileChangeType::Deleted)));
+        // The thread marks its session as ended so the root can be watched again
+        assert!(stop.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_watch_rejects_file_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("file.txt");
+        std::fs::write(&file, b"x").unwrap();
+
+        let config = WatchConfig {
+            root: file,
+            excludes: super::super::build_exclude_set(&[]).unwrap(),
+            debounce_ms: 20,
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        assert!(spawn(config, stop, |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_watch_honours_excludes() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir(root.join("node_modules")).unwrap();
+
+        let (stop, rx) = start(root, &["**/node_modules/**"]);
+
+        std::fs::write(root.join("node_modules/dep.js"), b"ignored").unwrap();
+        std::fs::write(root.join("index.js"), b"seen").unwrap();
+
+        let events = collect(&rx);
+        stop.store(true, Ordering::Relaxed);
+
+        assert!(events.contains(&(root.join("index.js"), FileChangeType::Added)));
+        assert!(!events.iter().any(|(path, _)| path.starts_with(root.join("node_modules"))));
+    }
+}
@@ -0,0 +1,301 @@
+/*---------------------------------------------------------------------------------------------
+ *  Copyright (c) Microsoft Corporation. All rights reserved.
+ *  Licensed under the MIT License. See License.txt in the project root for license information.
+ *--------------------------------------------------------------------------------------------*/
+
+use crate::error::{CoreError, JsResult};
+use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi_derive::napi;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[cfg(target_os = "linux")]
+mod inotify;
+
+const DEFAULT_DEBOUNCE_MS: u32 = 75;
+
+/// Recursive native file watcher delivering batched, coalesced change events.
+///
+/// Each watched root is served by its own background thread, so one slow or
+/// overflowing tree never delays events from another.
+#[napi]
+#[derive(Default)]
+pub struct FileWatcher {
+    /// Stop flag of each root's thread, which the thread also sets when it ends on its own
+    sessions: HashMap<String, Arc<AtomicBool>>,
+}
+
+#[napi]
+impl FileWatcher {
+    #[napi(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start watching `root` recursively. `callback` receives `(err, events)` for every batch.
+    /// The tree is registered in the background; failures there, such as an
+    /// exhausted inotify watch limit, are passed to `callback` as `err`.
+    #[napi(ts_args_type = "root: string, options: WatcherOptions | undefined | null, callback: (err: Error | null, events: FileChangeEvent[]) => void")]
+    pub fn watch(&mut self, root: String, options: Option<WatcherOptions>, callback: JsFunction) -> JsResult<()> {
+        // A session whose root was deleted has ended and can be replaced
+        self.sessions.retain(|_, stop| !stop.load(Ordering::Relaxed));
+        if self.sessions.contains_key(&root) {
+            return Err(CoreError::InvalidArgument(format!("Already watching {}", root))).into();
+        }
+
+        let opts = options.unwrap_or_default();
+        let excludes = match build_exclude_set(opts.exclude_patterns.as_deref().unwrap_or(&[])) {
+            Ok(excludes) => excludes,
+            Err(e) => return Err(CoreError::InvalidArgument(format!("Invalid exclude pattern: {}", e))).into(),
+        };
+
+        let tsfn: ThreadsafeFunction<Vec<FileChangeEvent>, ErrorStrategy::CalleeHandled> =
+            match callback.create_threadsafe_function(0, |ctx| Ok(vec![ctx.value])) {
+                Ok(tsfn) => tsfn,
+                Err(e) => return Err(CoreError::Unavailable(format!("Failed to create watcher callback: {}", e))).into(),
+            };
+
+        let config = WatchConfig {
+            root: PathBuf::from(&root),
+            excludes,
+            debounce_ms: opts.debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS),
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let started = spawn_backend(config, Arc::clone(&stop), move |batch| {
+            tsfn.call(batch, ThreadsafeFunctionCallMode::NonBlocking);
+        });
+        if let Err(e) = started {
+            return Err(CoreError::io(format!("Failed to watch {}", root), &root, e)).into();
+        }
+
+        self.sessions.insert(root, stop);
+        Ok(()).into()
+    }
+
+    /// Stop watching a root previously passed to `watch`
+    #[napi]
+    pub fn unwatch(&mut self, root: String) -> bool {
+        match self.sessions.remove(&root) {
+            Some(stop) => {
+                stop.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop all watches
+    #[napi]
+    pub fn dispose(&mut self) {
+        for (_, stop) in self.sessions.drain() {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[napi(getter)]
+    pub fn watched_roots(&self) -> Vec<String> {
+        self.sessions.iter()
+            .filter(|(_, stop)| !stop.load(Ordering::Relaxed))
+            .map(|(root, _)| root.clone())
+            .collect()
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.dispose();
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn spawn_backend<F>(config: WatchConfig, stop: Arc<AtomicBool>, sink: F) -> std::io::Result<()>
+where
+    F: Fn(Result<Vec<FileChangeEvent>>) + Send + 'static,
+{
+    inotify::spawn(config, stop, sink)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn spawn_backend<F>(_config: WatchConfig, _stop: Arc<AtomicBool>, _sink: F) -> std::io::Result<()>
+where
+    F: Fn(Result<Vec<FileChangeEvent>>) + Send + 'static,
+{
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "native file watching is only available on Linux",
+    ))
+}
+
+pub(crate) struct WatchConfig {
+    pub root: PathBuf,
+    pub excludes: GlobSet,
+    pub debounce_ms: u32,
+}
+
+impl WatchConfig {
+    /// Whether `path` (below the root) matches one of the exclude globs
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        match path.strip_prefix(&self.root) {
+            Ok(rel) if !rel.as_os_str().is_empty() => self.excludes.is_match(rel),
+            _ => false,
+        }
+    }
+}
+
+/// Build a glob set from VS Code style exclude patterns such as `**/node_modules/**`.
+///
+/// A trailing `/**` also matches the folder itself, so excluded folders can be
+/// pruned before they are ever descended into.
+pub(crate) fn build_exclude_set(patterns: &[String]) -> std::result::Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_start_matches("./");
+        builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+        if let Some(folder) = pattern.strip_suffix("/**") {
+            builder.add(GlobBuilder::new(folder).literal_separator(true).build()?);
+        }
+    }
+    builder.build()
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct WatcherOptions {
+    /// Globs relative to the watched root that are ignored, e.g. `**/node_modules/**`
+    pub exclude_patterns: Option<Vec<String>>,
+    /// Quiet period before a batch of events is delivered (default: 75ms)
+    pub debounce_ms: Option<u32>,
+}
+
+/// Mirrors VS Code's `FileChangeType`
+#[napi]
+#[derive(Debug, PartialEq, Eq)]
+pub enum FileChangeType {
+    Updated = 0,
+    Added = 1,
+    Deleted = 2,
+}
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct FileChangeEvent {
+    pub path: String,
+    pub change_type: FileChangeType,
+}
+
+/// Folds raw events into at most one event per path, in first-seen order:
+/// added+deleted cancel out, deleted+added becomes updated and changes below a
+/// deleted folder are dropped.
+#[derive(Default)]
+pub(crate) struct EventCoalescer {
+    events: Vec<Option<(PathBuf, FileChangeType)>>,
+    index: HashMap<PathBuf, usize>,
+}
+
+impl EventCoalescer {
+    pub fn push(&mut self, path: PathBuf, change_type: FileChangeType) {
+        let Some(&slot) = self.index.get(&path) else {
+            self.index.insert(path.clone(), self.events.len());
+            self.events.push(Some((path, change_type)));
+            return;
+        };
+
+        let previous = self.events[slot].as_ref().map(|(_, t)| t);
+        let merged = match (previous, change_type) {
+            (Some(FileChangeType::Added), FileChangeType::Deleted) => None,
+            (Some(FileChangeType::Added), _) => Some(FileChangeType::Added),
+            (Some(FileChangeType::Deleted), FileChangeType::Added) => Some(FileChangeType::Updated),
+            (None, FileChangeType::Deleted) => None,
+            (_, change_type) => Some(change_type),
+        };
+        self.events[slot] = merged.map(|t| (path, t));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn drain(&mut self) -> Vec<FileChangeEvent> {
+        self.index.clear();
+        let events: Vec<(PathBuf, FileChangeType)> = self.events.drain(..).flatten().collect();
+
+        let deleted: HashSet<&Path> = events.iter()
+            .filter(|(_, t)| *t == FileChangeType::Deleted)
+            .map(|(p, _)| p.as_path())
+            .collect();
+
+        events.iter()
+            .filter(|(path, _)| !path.ancestors().skip(1).any(|dir| deleted.contains(dir)))
+            .map(|(path, change_type)| FileChangeEvent {
+                path: path.to_string_lossy().to_string(),
+                change_type: *change_type,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(coalescer: &mut EventCoalescer) -> Vec<(String, FileChangeType)> {
+        coalescer.drain().into_iter().map(|e| (e.path, e.change_type)).collect()
+    }
+
+    #[test]
+    fn test_coalesce_add_delete_cancels() {
+        let mut coalescer = EventCoalescer::default();
+        coalescer.push(PathBuf::from("/w/a.txt"), FileChangeType::Added);
+        coalescer.push(PathBuf::from("/w/a.txt"), FileChangeType::Updated);
+        coalescer.push(PathBuf::from("/w/a.txt"), FileChangeType::Deleted);
+        coalescer.push(PathBuf::from("/w/b.txt"), FileChangeType::Updated);
+
+        assert_eq!(drain(&mut coalescer), vec![("/w/b.txt".to_string(), FileChangeType::Updated)]);
+        assert!(coalescer.is_empty());
+    }
+
+    #[test]
+    fn test_coalesce_delete_add_is_update() {
+        let mut coalescer = EventCoalescer::default();
+        coalescer.push(PathBuf::from("/w/a.txt"), FileChangeType::Deleted);
+        coalescer.push(PathBuf::from("/w/a.txt"), FileChangeType::Added);
+        coalescer.push(PathBuf::from("/w/new.txt"), FileChangeType::Added);
+        coalescer.push(PathBuf::from("/w/new.txt"), FileChangeType::Updated);
+
+        assert_eq!(drain(&mut coalescer), vec![
+            ("/w/a.txt".to_string(), FileChangeType::Updated),
+            ("/w/new.txt".to_string(), FileChangeType::Added),
+        ]);
+    }
+
+    #[test]
+    fn test_coalesce_drops_children_of_deleted_folder() {
+        let mut coalescer = EventCoalescer::default();
+        coalescer.push(PathBuf::from("/w/dir/a.txt"), FileChangeType::Deleted);
+        coalescer.push(PathBuf::from("/w/dir"), FileChangeType::Deleted);
+        coalescer.push(PathBuf::from("/w/dir2/b.txt"), FileChangeType::Updated);
+
+        assert_eq!(drain(&mut coalescer), vec![
+            ("/w/dir".to_string(), FileChangeType::Deleted),
+            ("/w/dir2/b.txt".to_string(), FileChangeType::Updated),
+        ]);
+    }
+
+    #[test]
+    fn test_exclude_set_prunes_folders() {
+        let excludes = build_exclude_set(&["**/node_modules/**".to_string(), "*.log".to_string()]).unwrap();
+        let config = WatchConfig { root: PathBuf::from("/w"), excludes, debounce_ms: 0 };
+
+        assert!(config.is_excluded(Path::new("/w/node_modules")));
+        assert!(config.is_excluded(Path::new("/w/pkg/node_modules/dep/index.js")));
+        assert!(config.is_excluded(Path::new("/w/debug.log")));
+        assert!(!config.is_excluded(Path::new("/w/src/debug.log")));
+        assert!(!config.is_excluded(Path::new("/w/src/main.rs")));
+        assert!(!config.is_excluded(Path::new("/w")));
+    }
+}
@@ -2,11 +2,15 @@
 
 use napi_derive::napi;
 
+pub mod cancellation;
+pub mod error;
+
 // Include the main file_operations implementation
 mod file_operations;
 pub use file_operations::*;
 
 // Other modules
+pub mod file_watcher;
 pub mod search_engine;
 pub mod performance_monitor;
 pub mod ai_orchestrator;
@@ -19,6 +19,9 @@ pub struct RustPerformanceMetrics {
     pub cache_hit_rate: f64,
     pub cache_misses: u64,
     pub cache_size_mb: f64,
+    /// Hit rate of the AI context cache, counted apart from the file cache
+    pub context_cache_hit_rate: f64,
+    pub context_cache_misses: u64,
     pub cpu_usage_percent: f64,
     pub active_handles: u32,
     pub pending_operations: u32,
@@ -67,6 +70,8 @@ pub struct PerformanceMonitor {
     cache_hits: AtomicU64,
     cache_misses: AtomicU64,
     cache_size_bytes: AtomicUsize,
+    context_cache_hits: AtomicU64,
+    context_cache_misses: AtomicU64,
     active_operations: AtomicU32,
     start_time: Instant,
     memory_samples: Arc<Mutex<Vec<usize>>>,
@@ -81,6 +86,8 @@ impl PerformanceMonitor {
             cache_hits: AtomicU64::new(0),
             cache_misses: AtomicU64::new(0),
             cache_size_bytes: AtomicUsize::new(0),
+            context_cache_hits: AtomicU64::new(0),
+            context_cache_misses: AtomicU64::new(0),
             active_operations: AtomicU32::new(0),
             start_time: Instant::now(),
             memory_samples: Arc::new(Mutex::new(Vec::with_capacity(60))),
@@ -112,6 +119,16 @@ impl PerformanceMonitor {
         self.cache_misses.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Record a hit in the AI context cache
+    pub fn record_context_cache_hit(&self) {
+        self.context_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a miss in the AI context cache
+    pub fn record_context_cache_miss(&self) {
+        self.context_cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Update cache size
     #[napi]
     pub fn update_cache_size(&self, size_bytes: u32) {
@@ -129,6 +146,13 @@ impl PerformanceMonitor {
         } else {
             0.0
         };
+        let context_cache_hits = self.context_cache_hits.load(Ordering::Relaxed);
+        let context_cache_misses = self.context_cache_misses.load(Ordering::Relaxed);
+        let context_cache_hit_rate = if context_cache_hits + context_cache_misses > 0 {
+            context_cache_hits as f64 / (context_cache_hits + context_cache_misses) as f64
+        } else {
+            0.0
+        };
 
         // Get memory usage
         let memory_mb = self.get_memory_usage_mb();
@@ -146,6 +170,8 @@ impl PerformanceMonitor {
             cache_hit_rate,
             cache_misses: self.cache_misses.load(Ordering::Relaxed),
             cache_size_mb: self.cache_size_bytes.load(Ordering::Relaxed) as f64 / 1024.0 / 1024.0,
+            context_cache_hit_rate,
+            context_cache_misses,
             cpu_usage_percent: self.estimate_cpu_usage(),
             active_handles: self.active_operations.load(Ordering::Relaxed),
             pending_operations: 0, // TODO: Track from file system provider
@@ -181,6 +207,8 @@ impl PerformanceMonitor {
         self.operation_stats.clear();
         self.cache_hits.store(0, Ordering::Relaxed);
         self.cache_misses.store(0, Ordering::Relaxed);
+        self.context_cache_hits.store(0, Ordering::Relaxed);
+        self.context_cache_misses.store(0, Ordering::Relaxed);
         self.active_operations.store(0, Ordering::Relaxed);
         
         if let Ok(mut samples) = self.memory_samples.lock() {
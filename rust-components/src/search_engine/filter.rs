@@ -0,0 +1,220 @@
+use super::{ExcludeRules, SearchOptions};
+use crate::error::{CoreError, CoreResult};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use napi::Either;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Decides which files below a search root are searched, from the include and
+/// exclude globs of `SearchOptions`. Globs use VS Code's syntax: `**`, `{a,b}`
+/// and `[...]`, with `*` never crossing a `/`.
+pub struct PathFilter {
+    root: PathBuf,
+    includes: Option<GlobSet>,
+    scopes: Vec<ExcludeScope>,
+}
+
+/// Exclude globs relative to one folder
+struct ExcludeScope {
+    folder: PathBuf,
+    globs: GlobSet,
+    /// The `when` clause of each glob, if it has one
+    when: Vec<Option<String>>,
+}
+
+impl PathFilter {
+    pub fn new(root: &Path, opts: &SearchOptions) -> CoreResult<Self> {
+        let includes = match opts.include_patterns.as_deref() {
+            Some(patterns) if !patterns.is_empty() => {
+                let mut builder = GlobSetBuilder::new();
+                for pattern in patterns.iter().flat_map(|p| expand_search_pattern(p)) {
+                    builder.add(compile(&pattern)?);
+                }
+                Some(builder.build().map_err(invalid_glob)?)
+            }
+            _ => None,
+        };
+
+        let mut scopes = Vec::new();
+        let mut root_rules: Vec<(String, Option<String>)> = opts
+            .exclude_patterns
+            .iter()
+            .flatten()
+            .flat_map(|p| expand_search_pattern(p))
+            .map(|pattern| (pattern, None))
+            .collect();
+        root_rules.extend(rules(opts.exclude.as_ref()));
+        if !root_rules.is_empty() {
+            scopes.push(ExcludeScope::new(root.to_path_buf(), root_rules)?);
+        }
+        for folder in opts.folder_excludes.iter().flatten() {
+            let folder_rules: Vec<_> = rules(Some(&folder.exclude)).collect();
+            if !folder_rules.is_empty() {
+                scopes.push(ExcludeScope::new(root.join(&folder.folder), folder_rules)?);
+            }
+        }
+
+        Ok(PathFilter { root: root.to_path_buf(), includes, scopes })
+    }
+
+    /// Whether the filter lets every file through
+    pub fn is_empty(&self) -> bool {
+        self.includes.is_none() && self.scopes.is_empty()
+    }
+
+    /// Whether the walk should not descend into `dir`
+    pub fn skips_dir(&self, dir: &Path) -> bool {
+        self.scopes.iter().any(|scope| scope.excludes(dir))
+    }
+
+    /// Whether `file` is filtered out, assuming its folders are not
+    pub fn skips_file(&self, file: &Path) -> bool {
+        if self.scopes.iter().any(|scope| scope.excludes(file)) {
+            return true;
+        }
+        match (&self.includes, file.strip_prefix(&self.root)) {
+            (Some(includes), Ok(rel)) => !includes.is_match(rel),
+            _ => false,
+        }
+    }
+
+    /// Whether `file` is filtered out, either itself or through one of its
+    /// folders below the root. Decisions about folders are memoized in `dirs`.
+    pub fn hides(&self, file: &Path, dirs: &mut HashMap<PathBuf, bool>) -> bool {
+        self.skips_file(file) || file.parent().is_some_and(|dir| self.hides_dir(dir, dirs))
+    }
+
+    fn hides_dir(&self, dir: &Path, dirs: &mut HashMap<PathBuf, bool>) -> bool {
+        if dir == self.root || !dir.starts_with(&self.root) {
+            return false;
+        }
+        if let Some(&hidden) = dirs.get(dir) {
+            return hidden;
+        }
+        let hidden = self.skips_dir(dir) || dir.parent().is_some_and(|parent| self.hides_dir(parent, dirs));
+        dirs.insert(dir.to_path_buf(), hidden);
+        hidden
+    }
+}
+
+impl ExcludeScope {
+    fn new(folder: PathBuf, rules: Vec<(String, Option<String>)>) -> CoreResult<Self> {
+        let mut builder = GlobSetBuilder::new();
+        let mut when = Vec::new();
+        for (pattern, clause) in rules {
+            let pattern = pattern.trim_start_matches("./");
+            builder.add(compile(pattern)?);
+            when.push(clause.clone());
+            // A trailing `/**` also matches the folder itself, so it is never walked
+            if let Some(folder) = pattern.strip_suffix("/**") {
+                builder.add(compile(folder)?);
+                when.push(clause);
+            }
+        }
+        Ok(ExcludeScope { folder, globs: builder.build().map_err(invalid_glob)?, when })
+    }
+
+    fn excludes(&self, path: &Path) -> bool {
+        let Ok(rel) = path.strip_prefix(&self.folder) else { return false };
+        if rel.as_os_str().is_empty() {
+            return false;
+        }
+        self.globs.matches(rel).into_iter().any(|i| match &self.when[i] {
+            None => true,
+            Some(clause) => sibling_exists(path, clause),
+        })
+    }
+}
+
+/// The enabled entries of a `files.exclude` style object
+fn rules(exclude: Option<&ExcludeRules>) -> impl Iterator<Item = (String, Option<String>)> + '_ {
+    exclude.into_iter().flatten().filter_map(|(pattern, value)| match value {
+        Either::A(true) => Some((pattern.clone(), None)),
+        Either::A(false) => None,
+        Either::B(clause) => Some((pattern.clone(), Some(clause.when.clone()))),
+    })
+}
+
+/// Whether the sibling named by a `when` clause exists, e.g. `$(basename).ts`
+/// for `app.js` is `app.ts` in the same folder
+fn sibling_exists(path: &Path, clause: &str) -> bool {
+    let (Some(parent), Some(stem)) = (path.parent(), path.file_stem()) else { return false };
+    let sibling = clause.replace("$(basename)", &stem.to_string_lossy());
+    parent.join(sibling).exists()
+}
+
+/// Expand a pattern typed into the search view like VS Code does: unless it is
+/// anchored with `./` it matches at any depth, and a folder matches its contents
+fn expand_search_pattern(pattern: &str) -> Vec<String> {
+    let pattern = pattern.trim().trim_end_matches('/');
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    let pattern = match pattern.strip_prefix("./") {
+        Some(anchored) => anchored.to_string(),
+        None if pattern.starts_with("**") => pattern.to_string(),
+        None => format!("**/{}", pattern.trim_start_matches('/')),
+    };
+    if pattern.ends_with("/**") {
+        vec![pattern]
+    } else {
+        vec![format!("{}/**", pattern), pattern]
+    }
+}
+
+fn compile(pattern: &str) -> CoreResult<globset::Glob> {
+    GlobBuilder::new(pattern).literal_separator(true).build().map_err(invalid_glob)
+}
+
+fn invalid_glob(e: globset::Error) -> CoreError {
+    CoreError::InvalidArgument(format!("Invalid glob: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_engine::{ExcludeClause, FolderExclude};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_path_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src/gen")).unwrap();
+        for file in ["src/app.ts", "src/app.js", "src/util.js", "src/gen/out.ts", "README.md"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        let mut exclude = HashMap::new();
+        exclude.insert("**/*.js".to_string(), Either::B(ExcludeClause { when: "$(basename).ts".to_string() }));
+        exclude.insert("**/*.md".to_string(), Either::A(false));
+        let mut folder_exclude = HashMap::new();
+        folder_exclude.insert("gen".to_string(), Either::A(true));
+        let opts = SearchOptions {
+            include_patterns: Some(vec!["*.{ts,js}".to_string()]),
+            exclude: Some(exclude),
+            folder_excludes: Some(vec![FolderExclude { folder: "src".to_string(), exclude: folder_exclude }]),
+            ..Default::default()
+        };
+        let filter = PathFilter::new(root, &opts).unwrap();
+
+        // `app.js` has a sibling `app.ts`, `util.js` does not
+        let visible = |file: &str| !filter.hides(&root.join(file), &mut HashMap::new());
+        assert!(visible("src/app.ts"));
+        assert!(!visible("src/app.js"));
+        assert!(visible("src/util.js"));
+        assert!(!visible("src/gen/out.ts"));
+        assert!(filter.skips_dir(&root.join("src/gen")));
+        // Disabled excludes do not apply, but the include still does
+        assert!(!visible("README.md"));
+
+        let opts = SearchOptions { exclude_patterns: Some(vec!["gen".to_string(), "./README.md".to_string()]), ..Default::default() };
+        let filter = PathFilter::new(root, &opts).unwrap();
+        assert!(filter.skips_dir(&root.join("src/gen")));
+        assert!(filter.skips_file(&root.join("README.md")));
+        assert!(!filter.skips_file(&root.join("src/app.js")));
+
+        let opts = SearchOptions { include_patterns: Some(vec!["src/{a".to_string()]), ..Default::default() };
+        assert!(PathFilter::new(root, &opts).is_err());
+    }
+}
@@ -0,0 +1,669 @@
+use super::index::{canonical_root, relative, walk, IGNORE_FILES};
+use super::{FuzzyMatch, HighlightRange};
+use crate::cancellation::CancelSignal;
+use crate::error::CoreResult;
+use crate::file_watcher::{FileChangeEvent, FileChangeType};
+use dashmap::DashMap;
+use memchr::{memchr, memchr2};
+use parking_lot::RwLock;
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Results returned when no limit is given
+pub const DEFAULT_FUZZY_LIMIT: u32 = 100;
+/// Paths are scored in parallel this many at a time
+const SCORE_CHUNK: usize = 8192;
+/// Query characters beyond this are ignored, which bounds the cost of scoring
+const MAX_QUERY_LEN: usize = 64;
+
+// Per-character scores, after VS Code's `fuzzyScore`
+const SCORE_MATCH: i32 = 1;
+const BONUS_SAME_CASE: i32 = 1;
+const BONUS_CONSECUTIVE: i32 = 5;
+const BONUS_START: i32 = 8;
+const BONUS_PATH_SEPARATOR: i32 = 5;
+const BONUS_WORD_SEPARATOR: i32 = 4;
+const BONUS_CAMEL_CASE: i32 = 2;
+const BONUS_FILENAME: i32 = 2;
+
+const NO_MATCH: i32 = i32::MIN;
+
+/// The files below a root that the search walk would visit, as sorted paths
+/// relative to the root with `/` separators
+pub struct FileList {
+    root: PathBuf,
+    paths: Vec<String>,
+    /// The signature of each path, for skipping paths without scoring them
+    signatures: Vec<Signature>,
+    /// Folders the walk descended into, so new files can be checked against them
+    dirs: HashSet<String>,
+}
+
+impl FileList {
+    fn new(root: PathBuf, mut paths: Vec<String>, dirs: HashSet<String>) -> Self {
+        paths.sort_unstable();
+        let signatures = paths.iter().map(|path| Signature::of(path)).collect();
+        FileList { root, paths, signatures, dirs }
+    }
+
+    fn build(root: PathBuf, signal: &CancelSignal) -> CoreResult<Self> {
+        let (mut paths, mut dirs) = (Vec::new(), HashSet::new());
+        for entry in walk(&root, &root, None, signal)? {
+            if entry.is_dir {
+                dirs.insert(entry.path);
+            } else {
+                paths.push(entry.path);
+            }
+        }
+        Ok(Self::new(root, paths, dirs))
+    }
+
+    /// Apply file change events, listing only the folders they touch
+    fn apply_changes(&mut self, changes: &[FileChangeEvent], signal: &CancelSignal) -> CoreResult<()> {
+        for change in changes {
+            let path = Path::new(&change.path);
+            let Some(rel) = relative(&self.root, path) else { continue };
+            if path.file_name().is_some_and(|name| IGNORE_FILES.iter().any(|f| name == *f)) {
+                *self = Self::build(self.root.clone(), signal)?;
+                return Ok(());
+            }
+            let parent = rel.rsplit_once('/').map_or("", |(parent, _)| parent);
+
+            self.remove(&rel);
+            let Ok(meta) = std::fs::metadata(path) else { continue };
+            if change.change_type == FileChangeType::Deleted || !self.dirs.contains(parent) {
+                continue;
+            }
+            // Walking from the parent applies the ignore rules of every folder above
+            let (from, max_depth) = if meta.is_dir() { (rel.as_str(), None) } else { (parent, Some(1)) };
+            for entry in walk(&self.root, &self.root.join(from), max_depth, signal)? {
+                if entry.is_dir {
+                    self.dirs.insert(entry.path);
+                } else if meta.is_dir() || entry.path == rel {
+                    self.insert(entry.path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, path: String) {
+        if let Err(pos) = self.paths.binary_search(&path) {
+            self.signatures.insert(pos, Signature::of(&path));
+            self.paths.insert(pos, path);
+        }
+    }
+
+    /// Remove a file, or a folder and everything below it
+    fn remove(&mut self, rel: &str) {
+        if let Ok(pos) = self.paths.binary_search_by(|p| p.as_str().cmp(rel)) {
+            self.paths.remove(pos);
+            self.signatures.remove(pos);
+        }
+        let prefix = format!("{}/", rel);
+        let start = self.paths.partition_point(|p| p.as_str() < prefix.as_str());
+        let end = start + self.paths[start..].iter().take_while(|p| p.starts_with(&prefix)).count();
+        self.paths.drain(start..end);
+        self.signatures.drain(start..end);
+        if self.dirs.remove(rel) {
+            self.dirs.retain(|d| !d.starts_with(&prefix));
+        }
+    }
+
+    /// The `limit` paths scoring best against `query`, best first
+    fn find(&self, query: &str, limit: usize) -> Vec<FuzzyMatch> {
+        let Some(query) = Query::new(query) else { return Vec::new() };
+        if limit == 0 {
+            return Vec::new();
+        }
+
+        // Each chunk keeps its own best `limit` results; paths are sorted, so
+        // the index breaks ties between paths of the same length. Paths that
+        // cannot beat the worst of them even with their best score are skipped.
+        let best = self
+            .signatures
+            .par_chunks(SCORE_CHUNK)
+            .enumerate()
+            .map(|(chunk, signatures)| {
+                let mut scorer = Scorer::default();
+                let mut best = BinaryHeap::with_capacity(limit + 1);
+                for (i, signature) in (chunk * SCORE_CHUNK..).zip(signatures) {
+                    let Some(bound) = query.upper_bound(signature) else { continue };
+                    let path = &self.paths[i];
+                    let rank = |score| Reverse((score, Reverse(path.len()), Reverse(i)));
+                    let beats_worst = |best: &BinaryHeap<_>, score| best.len() < limit || best.peek().is_some_and(|worst| rank(score) < *worst);
+                    if !beats_worst(&best, bound) || !query.may_match(path) {
+                        continue;
+                    }
+                    let score = scorer.score(&query, path, None);
+                    if score != NO_MATCH && beats_worst(&best, score) {
+                        best.push(rank(score));
+                        if best.len() > limit {
+                            best.pop();
+                        }
+                    }
+                }
+                best
+            })
+            .reduce(BinaryHeap::new, |mut a, b| {
+                for ranked in b {
+                    a.push(ranked);
+                    if a.len() > limit {
+                        a.pop();
+                    }
+                }
+                a
+            });
+
+        let mut scorer = Scorer::default();
+        let mut positions = Vec::new();
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((score, _, Reverse(i)))| {
+                let path = &self.paths[i];
+                scorer.score(&query, path, Some(&mut positions));
+                FuzzyMatch { path: path.clone(), score, highlights: highlights(path, &positions) }
+            })
+            .collect()
+    }
+}
+
+/// A query without whitespace, lowercased character by character so positions
+/// line up with the original
+struct Query {
+    chars: Vec<char>,
+    lower: Vec<char>,
+    /// Set when the query is ASCII, so ASCII paths can be scored bytewise
+    ascii: Option<Vec<u8>>,
+    ascii_lower: Vec<u8>,
+    /// The query characters each byte matches, as bitsets
+    ascii_rows: Box<[u64; 256]>,
+    /// The signature bit of each character
+    bits: Vec<u64>,
+    /// All of `bits`, which a path must have to match
+    required: u64,
+}
+
+impl Query {
+    fn new(query: &str) -> Option<Self> {
+        let chars: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).take(MAX_QUERY_LEN).collect();
+        if chars.is_empty() {
+            return None;
+        }
+        let lower: Vec<char> = chars.iter().map(|&c| Unit::lower(c)).collect();
+        let ascii: Option<Vec<u8>> = chars.iter().all(char::is_ascii).then(|| chars.iter().map(|&c| c as u8).collect());
+        let ascii_lower: Vec<u8> = ascii.iter().flatten().map(u8::to_ascii_lowercase).collect();
+        let bits: Vec<u64> = lower.iter().map(|&c| signature_bit(c)).collect();
+        let required = bits.iter().fold(0, |all, bit| all | bit);
+        let mut ascii_rows = Box::new([0; 256]);
+        for (b, rows) in ascii_rows.iter_mut().enumerate() {
+            let lower = (b as u8).to_ascii_lowercase();
+            *rows = ascii_lower.iter().enumerate().filter(|&(_, &q)| q == lower).fold(0, |rows, (i, _)| rows | 1 << i);
+        }
+        Some(Query { chars, lower, ascii, ascii_lower, ascii_rows, bits, required })
+    }
+
+    /// The highest score a path with this signature could reach, or `None` if
+    /// it lacks some of the query's characters
+    fn upper_bound(&self, signature: &Signature) -> Option<i32> {
+        if signature.chars & self.required != self.required {
+            return None;
+        }
+        let bound = self.bits.iter().enumerate().map(|(i, &bit)| {
+            let at_boundary = signature.starts & bit != 0;
+            // Only the first character can be at the start, and a match right
+            // after the previous one is only at a separator if that one is
+            let position = match i.checked_sub(1).map(|p| self.lower[p]) {
+                None if at_boundary => BONUS_START,
+                None => 0,
+                Some(p) if at_boundary && (p.is_path_separator() || p.is_word_separator()) => BONUS_CONSECUTIVE + BONUS_PATH_SEPARATOR,
+                Some(_) if at_boundary => (BONUS_CONSECUTIVE + BONUS_CAMEL_CASE).max(BONUS_PATH_SEPARATOR),
+                Some(_) => BONUS_CONSECUTIVE,
+            };
+            SCORE_MATCH + BONUS_SAME_CASE + BONUS_FILENAME + position
+        });
+        Some(bound.sum())
+    }
+
+    /// A cheap check that every query character occurs in order
+    fn may_match(&self, path: &str) -> bool {
+        if self.ascii.is_none() {
+            return true;
+        }
+        let mut rest = path.as_bytes();
+        for &b in &self.ascii_lower {
+            let found = if b.is_ascii_lowercase() { memchr2(b, b.to_ascii_uppercase(), rest) } else { memchr(b, rest) };
+            match found {
+                Some(i) => rest = &rest[i + 1..],
+                None => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Which lowercased characters occur in a path, and which occur where
+/// `char_score` gives a boundary bonus, as bitsets of `signature_bit`s
+#[derive(Clone, Copy)]
+struct Signature {
+    chars: u64,
+    starts: u64,
+}
+
+impl Signature {
+    fn of(path: &str) -> Self {
+        let mut signature = Signature { chars: 0, starts: 0 };
+        let mut previous: Option<char> = None;
+        for c in path.chars() {
+            let bit = signature_bit(c.lower());
+            signature.chars |= bit;
+            let boundary = match previous {
+                None => true,
+                Some(p) => p.is_path_separator() || p.is_word_separator() || (c.is_upper() && p.is_lower_or_digit()),
+            };
+            if boundary {
+                signature.starts |= bit;
+            }
+            previous = Some(c);
+        }
+        signature
+    }
+}
+
+/// Letters and digits get a bit each; other characters share the rest
+fn signature_bit(lower: char) -> u64 {
+    let bit = match lower {
+        'a'..='z' => lower as u32 - 'a' as u32,
+        '0'..='9' => 26 + lower as u32 - '0' as u32,
+        _ if lower.is_ascii() => 36 + lower as u32 % 27,
+        _ => 63,
+    };
+    1 << bit
+}
+
+/// A character of a path: a byte of ASCII paths, scored without decoding, or
+/// a `char` of any other path
+trait Unit: Copy + Eq {
+    fn lower(self) -> Self;
+    fn is_upper(self) -> bool;
+    fn is_lower_or_digit(self) -> bool;
+    fn is_path_separator(self) -> bool;
+    fn is_word_separator(self) -> bool;
+}
+
+impl Unit for u8 {
+    fn lower(self) -> Self {
+        self.to_ascii_lowercase()
+    }
+
+    fn is_upper(self) -> bool {
+        self.is_ascii_uppercase()
+    }
+
+    fn is_lower_or_digit(self) -> bool {
+        self.is_ascii_lowercase() || self.is_ascii_digit()
+    }
+
+    fn is_path_separator(self) -> bool {
+        matches!(self, b'/' | b'\\')
+    }
+
+    fn is_word_separator(self) -> bool {
+        matches!(self, b'_' | b'-' | b'.' | b' ')
+    }
+}
+
+impl Unit for char {
+    fn lower(self) -> Self {
+        if self.is_ascii() {
+            self.to_ascii_lowercase()
+        } else {
+            self.to_lowercase().next().unwrap_or(self)
+        }
+    }
+
+    fn is_upper(self) -> bool {
+        self.is_uppercase()
+    }
+
+    fn is_lower_or_digit(self) -> bool {
+        self.is_lowercase() || self.is_ascii_digit()
+    }
+
+    fn is_path_separator(self) -> bool {
+        matches!(self, '/' | '\\')
+    }
+
+    fn is_word_separator(self) -> bool {
+        matches!(self, '_' | '-' | '.' | ' ')
+    }
+}
+
+/// Buffers reused across the paths scored by one thread
+#[derive(Default)]
+struct Scorer {
+    /// The characters of a non-ASCII path
+    chars: Vec<char>,
+    /// The columns each query character occurs at
+    occurrences: Vec<Vec<u32>>,
+    /// Where the candidates of each query character start in `columns`
+    rows: Vec<usize>,
+    /// The columns each query character can be matched at, row by row
+    columns: Vec<u32>,
+    /// Best score of the query up to a row with that row's character matched
+    /// at the candidate column
+    scores: Vec<i32>,
+    /// The candidate of the row above that score extends
+    previous: Vec<usize>,
+}
+
+impl Scorer {
+    /// Score the best alignment of `query` in `path`, or `NO_MATCH`. With
+    /// `positions`, also return the matched character indices.
+    fn score(&mut self, query: &Query, path: &str, positions: Option<&mut Vec<usize>>) -> i32 {
+        if let (Some(ascii), true) = (&query.ascii, path.is_ascii()) {
+            return self.score_units(ascii, |b| query.ascii_rows[b as usize], path.as_bytes(), positions);
+        }
+        let mut chars = std::mem::take(&mut self.chars);
+        chars.clear();
+        chars.extend(path.chars());
+        let rows_of = |c: char| {
+            let c = c.lower();
+            query.lower.iter().enumerate().filter(|&(_, &q)| q == c).fold(0, |rows, (i, _)| rows | 1 << i)
+        };
+        let score = self.score_units(&query.chars, rows_of, &chars, positions);
+        self.chars = chars;
+        score
+    }
+
+    /// `rows_of` gives the query characters a path character matches, as a bitset
+    fn score_units<T: Unit>(&mut self, query: &[T], rows_of: impl Fn(T) -> u64, target: &[T], positions: Option<&mut Vec<usize>>) -> i32 {
+        let m = query.len();
+        self.occurrences.resize_with(m.max(self.occurrences.len()), Vec::new);
+        let occurrences = &mut self.occurrences[..m];
+        for row in occurrences.iter_mut() {
+            row.clear();
+        }
+        for (j, &c) in target.iter().enumerate() {
+            let mut rows = rows_of(c);
+            while rows != 0 {
+                occurrences[rows.trailing_zeros() as usize].push(j as u32);
+                rows &= rows - 1;
+            }
+        }
+
+        // Keep the occurrences that leave room for the rest of the query on
+        // both sides, giving up if there are none
+        let mut from = 0;
+        for row in occurrences.iter_mut() {
+            let start = row.partition_point(|&j| j < from);
+            let Some(&j) = row.get(start) else { return NO_MATCH };
+            row.drain(..start);
+            from = j + 1;
+        }
+        let mut to = u32::MAX;
+        for row in occurrences.iter_mut().rev() {
+            let end = row.partition_point(|&j| j < to);
+            row.truncate(end);
+            to = row[end - 1];
+        }
+
+        self.rows.clear();
+        self.columns.clear();
+        for row in occurrences.iter() {
+            self.rows.push(self.columns.len());
+            self.columns.extend_from_slice(row);
+        }
+        self.rows.push(self.columns.len());
+        let filename_start = target.iter().rposition(|c| c.is_path_separator()).map_or(0, |i| i + 1);
+        self.scores.clear();
+        self.scores.resize(self.columns.len(), NO_MATCH);
+        self.previous.clear();
+        self.previous.resize(self.columns.len(), 0);
+
+        for (i, &query_char) in query.iter().enumerate() {
+            let row = self.rows[i]..self.rows[i + 1];
+            let char_score = |j: usize, consecutive: bool| char_score(target, query_char, j, consecutive, filename_start);
+            if i == 0 {
+                for c in row {
+                    self.scores[c] = char_score(self.columns[c] as usize, false);
+                }
+                continue;
+            }
+
+            let above_end = self.rows[i];
+            let mut k = self.rows[i - 1];
+            // Best score of the row above over the columns before `j - 1`
+            let mut best_before = (NO_MATCH, 0);
+            for c in row {
+                let j = self.columns[c] as usize;
+                while k < above_end && self.columns[k] as usize + 1 < j {
+                    if self.scores[k] > best_before.0 {
+                        best_before = (self.scores[k], k);
+                    }
+                    k += 1;
+                }
+                if k < above_end && self.columns[k] as usize + 1 == j && self.scores[k] != NO_MATCH {
+                    self.scores[c] = self.scores[k] + char_score(j, true);
+                    self.previous[c] = k;
+                }
+                if best_before.0 != NO_MATCH && best_before.0 + char_score(j, false) > self.scores[c] {
+                    self.scores[c] = best_before.0 + char_score(j, false);
+                    self.previous[c] = best_before.1;
+                }
+            }
+        }
+
+        // The best candidate of the last row, the earliest one on ties
+        let last_row = self.rows[m - 1]..self.rows[m];
+        let Some(end) = last_row.rev().max_by_key(|&c| self.scores[c]) else {
+            return NO_MATCH;
+        };
+        let score = self.scores[end];
+        if let (Some(positions), true) = (positions, score != NO_MATCH) {
+            positions.clear();
+            let mut c = end;
+            for _ in 0..m {
+                positions.push(self.columns[c] as usize);
+                c = self.previous[c];
+            }
+            positions.reverse();
+        }
+        score
+    }
+}
+
+/// The score of matching `query_char` at `target[j]`
+fn char_score<T: Unit>(target: &[T], query_char: T, j: usize, consecutive: bool, filename_start: usize) -> i32 {
+    let c = target[j];
+    let mut score = SCORE_MATCH;
+    if c == query_char {
+        score += BONUS_SAME_CASE;
+    }
+    if consecutive {
+        score += BONUS_CONSECUTIVE;
+    }
+    score += match j.checked_sub(1).map(|p| target[p]) {
+        None => BONUS_START,
+        Some(p) if p.is_path_separator() => BONUS_PATH_SEPARATOR,
+        Some(p) if p.is_word_separator() => BONUS_WORD_SEPARATOR,
+        Some(p) if c.is_upper() && p.is_lower_or_digit() => BONUS_CAMEL_CASE,
+        Some(_) => 0,
+    };
+    if j >= filename_start {
+        score += BONUS_FILENAME;
+    }
+    score
+}
+
+/// Merge matched character indices into UTF-16 ranges of `path`
+fn highlights(path: &str, positions: &[usize]) -> Vec<HighlightRange> {
+    let mut ranges: Vec<HighlightRange> = Vec::new();
+    let mut positions = positions.iter().peekable();
+    let mut offset = 0;
+    for (i, c) in path.chars().enumerate() {
+        let width = c.len_utf16() as u32;
+        if positions.next_if(|&&p| p == i).is_some() {
+            match ranges.last_mut() {
+                Some(last) if last.end == offset => last.end += width,
+                _ => ranges.push(HighlightRange { start: offset, end: offset + width }),
+            }
+        }
+        offset += width;
+    }
+    ranges
+}
+
+/// The file lists kept for Quick Open, keyed by canonical root
+#[derive(Default)]
+pub struct FileLists {
+    lists: DashMap<PathBuf, Arc<RwLock<FileList>>>,
+}
+
+impl FileLists {
+    /// Score the files below `root`, listing them on first use
+    pub fn find(&self, root: &str, query: &str, limit: usize, signal: &CancelSignal) -> CoreResult<Vec<FuzzyMatch>> {
+        let root = canonical_root(root)?;
+        let list = match self.lists.get(&root) {
+            Some(list) => Arc::clone(list.value()),
+            None => {
+                let list = Arc::new(RwLock::new(FileList::build(root.clone(), signal)?));
+                Arc::clone(self.lists.entry(root).or_insert(list).value())
+            }
+        };
+        let matches = list.read().find(query, limit);
+        Ok(matches)
+    }
+
+    /// Apply file watcher events to the list of `root`, if it has one. Returns
+    /// the number of files listed afterwards.
+    pub fn update(&self, root: &str, changes: &[FileChangeEvent], signal: &CancelSignal) -> CoreResult<u32> {
+        let Some(list) = self.lists.get(&canonical_root(root)?).map(|list| Arc::clone(list.value())) else {
+            return Ok(0);
+        };
+        let mut list = list.write();
+        list.apply_changes(changes, signal)?;
+        Ok(list.paths.len() as u32)
+    }
+
+    pub fn forget(&self, root: &str) -> CoreResult<bool> {
+        Ok(self.lists.remove(&canonical_root(root)?).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn list(paths: &[&str]) -> FileList {
+        FileList::new(PathBuf::new(), paths.iter().map(|p| p.to_string()).collect(), HashSet::new())
+    }
+
+    fn ranked(list: &FileList, query: &str) -> Vec<String> {
+        list.find(query, 10).into_iter().map(|m| m.path).collect()
+    }
+
+    #[test]
+    fn test_fuzzy_ranking() {
+        let files = list(&[
+            "src/flowing.ts",
+            "src/fileWatcher.ts",
+            "src/model/index.ts",
+            "src/file_watcher/mod.rs",
+            "lib/a_b_c.rs",
+            "lib/abc.rs",
+        ]);
+
+        assert_eq!(ranked(&files, "abc"), ["lib/abc.rs", "lib/a_b_c.rs"]);
+        assert_eq!(ranked(&files, "fw")[0], "src/fileWatcher.ts");
+        // Matches in the file name beat matches in folders
+        assert_eq!(ranked(&files, "mod"), ["src/file_watcher/mod.rs", "src/model/index.ts"]);
+        assert!(ranked(&files, "xyz").is_empty());
+        assert!(ranked(&files, "  ").is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_highlights() {
+        let files = list(&["src/ünïcode/😀Main.rs"]);
+        let found = files.find("Ünmain", 10);
+        assert_eq!(found.len(), 1);
+        // The emoji takes two UTF-16 code units
+        let ranges: Vec<_> = found[0].highlights.iter().map(|r| (r.start, r.end)).collect();
+        assert_eq!(ranges, [(4, 6), (14, 18)]);
+    }
+
+    #[test]
+    #[ignore = "timing, run with `cargo test --release -- --ignored`"]
+    fn test_fuzzy_find_500k_paths_under_10ms() {
+        // A deterministic tree of 500k paths up to five folders deep. Chunks
+        // are scored in parallel, so the target assumes a few cores: a single
+        // one takes several times as long on broad queries.
+        const WORDS: [&str; 16] = [
+            "src", "lib", "components", "utils", "model", "view", "test", "core",
+            "fileWatcher", "search_engine", "index", "parser", "common", "browser", "node", "services",
+        ];
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+        let paths: Vec<String> = (0..500_000)
+            .map(|i| {
+                let depth = 1 + next() % 5;
+                let mut path: Vec<&str> = (0..depth).map(|_| WORDS[next() % WORDS.len()]).collect();
+                let name = format!("{}{}.ts", WORDS[next() % WORDS.len()], i);
+                path.push(&name);
+                path.join("/")
+            })
+            .collect();
+        let files = FileList::new(PathBuf::new(), paths, HashSet::new());
+
+        for query in ["fw", "srcidx", "components/parser", "searchengineindex12345"] {
+            let best = (0..5)
+                .map(|_| {
+                    let start = std::time::Instant::now();
+                    let found = files.find(query, DEFAULT_FUZZY_LIMIT as usize);
+                    assert!(!found.is_empty(), "no match for {:?}", query);
+                    start.elapsed()
+                })
+                .min()
+                .unwrap();
+            let threads = rayon::current_num_threads();
+            assert!(best.as_millis() < 10, "{:?} took {:?} on {} threads", query, best, threads);
+        }
+    }
+
+    #[test]
+    fn test_file_list_updates() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/a.rs"), "").unwrap();
+        std::fs::write(root.join(".ignore"), "*.log\n").unwrap();
+
+        let mut list = FileList::build(root.clone(), &CancelSignal::default()).unwrap();
+        assert_eq!(list.paths, ["src/a.rs"]);
+
+        std::fs::create_dir(root.join("lib")).unwrap();
+        std::fs::write(root.join("lib/b.rs"), "").unwrap();
+        std::fs::write(root.join("src/c.log"), "").unwrap();
+        std::fs::remove_file(root.join("src/a.rs")).unwrap();
+        let event = |path: &str, change_type| FileChangeEvent { path: root.join(path).to_string_lossy().to_string(), change_type };
+        let changes = [
+            event("lib", FileChangeType::Added),
+            event("src/c.log", FileChangeType::Added),
+            event("src/a.rs", FileChangeType::Deleted),
+        ];
+        list.apply_changes(&changes, &CancelSignal::default()).unwrap();
+        assert_eq!(list.paths, ["lib/b.rs"]);
+
+        list.apply_changes(&[event("lib", FileChangeType::Deleted)], &CancelSignal::default()).unwrap();
+        assert!(list.paths.is_empty());
+    }
+}
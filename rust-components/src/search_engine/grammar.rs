@@ -0,0 +1,64 @@
+use std::path::Path;
+use tree_sitter::{Parser, Tree};
+
+/// The tree-sitter grammars bundled for structural search and code analysis
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Grammar {
+    TypeScript,
+    Tsx,
+    JavaScript,
+    Rust,
+    Python,
+    Go,
+}
+
+impl Grammar {
+    /// From a VS Code language id such as `typescriptreact`
+    pub fn for_language_id(id: &str) -> Option<Grammar> {
+        Some(match id {
+            "typescript" => Grammar::TypeScript,
+            "typescriptreact" => Grammar::Tsx,
+            "javascript" | "javascriptreact" => Grammar::JavaScript,
+            "rust" => Grammar::Rust,
+            "python" => Grammar::Python,
+            "go" => Grammar::Go,
+            _ => return None,
+        })
+    }
+
+    pub fn for_path(path: &Path) -> Option<Grammar> {
+        Some(match path.extension()?.to_str()? {
+            "ts" | "mts" | "cts" => Grammar::TypeScript,
+            "tsx" => Grammar::Tsx,
+            "js" | "jsx" | "mjs" | "cjs" => Grammar::JavaScript,
+            "rs" => Grammar::Rust,
+            "py" | "pyi" => Grammar::Python,
+            "go" => Grammar::Go,
+            _ => return None,
+        })
+    }
+
+    pub fn language(self) -> tree_sitter::Language {
+        match self {
+            Grammar::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Grammar::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Grammar::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Grammar::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Grammar::Python => tree_sitter_python::LANGUAGE.into(),
+            Grammar::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+
+    /// TypeScript patterns also apply to TSX files and the other way round;
+    /// the grammars share their node kinds
+    pub fn same_family(self, other: Grammar) -> bool {
+        let family = |g| if g == Grammar::Tsx { Grammar::TypeScript } else { g };
+        family(self) == family(other)
+    }
+
+    pub fn parse(self, source: &[u8]) -> Option<Tree> {
+        let mut parser = Parser::new();
+        parser.set_language(&self.language()).ok()?;
+        parser.parse(source, None)
+    }
+}
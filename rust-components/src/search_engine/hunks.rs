@@ -0,0 +1,144 @@
+use super::replace::{apply_edits, Edit};
+use super::DiffHunk;
+
+/// Lines of the original content changed by a run of edits on overlapping lines
+struct Block {
+    /// First and one past the last changed line, 0-based
+    first: usize,
+    end: usize,
+    new_lines: Vec<String>,
+}
+
+/// Turn `edits` of `content` into unified diff hunks with `context` unchanged
+/// lines around each change, in the shape of jsdiff's `structuredPatch`
+pub fn build_hunks(content: &[u8], edits: &[Edit], context: usize) -> Vec<DiffHunk> {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(content.iter().enumerate().filter(|&(_, &b)| b == b'\n').map(|(i, _)| i + 1))
+        .filter(|&start| start < content.len())
+        .collect();
+    let line_count = line_starts.len();
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset).saturating_sub(1);
+    let line_range = |first: usize, end: usize| {
+        let from = line_starts.get(first).copied().unwrap_or(content.len());
+        let to = line_starts.get(end).copied().unwrap_or(content.len());
+        from..to
+    };
+    let old_line = |line: usize| display_line(&content[line_range(line, line + 1)]);
+
+    let mut blocks: Vec<(usize, usize, Vec<&Edit>)> = Vec::new();
+    for edit in edits {
+        let first = line_of(edit.range.start);
+        let end = line_of(edit.range.end.max(edit.range.start + 1) - 1).max(first) + 1;
+        match blocks.last_mut() {
+            Some((_, block_end, block_edits)) if first < *block_end => {
+                *block_end = (*block_end).max(end);
+                block_edits.push(edit);
+            }
+            _ => blocks.push((first, end, vec![edit])),
+        }
+    }
+
+    let blocks = blocks.into_iter().map(|(first, end, block_edits)| {
+        let range = line_range(first, end);
+        let relative: Vec<Edit> = block_edits
+            .iter()
+            .map(|edit| Edit { range: edit.range.start - range.start..edit.range.end - range.start, replacement: edit.replacement.clone() })
+            .collect();
+        let replaced = apply_edits(&content[range], &relative);
+        let new_lines = replaced.split_inclusive(|&b| b == b'\n').map(display_line).collect();
+        Block { first, end, new_lines }
+    });
+
+    let mut hunks: Vec<DiffHunk> = Vec::new();
+    // One past the last changed line of the current hunk, and how many lines
+    // the blocks before it added or removed
+    let mut hunk_end = 0;
+    let mut delta: i64 = 0;
+    for block in blocks {
+        let lead_from = block.first.saturating_sub(context);
+        let joins_previous = !hunks.is_empty() && lead_from <= hunk_end + context;
+        if !joins_previous {
+            if let Some(hunk) = hunks.last_mut() {
+                push_context(hunk, hunk_end..(hunk_end + context).min(line_count), &old_line);
+            }
+            hunks.push(DiffHunk {
+                old_start: lead_from as u32 + 1,
+                old_lines: 0,
+                new_start: (lead_from as i64 + delta) as u32 + 1,
+                new_lines: 0,
+                lines: Vec::new(),
+            });
+        }
+        let hunk = hunks.last_mut().unwrap();
+        let lead = if joins_previous { hunk_end..block.first } else { lead_from..block.first };
+        push_context(hunk, lead, &old_line);
+
+        for line in block.first..block.end {
+            hunk.lines.push(format!("-{}", old_line(line)));
+        }
+        for line in &block.new_lines {
+            hunk.lines.push(format!("+{}", line));
+        }
+        hunk.old_lines += (block.end - block.first) as u32;
+        hunk.new_lines += block.new_lines.len() as u32;
+        delta += block.new_lines.len() as i64 - (block.end - block.first) as i64;
+        hunk_end = block.end;
+    }
+    if let Some(hunk) = hunks.last_mut() {
+        push_context(hunk, hunk_end..(hunk_end + context).min(line_count), &old_line);
+    }
+    hunks
+}
+
+fn push_context(hunk: &mut DiffHunk, lines: std::ops::Range<usize>, old_line: &impl Fn(usize) -> String) {
+    for line in lines {
+        hunk.lines.push(format!(" {}", old_line(line)));
+        hunk.old_lines += 1;
+        hunk.new_lines += 1;
+    }
+}
+
+fn display_line(line: &[u8]) -> String {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(content: &str, needle: &str, replacement: &str) -> Vec<Edit> {
+        content
+            .match_indices(needle)
+            .map(|(i, m)| Edit { range: i..i + m.len(), replacement: replacement.as_bytes().to_vec() })
+            .collect()
+    }
+
+    #[test]
+    fn test_hunks_merge_nearby_changes() {
+        let content: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let mut edits = edit(&content, "line 3\n", "line three\n");
+        edits.extend(edit(&content, "line 6\n", ""));
+        edits.extend(edit(&content, "line 18\n", "line 18a\nline 18b\n"));
+
+        let hunks = build_hunks(content.as_bytes(), &edits, 2);
+        assert_eq!(hunks.len(), 2);
+
+        let first = &hunks[0];
+        assert_eq!((first.old_start, first.old_lines, first.new_start, first.new_lines), (1, 8, 1, 7));
+        assert_eq!(first.lines, [" line 1", " line 2", "-line 3", "+line three", " line 4", " line 5", "-line 6", " line 7", " line 8"]);
+
+        let second = &hunks[1];
+        assert_eq!((second.old_start, second.old_lines, second.new_start, second.new_lines), (16, 5, 15, 6));
+        assert_eq!(second.lines, [" line 16", " line 17", "-line 18", "+line 18a", "+line 18b", " line 19", " line 20"]);
+    }
+
+    #[test]
+    fn test_hunks_group_edits_on_one_line() {
+        let content = "a b a\r\nb\n";
+        let hunks = build_hunks(content.as_bytes(), &edit(content, "a", "x"), 3);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].lines, ["-a b a", "+x b x", " b"]);
+        assert_eq!((hunks[0].old_lines, hunks[0].new_lines), (2, 2));
+    }
+}
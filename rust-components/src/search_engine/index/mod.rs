@@ -0,0 +1,549 @@
+use super::filter::PathFilter;
+use super::{IndexOptions, IndexStats, SearchOptions};
+use crate::cancellation::CancelSignal;
+use crate::error::{CoreError, CoreResult};
+use crate::file_watcher::{FileChangeEvent, FileChangeType};
+use dashmap::DashMap;
+use ignore::{WalkBuilder, WalkState};
+use parking_lot::RwLock;
+use rayon::prelude::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+mod query;
+mod store;
+pub use query::{plan, Query};
+use query::trigram;
+
+/// Larger files are not indexed and always searched (default: 16 MiB)
+const DEFAULT_MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+/// Files with a NUL byte this close to the start are treated as binary
+const BINARY_SNIFF_LEN: usize = 8192;
+/// Files are read and split into trigrams in parallel this many at a time
+const BUILD_CHUNK: usize = 1024;
+/// Incremental updates are written to disk at most this often
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// Changes to these files can change which files the walk visits
+pub(crate) const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".rgignore"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+enum FileState {
+    Indexed = 0,
+    /// Binary or too large: not indexed, so a candidate for every query
+    Unindexed = 1,
+    Deleted = 2,
+}
+
+struct IndexedFile {
+    /// Relative to the root, with `/` separators
+    path: String,
+    mtime_ns: u64,
+    size: u64,
+    state: FileState,
+}
+
+/// An entry found by walking part of the root
+pub(crate) struct Entry {
+    pub path: String,
+    pub is_dir: bool,
+    mtime_ns: u64,
+    size: u64,
+}
+
+/// Trigram postings for the files the search walk would visit below `root`.
+///
+/// File ids only ever grow: a changed file is marked deleted and added again
+/// under a new id, which keeps every posting list sorted. Deleted entries are
+/// compacted away once they make up half of the files.
+pub struct TrigramIndex {
+    root: PathBuf,
+    files: Vec<IndexedFile>,
+    by_path: HashMap<String, u32>,
+    /// Folders the walk descended into, so new files can be checked against them
+    dirs: HashSet<String>,
+    postings: HashMap<u32, Vec<u32>>,
+    unindexed: Vec<u32>,
+    deleted: usize,
+    max_file_size: u64,
+    storage: Option<PathBuf>,
+    last_saved: Instant,
+}
+
+impl TrigramIndex {
+    fn empty(root: PathBuf) -> Self {
+        TrigramIndex {
+            root,
+            files: Vec::new(),
+            by_path: HashMap::new(),
+            dirs: HashSet::new(),
+            postings: HashMap::new(),
+            unindexed: Vec::new(),
+            deleted: 0,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            storage: None,
+            last_saved: Instant::now(),
+        }
+    }
+
+    /// Load the index of `root` from `opts.storage_dir` if there is one, bring
+    /// it up to date with the tree and save it back
+    fn open(root: PathBuf, opts: &IndexOptions, signal: &CancelSignal) -> CoreResult<Self> {
+        let storage = opts.storage_dir.as_ref().map(|dir| {
+            let mut hasher = DefaultHasher::new();
+            root.hash(&mut hasher);
+            Path::new(dir).join(format!("{:016x}.trigrams", hasher.finish()))
+        });
+
+        let mut index = storage.as_deref().and_then(|path| store::load(path, &root)).unwrap_or_else(|| Self::empty(root));
+        index.unindexed = (0..index.files.len() as u32).filter(|&id| index.files[id as usize].state == FileState::Unindexed).collect();
+        index.max_file_size = opts.max_file_size.map_or(DEFAULT_MAX_FILE_SIZE, |size| size as u64);
+        index.storage = storage;
+
+        index.refresh(signal)?;
+        index.save()?;
+        Ok(index)
+    }
+
+    fn save(&mut self) -> CoreResult<()> {
+        if let Some(path) = &self.storage {
+            store::save(self, path).map_err(|e| CoreError::io("Failed to save search index", path, e))?;
+        }
+        self.last_saved = Instant::now();
+        Ok(())
+    }
+
+    /// Walk the whole root and reindex whatever changed since the last walk
+    fn refresh(&mut self, signal: &CancelSignal) -> CoreResult<()> {
+        let root = self.root.clone();
+        let entries = walk(&root, &root, None, signal)?;
+        let seen: HashSet<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        let gone: Vec<String> = self.by_path.keys().filter(|path| !seen.contains(path.as_str())).cloned().collect();
+        for path in gone {
+            self.remove(&path);
+        }
+        self.dirs.clear();
+        self.merge(entries, signal)
+    }
+
+    /// Apply file change events, walking only the folders they touch. Paths
+    /// inside folders the walk skips, e.g. ignored ones, stay out of the index.
+    fn apply_changes(&mut self, changes: &[FileChangeEvent], signal: &CancelSignal) -> CoreResult<()> {
+        let mut files_by_dir: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut new_dirs = Vec::new();
+
+        for change in changes {
+            let path = Path::new(&change.path);
+            let Some(rel) = relative(&self.root, path) else { continue };
+            if path.file_name().is_some_and(|name| IGNORE_FILES.iter().any(|f| name == *f)) {
+                return self.refresh(signal);
+            }
+            let parent = rel.rsplit_once('/').map_or("", |(parent, _)| parent).to_string();
+
+            match (&change.change_type, std::fs::metadata(path)) {
+                (FileChangeType::Deleted, _) | (_, Err(_)) => {
+                    self.remove(&rel);
+                    self.remove_dir(&rel);
+                }
+                (_, Ok(meta)) if !self.dirs.contains(&parent) => {
+                    // Below a folder the walk skips; a folder may only have become a file
+                    if meta.is_file() {
+                        self.remove(&rel);
+                    }
+                }
+                (_, Ok(meta)) if meta.is_dir() => new_dirs.push(rel),
+                (_, Ok(_)) => {
+                    files_by_dir.entry(parent).or_default().insert(rel);
+                }
+            }
+        }
+
+        for dir in new_dirs {
+            let entries = walk(&self.root, &self.root.join(&dir), None, signal)?;
+            let seen: HashSet<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+            let prefix = format!("{}/", dir);
+            let gone: Vec<String> = self.by_path.keys().filter(|p| p.starts_with(&prefix) && !seen.contains(p.as_str())).cloned().collect();
+            for path in gone {
+                self.remove(&path);
+            }
+            self.merge(entries, signal)?;
+        }
+
+        for (dir, files) in files_by_dir {
+            // Listing the parent applies the ignore rules of every folder above the files
+            let entries: Vec<Entry> = walk(&self.root, &self.root.join(&dir), Some(1), signal)?
+                .into_iter()
+                .filter(|e| !e.is_dir && files.contains(&e.path))
+                .collect();
+            for path in &files {
+                if !entries.iter().any(|e| &e.path == path) {
+                    self.remove(path);
+                }
+            }
+            self.merge(entries, signal)?;
+        }
+
+        self.compact();
+        if self.last_saved.elapsed() >= SAVE_INTERVAL {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Record walked entries, reading files whose size or mtime changed
+    fn merge(&mut self, entries: Vec<Entry>, signal: &CancelSignal) -> CoreResult<()> {
+        let mut changed = Vec::new();
+        for entry in entries {
+            if entry.is_dir {
+                self.dirs.insert(entry.path);
+                continue;
+            }
+            let unchanged = self.by_path.get(&entry.path).is_some_and(|&id| {
+                let file = &self.files[id as usize];
+                file.mtime_ns == entry.mtime_ns && file.size == entry.size
+            });
+            if !unchanged {
+                changed.push(entry);
+            }
+        }
+
+        for chunk in changed.chunks(BUILD_CHUNK) {
+            signal.check()?;
+            let read: Vec<Option<Vec<u32>>> = chunk.par_iter().map(|entry| self.trigrams(entry)).collect();
+            for (entry, trigrams) in chunk.iter().zip(read) {
+                self.insert(entry, trigrams);
+            }
+        }
+        self.compact();
+        Ok(())
+    }
+
+    /// The sorted trigrams of a file, or `None` if it is not worth indexing
+    fn trigrams(&self, entry: &Entry) -> Option<Vec<u32>> {
+        if entry.size > self.max_file_size {
+            return None;
+        }
+        let content = std::fs::read(self.root.join(&entry.path)).ok()?;
+        // Binary files, and text that is transcoded before it is searched, can
+        // match without containing the pattern's UTF-8 trigrams
+        if content[..content.len().min(BINARY_SNIFF_LEN)].contains(&0) || std::str::from_utf8(&content).is_err() {
+            return None;
+        }
+        let mut trigrams: Vec<u32> = content.windows(3).map(trigram).collect();
+        trigrams.sort_unstable();
+        trigrams.dedup();
+        Some(trigrams)
+    }
+
+    fn insert(&mut self, entry: &Entry, trigrams: Option<Vec<u32>>) {
+        self.remove(&entry.path);
+        let id = self.files.len() as u32;
+        let state = match trigrams {
+            Some(trigrams) => {
+                for trigram in trigrams {
+                    self.postings.entry(trigram).or_default().push(id);
+                }
+                FileState::Indexed
+            }
+            None => {
+                self.unindexed.push(id);
+                FileState::Unindexed
+            }
+        };
+        self.files.push(IndexedFile { path: entry.path.clone(), mtime_ns: entry.mtime_ns, size: entry.size, state });
+        self.by_path.insert(entry.path.clone(), id);
+    }
+
+    fn remove(&mut self, path: &str) {
+        if let Some(id) = self.by_path.remove(path) {
+            self.files[id as usize].state = FileState::Deleted;
+            self.deleted += 1;
+        }
+    }
+
+    fn remove_dir(&mut self, dir: &str) {
+        let prefix = format!("{}/", dir);
+        let below: Vec<String> = self.by_path.keys().filter(|p| p.starts_with(&prefix)).cloned().collect();
+        for path in below {
+            self.remove(&path);
+        }
+        self.dirs.retain(|d| d != dir && !d.starts_with(&prefix));
+    }
+
+    /// Renumber the live files once deleted ones make up half of the index
+    fn compact(&mut self) {
+        if self.deleted == 0 || self.deleted * 2 < self.files.len() {
+            return;
+        }
+        let mut ids = vec![u32::MAX; self.files.len()];
+        let files = std::mem::take(&mut self.files);
+        for (old_id, file) in files.into_iter().enumerate() {
+            if file.state != FileState::Deleted {
+                ids[old_id] = self.files.len() as u32;
+                self.files.push(file);
+            }
+        }
+        for postings in self.postings.values_mut() {
+            postings.retain_mut(|id| {
+                *id = ids[*id as usize];
+                *id != u32::MAX
+            });
+        }
+        self.postings.retain(|_, postings| !postings.is_empty());
+        self.unindexed.retain_mut(|id| {
+            *id = ids[*id as usize];
+            *id != u32::MAX
+        });
+        for id in self.by_path.values_mut() {
+            *id = ids[*id as usize];
+        }
+        self.deleted = 0;
+    }
+
+    /// Files below `prefix` that can match `query`, sorted by id
+    fn candidates(&self, query: &Query, prefix: &str) -> Vec<&str> {
+        let ids = match self.eval(query) {
+            Some(ids) => union(&ids, &self.unindexed),
+            None => (0..self.files.len() as u32).collect(),
+        };
+        ids.into_iter()
+            .map(|id| &self.files[id as usize])
+            .filter(|file| file.state != FileState::Deleted)
+            .map(|file| file.path.as_str())
+            .filter(|path| prefix.is_empty() || path.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('/')))
+            .collect()
+    }
+
+    /// Ids of indexed files satisfying `query`, or `None` for all files
+    fn eval(&self, query: &Query) -> Option<Vec<u32>> {
+        match query {
+            Query::All => None,
+            Query::Trigram(t) => Some(self.postings.get(t).cloned().unwrap_or_default()),
+            Query::And(queries) => queries.iter().filter_map(|q| self.eval(q)).reduce(|a, b| intersect(&a, &b)),
+            Query::Or(queries) => queries.iter().try_fold(Vec::new(), |ids, q| self.eval(q).map(|more| union(&ids, &more))),
+        }
+    }
+
+    fn stats(&self, started: Instant) -> IndexStats {
+        IndexStats {
+            files: (self.files.len() - self.deleted) as u32,
+            unindexed_files: self.unindexed.iter().filter(|&&id| self.files[id as usize].state != FileState::Deleted).count() as u32,
+            trigrams: self.postings.len() as u32,
+            duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Walk `from` (below `root`) with the search walk's default filters
+pub(crate) fn walk(root: &Path, from: &Path, max_depth: Option<usize>, signal: &CancelSignal) -> CoreResult<Vec<Entry>> {
+    let entries = Mutex::new(Vec::new());
+    WalkBuilder::new(from)
+        .hidden(true)
+        .ignore(true)
+        .git_ignore(true)
+        .max_depth(max_depth)
+        .add_custom_ignore_filename(".rgignore")
+        .threads(num_cpus::get())
+        .build_parallel()
+        .run(|| {
+            let entries = &entries;
+            Box::new(move |result| {
+                if signal.is_cancelled() {
+                    return WalkState::Quit;
+                }
+                let Ok(entry) = result else { return WalkState::Continue };
+                let Some(path) = relative(root, entry.path()) else { return WalkState::Continue };
+                let Ok(meta) = entry.metadata() else { return WalkState::Continue };
+                if meta.is_dir() || meta.is_file() {
+                    let mtime_ns = meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_nanos() as u64);
+                    entries.lock().unwrap().push(Entry { path, is_dir: meta.is_dir(), mtime_ns, size: meta.len() });
+                }
+                WalkState::Continue
+            })
+        });
+    signal.check()?;
+    Ok(entries.into_inner().unwrap())
+}
+
+/// `path` relative to `root` with `/` separators; `""` for the root itself.
+/// Paths outside the root or that are not valid UTF-8 give `None`.
+pub(crate) fn relative(root: &Path, path: &Path) -> Option<String> {
+    let rel = path.strip_prefix(root).ok()?;
+    let parts: Option<Vec<&str>> = rel
+        .components()
+        .map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    Some(parts?.join("/"))
+}
+
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (mut i, mut j, mut out) = (0, 0, Vec::new());
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out
+}
+
+fn union(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (mut i, mut j, mut out) = (0, 0, Vec::with_capacity(a.len() + b.len()));
+    while i < a.len() || j < b.len() {
+        if j == b.len() || (i < a.len() && a[i] < b[j]) {
+            out.push(a[i]);
+            i += 1;
+        } else if i == a.len() || b[j] < a[i] {
+            out.push(b[j]);
+            j += 1;
+        } else {
+            out.push(a[i]);
+            i += 1;
+            j += 1;
+        }
+    }
+    out
+}
+
+/// The trigram indexes enabled on a `SearchEngine`, keyed by canonical root
+#[derive(Default)]
+pub struct IndexRegistry {
+    indexes: DashMap<PathBuf, Arc<RwLock<TrigramIndex>>>,
+}
+
+impl IndexRegistry {
+    pub fn enable(&self, root: &str, opts: &IndexOptions, signal: &CancelSignal) -> CoreResult<IndexStats> {
+        let started = Instant::now();
+        let root = canonical_root(root)?;
+        // Save what was built so far before replacing an index with new options
+        if let Some((_, previous)) = self.indexes.remove(&root) {
+            previous.write().save()?;
+        }
+        let index = TrigramIndex::open(root.clone(), opts, signal)?;
+        let stats = index.stats(started);
+        self.indexes.insert(root, Arc::new(RwLock::new(index)));
+        Ok(stats)
+    }
+
+    pub fn update(&self, root: &str, changes: &[FileChangeEvent], signal: &CancelSignal) -> CoreResult<IndexStats> {
+        let started = Instant::now();
+        let index = self.get(&canonical_root(root)?)
+            .ok_or_else(|| CoreError::InvalidArgument(format!("No search index is enabled for {}", root)))?;
+        let mut index = index.write();
+        index.apply_changes(changes, signal)?;
+        Ok(index.stats(started))
+    }
+
+    pub fn disable(&self, root: &str) -> CoreResult<bool> {
+        match self.indexes.remove(&canonical_root(root)?) {
+            Some((_, index)) => {
+                index.write().save()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn get(&self, root: &Path) -> Option<Arc<RwLock<TrigramIndex>>> {
+        self.indexes.get(root).map(|entry| Arc::clone(entry.value()))
+    }
+
+    /// The files below `search_root` that can match `pattern` and pass `filter`,
+    /// or `None` when no index covers it or `opts` ask for files the index does not hold
+    pub fn candidates(&self, search_root: &Path, pattern: &str, filter: &PathFilter, opts: &SearchOptions) -> Option<Vec<PathBuf>> {
+        if self.indexes.is_empty()
+            || opts.include_hidden == Some(true)
+            || opts.disable_ignore == Some(true)
+            || opts.disable_gitignore == Some(true)
+            || opts.max_depth.is_some()
+            || opts.follow_symlinks == Some(true)
+        {
+            return None;
+        }
+        let canonical = search_root.canonicalize().ok()?;
+        let index = self.indexes.iter()
+            .filter(|entry| canonical.starts_with(entry.key()))
+            .max_by_key(|entry| entry.key().as_os_str().len())
+            .map(|entry| Arc::clone(entry.value()))?;
+        let query = plan(pattern, opts.is_regex.unwrap_or(true));
+
+        let index = index.read();
+        let prefix = relative(&index.root, &canonical)?;
+        let mut dirs = HashMap::new();
+        // Paths are reported below the root as it was given, like in a walk
+        let paths = index.candidates(&query, &prefix).into_iter().map(|path| {
+            let path = index.root.join(path);
+            search_root.join(path.strip_prefix(&canonical).unwrap_or(&path))
+        });
+        let paths = paths.filter(|path| filter.is_empty() || !filter.hides(path, &mut dirs));
+        Some(paths.collect())
+    }
+}
+
+pub(crate) fn canonical_root(root: &str) -> CoreResult<PathBuf> {
+    Path::new(root).canonicalize().map_err(|e| CoreError::io("Failed to resolve index root", root, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn candidates(index: &TrigramIndex, pattern: &str) -> Vec<String> {
+        let mut paths: Vec<String> = index.candidates(&plan(pattern, true), "").into_iter().map(String::from).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_index_narrows_and_updates() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/a.rs"), "fn alpha() {}").unwrap();
+        std::fs::write(root.join("src/b.rs"), "fn beta() {}").unwrap();
+        std::fs::write(root.join("data.bin"), b"\0\0alpha").unwrap();
+        std::fs::write(root.join(".ignore"), "ignored/\n").unwrap();
+        std::fs::create_dir(root.join("ignored")).unwrap();
+        std::fs::write(root.join("ignored/c.rs"), "fn alpha() {}").unwrap();
+
+        let storage = TempDir::new().unwrap();
+        let opts = IndexOptions { storage_dir: Some(storage.path().to_string_lossy().to_string()), ..Default::default() };
+        let mut index = TrigramIndex::open(root.clone(), &opts, &CancelSignal::default()).unwrap();
+        // Binary files are always candidates
+        assert_eq!(candidates(&index, "ALPHA"), ["data.bin", "src/a.rs"]);
+        assert_eq!(candidates(&index, "beta|gamma"), ["data.bin", "src/b.rs"]);
+
+        std::fs::write(root.join("src/b.rs"), "fn alpha_beta() {}").unwrap();
+        std::fs::write(root.join("src/d.rs"), "fn alphabet() {}").unwrap();
+        std::fs::write(root.join("ignored/e.rs"), "fn alpha() {}").unwrap();
+        std::fs::remove_file(root.join("src/a.rs")).unwrap();
+        let event = |path: &str, change_type| FileChangeEvent { path: root.join(path).to_string_lossy().to_string(), change_type };
+        let changes = [
+            event("src/b.rs", FileChangeType::Updated),
+            event("src/d.rs", FileChangeType::Added),
+            event("ignored/e.rs", FileChangeType::Added),
+            event("src/a.rs", FileChangeType::Deleted),
+        ];
+        index.apply_changes(&changes, &CancelSignal::default()).unwrap();
+        assert_eq!(candidates(&index, "alpha"), ["data.bin", "src/b.rs", "src/d.rs"]);
+
+        // A reloaded index picks up where the saved one left off
+        index.save().unwrap();
+        let reloaded = TrigramIndex::open(root.clone(), &opts, &CancelSignal::default()).unwrap();
+        assert_eq!(candidates(&reloaded, "alpha"), ["data.bin", "src/b.rs", "src/d.rs"]);
+        assert_eq!(reloaded.stats(Instant::now()).files, 3);
+    }
+}
@@ -0,0 +1,191 @@
+use regex_syntax::hir::{Class, Hir, HirKind};
+use regex_syntax::ParserBuilder;
+use std::borrow::Cow;
+
+/// Exact-string sets larger than this are turned into trigram queries
+const MAX_EXACT: usize = 16;
+/// Classes with more members than this match "anything"
+const MAX_CLASS: usize = 8;
+
+/// The trigrams a file must contain to possibly match a pattern, as a boolean
+/// formula. Trigrams are ASCII-lowercased, like the index itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query {
+    All,
+    Trigram(u32),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+impl Query {
+    fn and(self, other: Query) -> Query {
+        match (self, other) {
+            (Query::All, q) | (q, Query::All) => q,
+            (Query::And(mut a), Query::And(b)) => {
+                a.extend(b);
+                Query::And(a)
+            }
+            (Query::And(mut a), q) | (q, Query::And(mut a)) => {
+                a.push(q);
+                Query::And(a)
+            }
+            (a, b) => Query::And(vec![a, b]),
+        }
+    }
+
+    fn or(self, other: Query) -> Query {
+        match (self, other) {
+            (Query::All, _) | (_, Query::All) => Query::All,
+            (Query::Or(mut a), Query::Or(b)) => {
+                a.extend(b);
+                Query::Or(a)
+            }
+            (Query::Or(mut a), q) | (q, Query::Or(mut a)) => {
+                a.push(q);
+                Query::Or(a)
+            }
+            (a, b) => Query::Or(vec![a, b]),
+        }
+    }
+}
+
+/// Pack three bytes, lowercased, into the key used by the index
+pub fn trigram(bytes: &[u8]) -> u32 {
+    let [a, b, c] = [bytes[0], bytes[1], bytes[2]].map(|b| b.to_ascii_lowercase() as u32);
+    (a << 16) | (b << 8) | c
+}
+
+/// Plan the index lookup for a search pattern. Patterns the regex parser
+/// cannot handle, e.g. with look-around, fall back to `Query::All`.
+pub fn plan(pattern: &str, is_regex: bool) -> Query {
+    let pattern = if is_regex { Cow::Borrowed(pattern) } else { Cow::Owned(regex_syntax::escape(pattern)) };
+    // Case-insensitive parsing keeps the plan valid for every case option
+    let parsed = ParserBuilder::new().case_insensitive(true).utf8(false).build().parse(&pattern);
+    match parsed {
+        Ok(hir) => analyze(&hir).into_query(),
+        Err(_) => Query::All,
+    }
+}
+
+/// What is known about the text a sub-pattern matches: either the exact set
+/// of strings it can match, or only a query every match satisfies
+struct Info {
+    exact: Option<Vec<Vec<u8>>>,
+    required: Query,
+}
+
+impl Info {
+    fn exact(set: Vec<Vec<u8>>) -> Self {
+        Info { exact: Some(set), required: Query::All }
+    }
+
+    fn any() -> Self {
+        Info { exact: None, required: Query::All }
+    }
+
+    fn into_query(self) -> Query {
+        self.required.and(exact_query(self.exact))
+    }
+
+    fn concat(self, next: Info) -> Info {
+        match (self.exact, next.exact) {
+            (Some(a), Some(b)) if a.len() * b.len() <= MAX_EXACT => {
+                let product = a.iter().flat_map(|x| b.iter().map(move |y| [x.as_slice(), y].concat())).collect();
+                Info { exact: Some(product), required: self.required.and(next.required) }
+            }
+            (a, b) => Info {
+                exact: b,
+                required: self.required.and(exact_query(a)).and(next.required),
+            },
+        }
+    }
+
+    fn alternate(self, other: Info) -> Info {
+        match (self.exact, other.exact) {
+            (Some(mut a), Some(b)) if a.len() + b.len() <= MAX_EXACT => {
+                a.extend(b);
+                Info { exact: Some(a), required: self.required.or(other.required) }
+            }
+            (a, b) => {
+                let left = self.required.and(exact_query(a));
+                let right = other.required.and(exact_query(b));
+                Info { exact: None, required: left.or(right) }
+            }
+        }
+    }
+}
+
+/// Any of the strings, each needing all of its trigrams
+fn exact_query(exact: Option<Vec<Vec<u8>>>) -> Query {
+    let Some(set) = exact else { return Query::All };
+    if set.is_empty() || set.iter().any(|s| s.len() < 3) {
+        return Query::All;
+    }
+    set.iter()
+        .map(|s| s.windows(3).map(|w| Query::Trigram(trigram(w))).fold(Query::All, Query::and))
+        .reduce(Query::or)
+        .unwrap_or(Query::All)
+}
+
+fn analyze(hir: &Hir) -> Info {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => Info::exact(vec![Vec::new()]),
+        HirKind::Literal(lit) => Info::exact(vec![lit.0.to_ascii_lowercase()]),
+        HirKind::Class(class) => class_members(class).map_or_else(Info::any, Info::exact),
+        HirKind::Repetition(rep) if rep.min == 0 => Info::any(),
+        HirKind::Repetition(rep) if rep.min == 1 && rep.max == Some(1) => analyze(&rep.sub),
+        HirKind::Repetition(rep) => Info { exact: None, required: analyze(&rep.sub).into_query() },
+        HirKind::Capture(cap) => analyze(&cap.sub),
+        HirKind::Concat(subs) => subs.iter().map(analyze).fold(Info::exact(vec![Vec::new()]), Info::concat),
+        HirKind::Alternation(subs) => subs.iter().map(analyze).reduce(Info::alternate).unwrap_or_else(Info::any),
+    }
+}
+
+/// The lowercased members of a small class, e.g. `[aA]` becomes `["a"]`
+fn class_members(class: &Class) -> Option<Vec<Vec<u8>>> {
+    let mut members: Vec<Vec<u8>> = Vec::new();
+    match class {
+        Class::Unicode(class) => {
+            for range in class.iter() {
+                if range.end() as usize - range.start() as usize >= MAX_CLASS {
+                    return None;
+                }
+                for c in range.start()..=range.end() {
+                    members.push(c.to_string().into_bytes().to_ascii_lowercase());
+                }
+            }
+        }
+        Class::Bytes(class) => {
+            for range in class.iter() {
+                if (range.end() - range.start()) as usize >= MAX_CLASS {
+                    return None;
+                }
+                members.extend((range.start()..=range.end()).map(|b| vec![b.to_ascii_lowercase()]));
+            }
+        }
+    }
+    members.sort();
+    members.dedup();
+    (members.len() <= MAX_CLASS).then_some(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tri(s: &str) -> Query {
+        Query::Trigram(trigram(s.as_bytes()))
+    }
+
+    #[test]
+    fn test_plan() {
+        assert_eq!(plan("Hello", true), Query::And(vec![tri("hel"), tri("ell"), tri("llo")]));
+        assert_eq!(plan("a.b(", false), Query::And(vec![tri("a.b"), tri(".b(")]));
+        assert_eq!(plan("foo|bar", true), Query::Or(vec![tri("foo"), tri("bar")]));
+        assert_eq!(plan(r"abc\w+xyz", true), Query::And(vec![tri("abc"), tri("xyz")]));
+        assert_eq!(plan("ab[cd]", true), Query::Or(vec![tri("abc"), tri("abd")]));
+        assert_eq!(plan(r"\d+", true), Query::All);
+        assert_eq!(plan("fo", true), Query::All);
+        assert_eq!(plan("(?<=x)foo", true), Query::All);
+    }
+}
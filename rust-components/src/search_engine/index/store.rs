@@ -0,0 +1,191 @@
+use super::{FileState, IndexedFile, TrigramIndex};
+use crate::file_operations::{write_atomic, DEFAULT_ATOMIC_POSTFIX};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Bumped whenever the layout below changes; older files are rebuilt
+const MAGIC: &[u8; 8] = b"CSTRI002";
+
+/// Serialize the live part of an index:
+///
+/// ```text
+/// magic, root, #files, (path, mtime_ns, size, state)*, #dirs, path*,
+/// #trigrams, (trigram, #ids, delta-encoded varint ids)*
+/// ```
+pub fn save(index: &TrigramIndex, path: &Path) -> io::Result<()> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    put_str(&mut out, &index.root.to_string_lossy());
+
+    // File ids are renumbered densely, skipping deleted files
+    let mut ids = vec![u32::MAX; index.files.len()];
+    let live: Vec<(usize, &IndexedFile)> = index.files.iter().enumerate().filter(|(_, f)| f.state != FileState::Deleted).collect();
+    put_u32(&mut out, live.len() as u32);
+    for (new_id, (old_id, file)) in live.iter().enumerate() {
+        ids[*old_id] = new_id as u32;
+        put_str(&mut out, &file.path);
+        out.extend_from_slice(&file.mtime_ns.to_le_bytes());
+        out.extend_from_slice(&file.size.to_le_bytes());
+        out.push(file.state as u8);
+    }
+
+    put_u32(&mut out, index.dirs.len() as u32);
+    for dir in &index.dirs {
+        put_str(&mut out, dir);
+    }
+
+    put_u32(&mut out, index.postings.len() as u32);
+    for (&trigram, postings) in &index.postings {
+        let live_ids: Vec<u32> = postings.iter().map(|&id| ids[id as usize]).filter(|&id| id != u32::MAX).collect();
+        put_u32(&mut out, trigram);
+        put_u32(&mut out, live_ids.len() as u32);
+        let mut previous = 0;
+        for id in live_ids {
+            put_varint(&mut out, id - previous);
+            previous = id;
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_atomic(path, &out, None, DEFAULT_ATOMIC_POSTFIX)
+}
+
+/// Read an index saved by `save`, or `None` if it is missing, from another
+/// version or for another root
+pub fn load(path: &Path, root: &Path) -> Option<TrigramIndex> {
+    let data = std::fs::read(path).ok()?;
+    let mut reader = Reader { data: &data, pos: 0 };
+    if reader.bytes(MAGIC.len())? != MAGIC || reader.str()? != root.to_string_lossy() {
+        return None;
+    }
+
+    let mut index = TrigramIndex::empty(root.to_path_buf());
+    for _ in 0..reader.u32()? {
+        let path = reader.str()?;
+        let mtime_ns = reader.u64()?;
+        let size = reader.u64()?;
+        let state = match reader.bytes(1)?[0] {
+            0 => FileState::Indexed,
+            1 => FileState::Unindexed,
+            _ => return None,
+        };
+        index.by_path.insert(path.clone(), index.files.len() as u32);
+        index.files.push(IndexedFile { path, mtime_ns, size, state });
+    }
+    for _ in 0..reader.u32()? {
+        index.dirs.insert(reader.str()?);
+    }
+
+    // Counts come from the file, so reserve no more than its remaining
+    // bytes could hold: 8 per trigram and 1 per id
+    let trigram_count = reader.u32()? as usize;
+    let mut postings = HashMap::with_capacity(trigram_count.min(reader.remaining() / 8));
+    for _ in 0..trigram_count {
+        let trigram = reader.u32()?;
+        let count = reader.u32()? as usize;
+        let mut ids = Vec::with_capacity(count.min(reader.remaining()));
+        let mut previous = 0u32;
+        for _ in 0..count {
+            previous = previous.checked_add(reader.varint()?)?;
+            if previous as usize >= index.files.len() {
+                return None;
+            }
+            ids.push(previous);
+        }
+        postings.insert(trigram, ids);
+    }
+    index.postings = postings;
+    Some(index)
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn varint(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.bytes(1)?[0];
+            value |= ((byte & 0x7f) as u32).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_rejects_oversized_counts() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let path = root.join("index.bin");
+        let header = |trigrams: u32, ids: u32| {
+            let mut out = MAGIC.to_vec();
+            put_str(&mut out, &root.to_string_lossy());
+            put_u32(&mut out, 0);
+            put_u32(&mut out, 0);
+            put_u32(&mut out, trigrams);
+            put_u32(&mut out, 7);
+            put_u32(&mut out, ids);
+            out
+        };
+
+        // Huge counts with nothing behind them fail without reserving for them
+        std::fs::write(&path, header(u32::MAX, 1)).unwrap();
+        assert!(load(&path, root).is_none());
+        std::fs::write(&path, header(1, u32::MAX)).unwrap();
+        assert!(load(&path, root).is_none());
+        std::fs::write(&path, header(1, 0)).unwrap();
+        assert!(load(&path, root).is_some_and(|index| index.postings[&7].is_empty()));
+    }
+}
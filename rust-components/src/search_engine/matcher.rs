@@ -0,0 +1,387 @@
+use super::SearchOptions;
+use crate::error::{CoreError, CoreResult};
+use fancy_regex::Expr;
+use grep::matcher::{ByteSet, Captures, LineTerminator, Match, Matcher, NoError};
+use grep::regex::{RegexCaptures, RegexMatcher, RegexMatcherBuilder};
+use regex_syntax::ast::{self, Ast, ClassSetItem};
+use std::fmt;
+
+/// Matches a search pattern with the semantics of VS Code's `IPatternInfo`.
+///
+/// Patterns go to Rust's regex engine, like ripgrep's default. Those it
+/// rejects, such as look-arounds and backreferences, fall back to fancy-regex
+/// the way ripgrep's `--auto-hybrid-regex` falls back to PCRE2.
+#[derive(Clone, Debug)]
+pub struct PatternMatcher {
+    engine: Engine,
+    multi_line: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Engine {
+    Rust(RegexMatcher),
+    Fancy(fancy_regex::Regex),
+}
+
+/// A fancy-regex pattern gave up on some text, e.g. after backtracking too much
+#[derive(Debug)]
+pub struct MatchError(String);
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for MatchError {}
+
+impl From<fancy_regex::Error> for MatchError {
+    fn from(e: fancy_regex::Error) -> Self {
+        MatchError(format!("Pattern could not be matched: {}", e))
+    }
+}
+
+impl From<NoError> for MatchError {
+    fn from(e: NoError) -> Self {
+        MatchError(e.to_string())
+    }
+}
+
+impl From<MatchError> for CoreError {
+    fn from(e: MatchError) -> Self {
+        CoreError::InvalidArgument(e.0)
+    }
+}
+
+impl PatternMatcher {
+    /// Matches may span lines, so files have to be searched as a whole
+    pub fn multi_line(&self) -> bool {
+        self.multi_line
+    }
+
+    /// Run `search` from `at` on the text a fancy-regex match may see: the
+    /// line holding `at` unless matches may span lines, and never across
+    /// invalid UTF-8. Moves on to the following text until `search` finds
+    /// something, returning the offset of the text it was found in.
+    fn search_fancy<'h, T>(
+        &self,
+        haystack: &'h [u8],
+        mut at: usize,
+        search: impl Fn(&'h str, usize) -> Result<Option<T>, MatchError>,
+    ) -> Result<Option<(usize, T)>, MatchError> {
+        while at <= haystack.len() {
+            let (mut start, mut end) = (0, haystack.len());
+            if !self.multi_line {
+                start = haystack[..at].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+                end = haystack[at..].iter().position(|&b| b == b'\n').map_or(haystack.len(), |i| at + i);
+            }
+            let text = loop {
+                match std::str::from_utf8(&haystack[start..end]) {
+                    Ok(text) => break text,
+                    Err(e) if at <= start + e.valid_up_to() => {
+                        end = start + e.valid_up_to();
+                    }
+                    Err(e) => {
+                        start += e.valid_up_to() + e.error_len().unwrap_or(end - start - e.valid_up_to());
+                        at = at.max(start);
+                    }
+                }
+            };
+            let mut pos = at - start;
+            while !text.is_char_boundary(pos) {
+                pos += 1;
+            }
+            if let Some(found) = search(text, pos)? {
+                return Ok(Some((start, found)));
+            }
+            at = end + 1;
+        }
+        Ok(None)
+    }
+}
+
+/// Capture groups of either engine
+#[derive(Clone, Debug)]
+pub enum PatternCaptures {
+    Rust(RegexCaptures),
+    Fancy(Vec<Option<Match>>),
+}
+
+impl Captures for PatternCaptures {
+    fn len(&self) -> usize {
+        match self {
+            PatternCaptures::Rust(caps) => caps.len(),
+            PatternCaptures::Fancy(groups) => groups.len(),
+        }
+    }
+
+    fn get(&self, i: usize) -> Option<Match> {
+        match self {
+            PatternCaptures::Rust(caps) => caps.get(i),
+            PatternCaptures::Fancy(groups) => groups.get(i).copied().flatten(),
+        }
+    }
+}
+
+impl Matcher for PatternMatcher {
+    type Captures = PatternCaptures;
+    type Error = MatchError;
+
+    fn find_at(&self, haystack: &[u8], at: usize) -> Result<Option<Match>, MatchError> {
+        match &self.engine {
+            Engine::Rust(regex) => Ok(regex.find_at(haystack, at)?),
+            Engine::Fancy(regex) => {
+                let found = self.search_fancy(haystack, at, |text, pos| Ok(regex.find_from_pos(text, pos)?))?;
+                Ok(found.map(|(start, m)| Match::new(start + m.start(), start + m.end())))
+            }
+        }
+    }
+
+    fn new_captures(&self) -> Result<PatternCaptures, MatchError> {
+        match &self.engine {
+            Engine::Rust(regex) => Ok(PatternCaptures::Rust(regex.new_captures()?)),
+            Engine::Fancy(regex) => Ok(PatternCaptures::Fancy(vec![None; regex.captures_len()])),
+        }
+    }
+
+    fn capture_count(&self) -> usize {
+        match &self.engine {
+            Engine::Rust(regex) => regex.capture_count(),
+            Engine::Fancy(regex) => regex.captures_len(),
+        }
+    }
+
+    fn capture_index(&self, name: &str) -> Option<usize> {
+        match &self.engine {
+            Engine::Rust(regex) => regex.capture_index(name),
+            Engine::Fancy(regex) => regex.capture_names().position(|group| group == Some(name)),
+        }
+    }
+
+    fn captures_at(&self, haystack: &[u8], at: usize, caps: &mut PatternCaptures) -> Result<bool, MatchError> {
+        match (&self.engine, caps) {
+            (Engine::Rust(regex), PatternCaptures::Rust(caps)) => Ok(regex.captures_at(haystack, at, caps)?),
+            (Engine::Fancy(regex), PatternCaptures::Fancy(groups)) => {
+                let found = self.search_fancy(haystack, at, |text, pos| Ok(regex.captures_from_pos(text, pos)?))?;
+                let Some((start, found)) = found else { return Ok(false) };
+                for (i, group) in groups.iter_mut().enumerate() {
+                    *group = found.get(i).map(|m| Match::new(start + m.start(), start + m.end()));
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn non_matching_bytes(&self) -> Option<&ByteSet> {
+        match &self.engine {
+            Engine::Rust(regex) => regex.non_matching_bytes(),
+            Engine::Fancy(_) => None,
+        }
+    }
+
+    fn line_terminator(&self) -> Option<LineTerminator> {
+        match &self.engine {
+            Engine::Rust(regex) => regex.line_terminator(),
+            // Fancy matches are searched for within a line unless they may span lines
+            Engine::Fancy(_) if !self.multi_line => Some(LineTerminator::byte(b'\n')),
+            Engine::Fancy(_) => None,
+        }
+    }
+}
+
+/// Compile `pattern` according to the pattern options of `opts`
+pub fn build_matcher(pattern: &str, opts: &SearchOptions) -> CoreResult<PatternMatcher> {
+    let is_regex = opts.is_regex.unwrap_or(true);
+    // VS Code flags patterns containing a newline as multiline; do the same for
+    // callers that do not, since a line-oriented search can never match them
+    let multi_line = opts.multiline.unwrap_or(false) || pattern.contains('\n') || (is_regex && pattern.contains("\\n"));
+
+    let case_insensitive = match opts.case_sensitive {
+        Some(true) => false,
+        _ if opts.smart_case.unwrap_or(false) => !has_uppercase(pattern, is_regex),
+        Some(false) => true,
+        None => false,
+    };
+    let whole_word = opts.whole_word.unwrap_or(false);
+
+    let rust = RegexMatcherBuilder::new()
+        .case_insensitive(case_insensitive)
+        .word(whole_word)
+        .fixed_strings(!is_regex)
+        .multi_line(true)
+        .line_terminator(if multi_line { None } else { Some(b'\n') })
+        .build(pattern);
+    let engine = match rust {
+        Ok(regex) => Engine::Rust(regex),
+        Err(e) if !is_regex => return Err(CoreError::InvalidArgument(format!("Invalid regex pattern: {}", e))),
+        Err(e) => {
+            let wrapped;
+            let fancy_pattern = match whole_word {
+                true => {
+                    wrapped = format!(r"(?<!\w)(?:{})(?!\w)", pattern);
+                    &wrapped
+                }
+                false => pattern,
+            };
+            let fancy = fancy_regex::RegexBuilder::new(fancy_pattern)
+                .case_insensitive(case_insensitive)
+                .multi_line(true)
+                .build();
+            match fancy {
+                Ok(regex) => Engine::Fancy(regex),
+                // Patterns the fallback cannot handle either report the primary engine's error
+                Err(_) => return Err(CoreError::InvalidArgument(format!("Invalid regex pattern: {}", e))),
+            }
+        }
+    };
+    Ok(PatternMatcher { engine, multi_line })
+}
+
+/// Whether `piece` has an uppercase literal, which turns smart case off
+fn has_uppercase(piece: &str, is_regex: bool) -> bool {
+    if !is_regex {
+        return piece.chars().any(char::is_uppercase);
+    }
+
+    struct Uppercase(bool);
+
+    impl ast::Visitor for Uppercase {
+        type Output = bool;
+        type Err = ();
+
+        fn finish(self) -> Result<bool, ()> {
+            Ok(self.0)
+        }
+
+        fn visit_pre(&mut self, ast: &Ast) -> Result<(), ()> {
+            if let Ast::Literal(lit) = ast {
+                self.0 |= lit.c.is_uppercase();
+            }
+            Ok(())
+        }
+
+        fn visit_class_set_item_pre(&mut self, item: &ClassSetItem) -> Result<(), ()> {
+            match item {
+                ClassSetItem::Literal(lit) => self.0 |= lit.c.is_uppercase(),
+                ClassSetItem::Range(range) => self.0 |= range.start.c.is_uppercase() || range.end.c.is_uppercase(),
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    match ast::parse::Parser::new().parse(piece) {
+        Ok(ast) => ast::visit(&ast, Uppercase(false)).unwrap_or(false),
+        // Look-arounds and backreferences only parse as fancy-regex, which
+        // hands the plain parts of the pattern back as regex-syntax strings
+        Err(_) => match Expr::parse_tree(piece) {
+            Ok(tree) => expr_has_uppercase(&tree.expr),
+            Err(_) => false,
+        },
+    }
+}
+
+fn expr_has_uppercase(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { val, .. } => val.chars().any(char::is_uppercase),
+        Expr::Delegate { inner, .. } => has_uppercase(inner, true),
+        Expr::Concat(children) | Expr::Alt(children) => children.iter().any(expr_has_uppercase),
+        Expr::Group(child) | Expr::LookAround(child, _) | Expr::AtomicGroup(child) | Expr::Repeat { child, .. } => {
+            expr_has_uppercase(child)
+        }
+        Expr::Conditional { condition, true_branch, false_branch } => {
+            [condition, true_branch, false_branch].into_iter().any(|child| expr_has_uppercase(child))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_all(pattern: &str, opts: SearchOptions, haystack: &str) -> Vec<String> {
+        let matcher = build_matcher(pattern, &opts).unwrap();
+        let mut found = Vec::new();
+        matcher
+            .find_iter(haystack.as_bytes(), |m| {
+                found.push(haystack[m.start()..m.end()].to_string());
+                true
+            })
+            .unwrap();
+        found
+    }
+
+    #[test]
+    fn test_pattern_options() {
+        let text = "Foo foo food FOO";
+        assert_eq!(find_all("foo", SearchOptions::default(), text), ["foo", "foo"]);
+        let insensitive = SearchOptions { case_sensitive: Some(false), ..Default::default() };
+        assert_eq!(find_all("foo", insensitive, text).len(), 4);
+
+        let smart = || SearchOptions { smart_case: Some(true), ..Default::default() };
+        assert_eq!(find_all("foo", smart(), text).len(), 4);
+        assert_eq!(find_all("Foo", smart(), text), ["Foo"]);
+        assert_eq!(find_all(r"\Sfoo", smart(), "xFOO").len(), 1);
+
+        let word = SearchOptions { whole_word: Some(true), case_sensitive: Some(false), ..Default::default() };
+        assert_eq!(find_all("foo", word, text), ["Foo", "foo", "FOO"]);
+
+        let literal = SearchOptions { is_regex: Some(false), ..Default::default() };
+        assert_eq!(find_all("a.b(", literal, "axb( a.b("), ["a.b("]);
+    }
+
+    #[test]
+    fn test_multiline() {
+        let matcher = build_matcher(r"foo\nbar", &SearchOptions::default()).unwrap();
+        assert!(matcher.multi_line());
+        assert!(matcher.line_terminator().is_none());
+        assert_eq!(find_all(r"foo\s+bar", SearchOptions { multiline: Some(true), ..Default::default() }, "foo\n bar"), ["foo\n bar"]);
+        assert!(build_matcher("foo", &SearchOptions::default()).unwrap().line_terminator().is_some());
+    }
+
+    #[test]
+    fn test_look_around() {
+        let opts = SearchOptions::default;
+        assert_eq!(find_all(r"(?<=\$)\d+", opts(), "1 $2 3 $45"), ["2", "45"]);
+        assert_eq!(find_all(r"(?<!\$)\b\d+", opts(), "1 $2 3 $45"), ["1", "3"]);
+        assert_eq!(find_all(r"\w+(?=\()", opts(), "call(x) name y()"), ["call", "y"]);
+        assert_eq!(find_all(r"foo(?!bar)", opts(), "foobar foobaz"), ["foo"]);
+        assert_eq!(find_all(r"(?<=^let )\w+", opts(), "let x\nlet y\n let z"), ["x", "y"]);
+        // Line-oriented searches do not look across lines
+        assert!(find_all(r"(?<=a)b", opts(), "a\nb").is_empty());
+        assert_eq!(find_all(r"x(?=\ny)", opts(), "x\ny"), ["x"]);
+        assert_eq!(find_all(r"(?<=A)b", SearchOptions { case_sensitive: Some(false), ..opts() }, "aB"), ["B"]);
+
+        // Look-arounds anywhere, including inside groups and alternations
+        assert!(find_all(r"a(?=b)c", opts(), "abc ac").is_empty());
+        assert_eq!(find_all(r"x|y(?=z)", opts(), "x yz y"), ["x", "y"]);
+        assert_eq!(find_all(r"(?:(?<=\$)|#)\d+", opts(), "$1 #2 3"), ["1", "#2"]);
+        assert_eq!(find_all(r"\w+(?=\()", SearchOptions { whole_word: Some(true), ..opts() }, "call(x) y()"), ["call", "y"]);
+
+        for pattern in [r"(?<=a+)b", r"(?<=a", r"a)"] {
+            let err = build_matcher(pattern, &opts()).unwrap_err();
+            assert_eq!(err.code(), crate::error::ErrorCode::InvalidArgument, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_backreferences() {
+        let opts = SearchOptions::default;
+        assert_eq!(find_all(r"(\w)\1", opts(), "book keeper cat"), ["oo", "ee"]);
+        assert_eq!(find_all(r#"(?P<q>['"]).*?(?P=q)"#, opts(), r#"a 'b' "c" 'd""#), ["'b'", "\"c\""]);
+        // Smart case still sees the literals of patterns only fancy-regex parses
+        let smart = || SearchOptions { smart_case: Some(true), ..opts() };
+        assert_eq!(find_all(r"(a)\1", smart(), "aA AA aa"), ["aA", "AA", "aa"]);
+        assert_eq!(find_all(r"(A)\1", smart(), "aA AA aa"), ["AA"]);
+
+        let matcher = build_matcher(r"(?<word>\w+) \k<word>", &opts()).unwrap();
+        assert_eq!(matcher.capture_index("word"), Some(1));
+        let mut caps = matcher.new_captures().unwrap();
+        // Matches skip invalid UTF-8 and do not span lines
+        assert!(matcher.captures_at(b"\xff\nhi hi", 0, &mut caps).unwrap());
+        assert_eq!((caps.get(0), caps.get(1)), (Some(Match::new(2, 7)), Some(Match::new(2, 4))));
+        assert!(!matcher.captures_at(b"hi\nhi", 0, &mut caps).unwrap());
+    }
+}
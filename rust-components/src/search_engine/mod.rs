@@ -1,192 +1,492 @@
-use napi::bindgen_prelude::*;
+use crate::cancellation::CancelSignal;
+use crate::error::{blocking, CoreError, CoreResult, JsResult};
+use napi::threadsafe_function::{ErrorStrategy, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::{Either, JsUnknown, Status};
 use napi_derive::napi;
-use ignore::WalkBuilder;
-use grep::regex::RegexMatcher;
-use grep::searcher::{BinaryDetection, SearcherBuilder};
-use grep::searcher::SinkMatch;
-use std::sync::{Arc, Mutex};
+use ignore::{WalkBuilder, WalkState};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+mod filter;
+mod fuzzy;
+pub(crate) mod grammar;
+mod hunks;
+pub(crate) mod index;
+mod matcher;
+mod preview;
+mod replace;
+mod structural;
+mod walk;
+use crate::file_operations::encoding;
+use crate::file_watcher::FileChangeEvent;
+use filter::PathFilter;
+use fuzzy::{FileLists, DEFAULT_FUZZY_LIMIT};
+use index::IndexRegistry;
+use matcher::{build_matcher, PatternMatcher};
+use replace::{PendingReplacements, Rewrite, Template};
+use structural::StructuralPattern;
+use walk::{search_paths, search_tree, SearchCounters};
+
+/// A streamed batch is delivered once it holds this many files...
+const STREAM_BATCH_FILES: usize = 50;
+/// ...or this long after the previous one, whichever comes first
+const STREAM_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+/// Unchanged lines shown around each change of a replace preview
+const DEFAULT_DIFF_CONTEXT: u32 = 3;
 
 #[napi]
-pub struct SearchEngine;
+pub struct SearchEngine {
+    replacements: Arc<PendingReplacements>,
+    indexes: Arc<IndexRegistry>,
+    file_lists: Arc<FileLists>,
+}
 
 #[napi]
 impl SearchEngine {
     #[napi(constructor)]
     pub fn new() -> Self {
-        SearchEngine
+        SearchEngine {
+            replacements: Arc::default(),
+            indexes: Arc::default(),
+            file_lists: Arc::default(),
+        }
     }
 
     #[napi]
-    pub async fn search_pattern(&self, root_path: String, pattern: String, options: Option<SearchOptions>) -> Result<Vec<SearchResult>> {
+    pub async fn search_pattern(&self, root_path: String, pattern: String, options: Option<SearchOptions>, token: Option<CancelSignal>) -> JsResult<Vec<SearchResult>> {
         let opts = options.unwrap_or_default();
-        let start = std::time::Instant::now();
-
-        // Build regex matcher
-        let matcher = RegexMatcher::new_line_matcher(&pattern)
-            .map_err(|e| Error::from_reason(format!("Invalid regex pattern: {}", e)))?;
-
-        // Configure walk builder
-        let mut builder = WalkBuilder::new(&root_path);
-        builder
-            .hidden(!opts.include_hidden.unwrap_or(false))
-            .ignore(!opts.disable_ignore.unwrap_or(false))
-            .git_ignore(!opts.disable_gitignore.unwrap_or(false))
-            .max_depth(opts.max_depth.map(|d| d as usize))
-            .threads(num_cpus::get());
-
-        if let Some(ref globs) = opts.include_patterns {
-            for glob in globs {
-                builder.add(glob);
-            }
-        }
+        let signal = token.unwrap_or_default();
+        let start = Instant::now();
+        let indexes = Arc::clone(&self.indexes);
+
+        blocking(move || {
+            let matcher = build_matcher(&pattern, &opts)?;
+            let filter = PathFilter::new(Path::new(&root_path), &opts)?;
+            let results = Mutex::new(Vec::new());
+            let counters = SearchCounters::default();
+
+            run_search(&indexes, Path::new(&root_path), &pattern, &matcher, &filter, &opts, &signal, &counters, |result| {
+                results.lock().unwrap().push(result);
+            });
+            signal.check()?;
 
-        if let Some(ref globs) = opts.exclude_patterns {
-            for glob in globs {
-                builder.add(&format!("!{}", glob));
-            }
-        }
+            let results = results.into_inner().unwrap();
+            tracing::debug!("Search completed in {:?}, found {} files with matches", start.elapsed(), results.len());
 
-        let results = Arc::new(Mutex::new(Vec::new()));
-        let results_clone = Arc::clone(&results);
-
-        // Parallel walk and search
-        builder.build_parallel().run(|| {
-            let matcher = matcher.clone();
-            let results = Arc::clone(&results_clone);
-
-            Box::new(move |result| {
-                if let Ok(entry) = result {
-                    if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                        if let Ok(path) = entry.path().canonicalize() {
-                            let path_str = path.to_string_lossy().to_string();
-
-                            // Search in file
-                            if let Ok(matches) = search_in_file(&path_str, &matcher) {
-                                if !matches.is_empty() {
-                                    let mut results = results.lock().unwrap();
-                                    results.push(SearchResult {
-                                        file_path: path_str,
-                                        matches,
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
-                ignore::WalkState::Continue
+            Ok(results)
+        })
+        .await
+        .into()
+    }
+
+    /// Search like `search_pattern`, but deliver results to `callback` in batches
+    /// while the walk is still running. Each batch carries the progress so far;
+    /// the returned promise resolves with the final totals after the last batch
+    /// has been handled.
+    #[napi(ts_args_type = "rootPath: string, pattern: string, options: SearchOptions | undefined | null, callback: (err: Error | null, batch: SearchBatch) => void, token?: CancellationToken | undefined | null")]
+    pub async fn search_pattern_stream(
+        &self,
+        root_path: String,
+        pattern: String,
+        options: Option<SearchOptions>,
+        callback: ThreadsafeFunction<SearchBatch, ErrorStrategy::CalleeHandled>,
+        token: Option<CancelSignal>,
+    ) -> JsResult<SearchSummary> {
+        let opts = options.unwrap_or_default();
+        let signal = token.unwrap_or_default();
+        let indexes = Arc::clone(&self.indexes);
+
+        blocking(move || {
+            let root = PathBuf::from(root_path);
+            let matcher = build_matcher(&pattern, &opts)?;
+            let filter = PathFilter::new(&root, &opts)?;
+            stream_search(&signal, &callback, |counters, on_result| {
+                run_search(&indexes, &root, &pattern, &matcher, &filter, &opts, &signal, counters, on_result);
             })
-        });
+        })
+        .await
+        .into()
+    }
 
-        let duration = start.elapsed();
-        let results = Arc::try_unwrap(results).map_err(|_| Error::from_reason("Failed to unwrap results"))?.into_inner().map_err(|_| Error::from_reason("Failed to access results"))?;
+    /// Structural search: find code matching `pattern`, a snippet of the VS Code
+    /// language `language_id` (TypeScript, JavaScript, Rust, Python or Go) in which
+    /// `$NAME` stands for any single syntax node and `$$$NAME` for any number
+    /// of them, e.g. `foo($A, $B)` for calls to `foo` with two arguments. Only
+    /// files of that language are searched, and results are streamed like
+    /// those of `search_pattern_stream`. The pattern and context options of
+    /// `options` do not apply.
+    #[napi(ts_args_type = "rootPath: string, pattern: string, languageId: string, options: SearchOptions | undefined | null, callback: (err: Error | null, batch: SearchBatch) => void, token?: CancellationToken | undefined | null")]
+    pub async fn search_structural_stream(
+        &self,
+        root_path: String,
+        pattern: String,
+        language_id: String,
+        options: Option<SearchOptions>,
+        callback: ThreadsafeFunction<SearchBatch, ErrorStrategy::CalleeHandled>,
+        token: Option<CancelSignal>,
+    ) -> JsResult<SearchSummary> {
+        let opts = options.unwrap_or_default();
+        let signal = token.unwrap_or_default();
+
+        blocking(move || {
+            let root = PathBuf::from(root_path);
+            let pattern = StructuralPattern::parse(&pattern, &language_id)?;
+            let filter = PathFilter::new(&root, &opts)?;
+            stream_search(&signal, &callback, |counters, on_result| {
+                search_tree(&root, &pattern, &filter, &opts, &signal, counters, on_result);
+            })
+        })
+        .await
+        .into()
+    }
+
+    /// Preview replacing every match of `pattern` with `replacement` as diff
+    /// hunks per file. Nothing is written until `apply_replace` is called with
+    /// the returned id; previews are kept until applied or discarded.
+    #[napi]
+    pub async fn replace_preview(
+        &self,
+        root_path: String,
+        pattern: String,
+        replacement: String,
+        options: Option<SearchOptions>,
+        replace_options: Option<ReplaceOptions>,
+        token: Option<CancelSignal>,
+    ) -> JsResult<ReplacePreview> {
+        // Every match of a file is replaced, so limits and context do not apply
+        let opts = SearchOptions { max_results: None, before_context: None, after_context: None, ..options.unwrap_or_default() };
+        let replace_opts = replace_options.unwrap_or_default();
+        let signal = token.unwrap_or_default();
+        let pending = Arc::clone(&self.replacements);
+        let indexes = Arc::clone(&self.indexes);
+
+        blocking(move || {
+            let matcher = build_matcher(&pattern, &opts)?;
+            let template = if opts.is_regex.unwrap_or(true) {
+                Template::parse(&replacement, &matcher)
+            } else {
+                Template::literal(&replacement)
+            };
+            let rewrite = Rewrite {
+                matcher,
+                template,
+                preserve_case: replace_opts.preserve_case.unwrap_or(false),
+                encoding: opts.encoding.as_deref().and_then(encoding::for_label),
+            };
+            let context_lines = replace_opts.context_lines.unwrap_or(DEFAULT_DIFF_CONTEXT) as usize;
+            let filter = PathFilter::new(Path::new(&root_path), &opts)?;
+
+            let previews = Mutex::new(Vec::new());
+            let counters = SearchCounters::default();
+            run_search(&indexes, Path::new(&root_path), &pattern, &rewrite.matcher, &filter, &opts, &signal, &counters, |result| {
+                match PendingReplacements::preview_file(&rewrite, &result.file_path, context_lines) {
+                    Ok(Some(preview)) => previews.lock().unwrap().push(preview),
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Skipping {} in replace preview: {}", result.file_path, e),
+                }
+            });
+            signal.check()?;
+
+            let mut previews = previews.into_inner().unwrap();
+            previews.sort_by(|(a, _), (b, _)| a.file_path.cmp(&b.file_path));
+            let versions = previews.iter().map(|(file, version)| (file.file_path.clone(), *version)).collect();
+            let files: Vec<FileReplacePreview> = previews.into_iter().map(|(file, _)| file).collect();
+            let replacement_count = files.iter().map(|file| file.replacement_count).sum();
+
+            Ok(ReplacePreview { id: pending.insert(rewrite, versions), files, replacement_count })
+        })
+        .await
+        .into()
+    }
 
-        tracing::debug!("Search completed in {:?}, found {} files with matches", duration, results.len());
+    /// Write the replacements of a preview, optionally only for some of its
+    /// files. Fails with `FileModified` before writing anything if a file
+    /// changed since the preview; if a write fails, files already written are
+    /// restored. The preview cannot be applied twice.
+    #[napi]
+    pub async fn apply_replace(&self, preview_id: u32, file_paths: Option<Vec<String>>, token: Option<CancelSignal>) -> JsResult<ReplaceSummary> {
+        let pending = Arc::clone(&self.replacements);
+        let signal = token.unwrap_or_default();
 
-        Ok(results)
+        blocking(move || pending.apply(preview_id, file_paths, &signal)).await.into()
     }
 
+    /// Drop a preview without applying it
     #[napi]
-    pub async fn search_files(&self, root_path: String, file_pattern: String) -> Result<Vec<String>> {
-        let start = std::time::Instant::now();
-
-        let mut builder = WalkBuilder::new(&root_path);
-        builder
-            .hidden(false)
-            .ignore(false)
-            .git_ignore(true)
-            .threads(num_cpus::get());
-
-        let pattern = regex::Regex::new(&file_pattern)
-            .map_err(|e| Error::from_reason(format!("Invalid file pattern: {}", e)))?;
-
-        let files = Arc::new(Mutex::new(Vec::new()));
-        let files_clone = Arc::clone(&files);
-
-        builder.build_parallel().run(|| {
-            let pattern = pattern.clone();
-            let files = Arc::clone(&files_clone);
-
-            Box::new(move |result| {
-                if let Ok(entry) = result {
-                    if entry.file_type().map_or(false, |ft| ft.is_file()) {
-                        let path = entry.path();
-                        if let Some(file_name) = path.file_name() {
-                            if pattern.is_match(&file_name.to_string_lossy()) {
-                                if let Ok(canonical) = path.canonicalize() {
-                                    let mut files = files.lock().unwrap();
-                                    files.push(canonical.to_string_lossy().to_string());
-                                }
-                            }
-                        }
-                    }
-                }
-                ignore::WalkState::Continue
-            })
-        });
+    pub fn discard_replace(&self, preview_id: u32) -> bool {
+        self.replacements.discard(preview_id)
+    }
 
-        let duration = start.elapsed();
-        let files = Arc::try_unwrap(files).map_err(|_| Error::from_reason("Failed to unwrap files"))?.into_inner().map_err(|_| Error::from_reason("Failed to access files"))?;
+    /// Build a trigram index of `root_path` so searches below it only read files
+    /// that can match. With `storage_dir` the index is saved there and reused by
+    /// later calls, rereading only files whose size or mtime changed. Searches
+    /// that include hidden or ignored files, or limit the depth, still walk.
+    #[napi]
+    pub async fn enable_index(&self, root_path: String, options: Option<IndexOptions>, token: Option<CancelSignal>) -> JsResult<IndexStats> {
+        let opts = options.unwrap_or_default();
+        let signal = token.unwrap_or_default();
+        let indexes = Arc::clone(&self.indexes);
 
-        tracing::debug!("File search completed in {:?}, found {} files", duration, files.len());
+        blocking(move || indexes.enable(&root_path, &opts, &signal)).await.into()
+    }
 
-        Ok(files)
+    /// Bring an enabled index up to date with file watcher events
+    #[napi]
+    pub async fn update_index(&self, root_path: String, changes: Vec<FileChangeEvent>, token: Option<CancelSignal>) -> JsResult<IndexStats> {
+        let signal = token.unwrap_or_default();
+        let indexes = Arc::clone(&self.indexes);
+
+        blocking(move || indexes.update(&root_path, &changes, &signal)).await.into()
     }
-}
 
-fn search_in_file(path: &str, matcher: &RegexMatcher) -> Result<Vec<Match>> {
-    use std::fs::File;
-    use std::io::BufReader;
-    use grep::searcher::Sink;
+    /// Save and drop the index of `root_path`. Resolves to false if there was none.
+    #[napi]
+    pub async fn disable_index(&self, root_path: String) -> JsResult<bool> {
+        let indexes = Arc::clone(&self.indexes);
 
-    let file = File::open(path)
-        .map_err(|e| Error::from_reason(format!("Failed to open file: {}", e)))?;
+        blocking(move || indexes.disable(&root_path)).await.into()
+    }
 
-    let mut matches = Vec::new();
+    /// Quick Open: rank the files below `root_path` by how well their relative
+    /// paths fuzzy-match `query`, best first. The file list is walked on first
+    /// use and kept in memory; keep it current with `update_file_list`.
+    #[napi]
+    pub async fn fuzzy_find_files(&self, root_path: String, query: String, limit: Option<u32>, token: Option<CancelSignal>) -> JsResult<Vec<FuzzyMatch>> {
+        let signal = token.unwrap_or_default();
+        let file_lists = Arc::clone(&self.file_lists);
+        let limit = limit.unwrap_or(DEFAULT_FUZZY_LIMIT) as usize;
+
+        blocking(move || file_lists.find(&root_path, &query, limit, &signal)).await.into()
+    }
+
+    /// Apply file watcher events to the Quick Open list of `root_path`.
+    /// Resolves to the number of files listed, or 0 if no list is kept.
+    #[napi]
+    pub async fn update_file_list(&self, root_path: String, changes: Vec<FileChangeEvent>, token: Option<CancelSignal>) -> JsResult<u32> {
+        let signal = token.unwrap_or_default();
+        let file_lists = Arc::clone(&self.file_lists);
 
-    struct MatchSink<'a> {
-        matches: &'a mut Vec<Match>,
+        blocking(move || file_lists.update(&root_path, &changes, &signal)).await.into()
     }
 
-        impl<'a> Sink for MatchSink<'a> {
-        type Error = std::io::Error;
+    /// Drop the Quick Open list of `root_path`. Resolves to false if there was none.
+    #[napi]
+    pub async fn forget_file_list(&self, root_path: String) -> JsResult<bool> {
+        let file_lists = Arc::clone(&self.file_lists);
+
+        blocking(move || file_lists.forget(&root_path)).await.into()
+    }
 
-        fn matched(
-            &mut self,
-            _searcher: &grep::searcher::Searcher,
-            mat: &SinkMatch<'_>,
-        ) -> std::result::Result<bool, std::io::Error> {
-            self.matches.push(Match {
-                line_number: mat.line_number().unwrap_or(0) as f64,
-                column_start: mat.absolute_byte_offset() as f64,
-                column_end: (mat.absolute_byte_offset() + mat.bytes().len() as u64) as f64,
-                text: String::from_utf8_lossy(mat.bytes()).to_string(),
+    #[napi]
+    pub async fn search_files(&self, root_path: String, file_pattern: String, token: Option<CancelSignal>) -> JsResult<Vec<String>> {
+        let start = Instant::now();
+        let signal = token.unwrap_or_default();
+
+        blocking(move || {
+            let pattern = regex::Regex::new(&file_pattern)
+                .map_err(|e| CoreError::InvalidArgument(format!("Invalid file pattern: {}", e)))?;
+
+            let mut builder = WalkBuilder::new(&root_path);
+            builder
+                .hidden(false)
+                .ignore(false)
+                .git_ignore(true)
+                .threads(num_cpus::get());
+
+            let files = Mutex::new(Vec::new());
+            builder.build_parallel().run(|| {
+                let pattern = pattern.clone();
+                let files = &files;
+                let signal = signal.clone();
+
+                Box::new(move |result| {
+                    if signal.is_cancelled() {
+                        return WalkState::Quit;
+                    }
+                    if let Ok(entry) = result {
+                        if entry.file_type().is_some_and(|ft| ft.is_file()) {
+                            let path = entry.path();
+                            if let Some(file_name) = path.file_name() {
+                                if pattern.is_match(&file_name.to_string_lossy()) {
+                                    files.lock().unwrap().push(path.to_string_lossy().to_string());
+                                }
+                            }
+                        }
+                    }
+                    WalkState::Continue
+                })
             });
-            Ok(true)
-        }
+            signal.check()?;
+
+            let files = files.into_inner().unwrap();
+            tracing::debug!("File search completed in {:?}, found {} files", start.elapsed(), files.len());
+
+            Ok(files)
+        })
+        .await
+        .into()
+    }
+}
+
+/// Search the candidates of the trigram index covering `root` if there is one,
+/// otherwise walk the tree
+#[allow(clippy::too_many_arguments)]
+fn run_search<F>(
+    indexes: &IndexRegistry,
+    root: &Path,
+    pattern: &str,
+    matcher: &PatternMatcher,
+    filter: &PathFilter,
+    opts: &SearchOptions,
+    signal: &CancelSignal,
+    counters: &SearchCounters,
+    on_result: F,
+) where
+    F: Fn(SearchResult) + Sync,
+{
+    match indexes.candidates(root, pattern, filter, opts) {
+        Some(paths) => search_paths(&paths, matcher, opts, signal, counters, on_result),
+        None => search_tree(root, matcher, filter, opts, signal, counters, on_result),
     }
+}
 
-    let mut sink = MatchSink { matches: &mut matches };
+/// Run `search` on a scoped thread and forward the results it reports to
+/// `callback` in batches
+fn stream_search<S>(
+    signal: &CancelSignal,
+    callback: &ThreadsafeFunction<SearchBatch, ErrorStrategy::CalleeHandled>,
+    search: S,
+) -> CoreResult<SearchSummary>
+where
+    S: FnOnce(&SearchCounters, &(dyn Fn(SearchResult) + Sync)) + Send,
+{
+    let start = Instant::now();
+    let counters = SearchCounters::default();
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            search(&counters, &move |result| {
+                let _ = tx.send(result);
+            });
+        });
 
-    SearcherBuilder::new()
-        .binary_detection(BinaryDetection::quit(b'\x00'))
-        .line_number(true)
-        .build()
-        .search_reader(matcher, BufReader::new(file), &mut sink)
-        .map_err(|e| Error::from_reason(format!("Search failed: {}", e)))?;
+        let mut batch = Vec::new();
+        let mut last_flush = Instant::now();
+        let mut last_scanned = 0;
+        loop {
+            let finished = match rx.recv_timeout(STREAM_BATCH_INTERVAL.saturating_sub(last_flush.elapsed())) {
+                Ok(result) => {
+                    batch.push(result);
+                    false
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => false,
+                Err(mpsc::RecvTimeoutError::Disconnected) => true,
+            };
+
+            if finished || batch.len() >= STREAM_BATCH_FILES || last_flush.elapsed() >= STREAM_BATCH_INTERVAL {
+                let progress = counters.progress();
+                let changed = !batch.is_empty() || progress.files_scanned != last_scanned;
+                if changed && !signal.is_cancelled() {
+                    last_scanned = progress.files_scanned;
+                    deliver(callback, SearchBatch { results: std::mem::take(&mut batch), progress });
+                }
+                last_flush = Instant::now();
+            }
+            if finished {
+                break;
+            }
+        }
+    });
+    signal.check()?;
+
+    let progress = counters.progress();
+    tracing::debug!("Streaming search completed in {:?}, found {} files with matches", start.elapsed(), progress.files_matched);
+
+    Ok(SearchSummary {
+        files_scanned: progress.files_scanned,
+        files_matched: progress.files_matched,
+        match_count: counters.match_count(),
+        truncated: counters.truncated(),
+        duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+    })
+}
 
-    Ok(matches)
+/// Hand a batch to JS and wait until the callback has run, so batches arrive in
+/// order, before the final summary, and a slow consumer throttles the search
+fn deliver(callback: &ThreadsafeFunction<SearchBatch, ErrorStrategy::CalleeHandled>, batch: SearchBatch) {
+    let (ack, acked) = mpsc::sync_channel::<()>(1);
+    let status = callback.call_with_return_value(Ok(batch), ThreadsafeFunctionCallMode::Blocking, move |_: JsUnknown| {
+        let _ = ack.send(());
+        Ok(())
+    });
+    if status == Status::Ok {
+        // Also returns when the callback threw and the acknowledgement was dropped
+        let _ = acked.recv();
+    }
 }
 
 #[napi(object)]
 #[derive(Default)]
 pub struct SearchOptions {
+    /// Treat the pattern as a regex rather than a literal string (default: true)
+    pub is_regex: Option<bool>,
+    /// Match case exactly (default: true); `false` ignores case
     pub case_sensitive: Option<bool>,
+    /// Unless `case_sensitive` is true, ignore case when the pattern has no uppercase letters
+    pub smart_case: Option<bool>,
+    /// Only match at word boundaries
+    pub whole_word: Option<bool>,
+    /// Let matches span lines; implied by patterns containing `\n`
+    pub multiline: Option<bool>,
     pub include_hidden: Option<bool>,
     pub disable_ignore: Option<bool>,
     pub disable_gitignore: Option<bool>,
     pub max_depth: Option<i32>,
+    /// Descend into symlinked folders and search symlinked files (default: false).
+    /// Links back into a folder that is already being walked are skipped.
+    pub follow_symlinks: Option<bool>,
+    /// Skip files larger than this many bytes
+    pub max_filesize: Option<f64>,
+    /// Only search files matching one of these globs. As in VS Code's search view,
+    /// `*.ts` or `src` match at any depth unless anchored with `./`
     pub include_patterns: Option<Vec<String>>,
+    /// Skip files and folders matching these globs, expanded like `include_patterns`
     pub exclude_patterns: Option<Vec<String>>,
+    /// `files.exclude` style globs relative to the root
+    pub exclude: Option<ExcludeRules>,
+    /// `files.exclude` style globs relative to folders below the root
+    pub folder_excludes: Option<Vec<FolderExclude>>,
+    /// Stop once this many matches have been found and flag the search as truncated
+    pub max_results: Option<u32>,
+    /// Lines of context to report before each match
+    pub before_context: Option<u32>,
+    /// Lines of context to report after each match
+    pub after_context: Option<u32>,
+    /// Trim match previews and context lines to this many UTF-16 code units (default: 1000, 0 disables trimming)
+    pub preview_chars_per_line: Option<u32>,
+    /// VS Code encoding id or WHATWG label of files without a BOM; unless set
+    /// or unknown, each file's encoding is guessed
+    pub encoding: Option<String>,
+}
+
+/// Globs mapped to `true` to exclude what they match, or to a clause that
+/// only excludes matches with a certain sibling, like VS Code's `files.exclude`
+pub type ExcludeRules = HashMap<String, Either<bool, ExcludeClause>>;
+
+#[napi(object)]
+pub struct ExcludeClause {
+    /// The sibling a match needs to be excluded, e.g. `$(basename).ts` for `*.js`
+    pub when: String,
+}
+
+#[napi(object)]
+pub struct FolderExclude {
+    /// Relative to the searched root
+    pub folder: String,
+    pub exclude: ExcludeRules,
 }
 
 #[napi(object)]
@@ -194,13 +494,135 @@ pub struct SearchOptions {
 pub struct SearchResult {
     pub file_path: String,
     pub matches: Vec<Match>,
+    /// Lines around the matches requested through `before_context`/`after_context`, in file order
+    pub context: Vec<ContextLine>,
 }
 
+/// One occurrence of the pattern. Lines are 1-based, columns are 0-based UTF-16 offsets.
 #[napi(object)]
 #[derive(Debug)]
 pub struct Match {
     pub line_number: f64,
     pub column_start: f64,
+    /// Differs from `line_number` for multiline matches
+    pub end_line_number: f64,
     pub column_end: f64,
+    /// The matched line(s), trimmed around the match when longer than `preview_chars_per_line`
     pub text: String,
+    /// Where the match lies within `text`
+    pub preview_column_start: f64,
+    pub preview_column_end: f64,
+}
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct ContextLine {
+    pub line_number: f64,
+    pub text: String,
+}
+
+#[napi(object)]
+#[derive(Debug, Clone, Copy)]
+pub struct SearchProgress {
+    pub files_scanned: u32,
+    pub files_matched: u32,
+}
+
+#[napi(object)]
+pub struct SearchBatch {
+    pub results: Vec<SearchResult>,
+    pub progress: SearchProgress,
+}
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct SearchSummary {
+    pub files_scanned: u32,
+    pub files_matched: u32,
+    pub match_count: u32,
+    /// The walk stopped early because `max_results` was reached
+    pub truncated: bool,
+    pub duration_ms: f64,
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct ReplaceOptions {
+    /// Keep the casing of each match, e.g. `foo`/`Foo`/`FOO` become `bar`/`Bar`/`BAR`
+    pub preserve_case: Option<bool>,
+    /// Unchanged lines shown around each change (default: 3)
+    pub context_lines: Option<u32>,
+}
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct ReplacePreview {
+    /// Pass to `apply_replace` or `discard_replace`
+    pub id: u32,
+    pub files: Vec<FileReplacePreview>,
+    pub replacement_count: u32,
+}
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct FileReplacePreview {
+    pub file_path: String,
+    pub replacement_count: u32,
+    pub hunks: Vec<DiffHunk>,
+}
+
+/// A unified diff hunk in jsdiff's `structuredPatch` shape: `lines` start with
+/// `' '`, `'-'` or `'+'`, and line numbers are 1-based
+#[napi(object)]
+#[derive(Debug)]
+pub struct DiffHunk {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<String>,
+}
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct ReplaceSummary {
+    pub files_changed: u32,
+    pub replacement_count: u32,
+}
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct FuzzyMatch {
+    /// Relative to the searched root, with `/` separators
+    pub path: String,
+    pub score: i32,
+    /// The matched characters of `path` as UTF-16 ranges
+    pub highlights: Vec<HighlightRange>,
+}
+
+/// A half-open `[start, end)` range of UTF-16 code units
+#[napi(object)]
+#[derive(Debug)]
+pub struct HighlightRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct IndexOptions {
+    /// Folder to save the index in; without it the index lives in memory only
+    pub storage_dir: Option<String>,
+    /// Larger files are not indexed and always searched (default: 16 MiB)
+    pub max_file_size: Option<f64>,
+}
+
+#[napi(object)]
+#[derive(Debug)]
+pub struct IndexStats {
+    pub files: u32,
+    pub trigrams: u32,
+    /// Binary or oversized files, searched by every query
+    pub unindexed_files: u32,
+    pub duration_ms: f64,
 }
@@ -0,0 +1,123 @@
+use super::{ContextLine, Match};
+use std::ops::Range;
+
+/// Preview lines are cut down to this many UTF-16 code units around the match,
+/// like the `charsPerLine` VS Code's search view asks ripgrep for
+pub const DEFAULT_PREVIEW_CHARS: u32 = 1000;
+
+/// Describe the match at `range` of `lines`, whose first line is `first_line`,
+/// in the editor's terms: 1-based lines and UTF-16 columns
+pub fn build_match(lines: &[u8], first_line: u64, range: Range<usize>, chars_per_line: usize) -> Match {
+    let start_line = line_start(lines, range.start);
+    let end_line = line_start(lines, range.end);
+    let preview_end = lines[range.end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(lines.len(), |i| range.end + i);
+
+    let column_start = utf16_len(&lines[start_line..range.start]);
+    let column_end = utf16_len(&lines[end_line..range.end]);
+    let line_number = first_line + count_lines(&lines[..range.start]);
+    let end_line_number = line_number + count_lines(&lines[range.start..range.end]);
+
+    let mut preview = lossy(trim_line_end(&lines[start_line..preview_end]));
+    let mut preview_column_start = column_start;
+    let mut preview_column_end = utf16_len(&lines[start_line..range.end]);
+
+    // Only single-line previews are trimmed, as in VS Code's `TextSearchMatch`
+    if line_number == end_line_number && chars_per_line > 0 && preview.encode_utf16().count() > chars_per_line {
+        let from = column_start.saturating_sub(chars_per_line / 5);
+        preview = utf16_slice(&preview, from, chars_per_line);
+        preview_column_start -= from;
+        preview_column_end = (preview_column_end - from).min(preview.encode_utf16().count());
+    }
+
+    Match {
+        line_number: line_number as f64,
+        column_start: column_start as f64,
+        end_line_number: end_line_number as f64,
+        column_end: column_end as f64,
+        text: preview,
+        preview_column_start: preview_column_start as f64,
+        preview_column_end: preview_column_end as f64,
+    }
+}
+
+/// A context line with its terminator removed and its tail cut off past `chars_per_line`
+pub fn build_context_line(line: &[u8], line_number: u64, chars_per_line: usize) -> ContextLine {
+    let mut text = lossy(trim_line_end(line));
+    if chars_per_line > 0 && text.encode_utf16().count() > chars_per_line {
+        text = utf16_slice(&text, 0, chars_per_line);
+    }
+    ContextLine { line_number: line_number as f64, text }
+}
+
+fn line_start(bytes: &[u8], offset: usize) -> usize {
+    bytes[..offset].iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1)
+}
+
+fn count_lines(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|&&b| b == b'\n').count() as u64
+}
+
+fn trim_line_end(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn utf16_len(bytes: &[u8]) -> usize {
+    String::from_utf8_lossy(bytes).encode_utf16().count()
+}
+
+fn utf16_slice(text: &str, from: usize, len: usize) -> String {
+    let units: Vec<u16> = text.encode_utf16().skip(from).take(len).collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_utf16_columns() {
+        let lines = "let 🦀 = \"héllo\";\r\n".as_bytes();
+        let start = lines.windows(5).position(|w| w == "héll".as_bytes()).unwrap();
+        let m = build_match(lines, 7, start..start + "héllo".len(), 1000);
+
+        assert_eq!(m.line_number, 7.0);
+        assert_eq!(m.end_line_number, 7.0);
+        // The crab is two UTF-16 units, the accented e one
+        assert_eq!((m.column_start, m.column_end), (10.0, 15.0));
+        assert_eq!(m.text, "let 🦀 = \"héllo\";");
+        assert_eq!((m.preview_column_start, m.preview_column_end), (10.0, 15.0));
+    }
+
+    #[test]
+    fn test_multiline_match() {
+        let lines = b"fn main() {\n    run();\n}\n";
+        let m = build_match(lines, 3, 10..20, 1000);
+
+        assert_eq!((m.line_number, m.column_start), (3.0, 10.0));
+        assert_eq!((m.end_line_number, m.column_end), (4.0, 8.0));
+        assert_eq!(m.text, "fn main() {\n    run();");
+        assert_eq!((m.preview_column_start, m.preview_column_end), (10.0, 20.0));
+    }
+
+    #[test]
+    fn test_long_line_preview_is_trimmed() {
+        let line = format!("{}needle{}", "a".repeat(500), "b".repeat(500));
+        let m = build_match(line.as_bytes(), 1, 500..506, 100);
+
+        assert_eq!(m.column_start, 500.0);
+        assert_eq!(m.text.len(), 100);
+        assert_eq!(m.text, format!("{}needle{}", "a".repeat(20), "b".repeat(74)));
+        assert_eq!((m.preview_column_start, m.preview_column_end), (20.0, 26.0));
+
+        let context = build_context_line(line.as_bytes(), 2, 100);
+        assert_eq!(context.text, "a".repeat(100));
+    }
+}
@@ -0,0 +1,535 @@
+use super::hunks::build_hunks;
+use super::matcher::PatternMatcher;
+use super::{FileReplacePreview, ReplaceSummary};
+use crate::cancellation::CancelSignal;
+use crate::error::{CoreError, CoreResult};
+use crate::file_operations::{encoding, write_atomic, DEFAULT_ATOMIC_POSTFIX};
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use grep::matcher::{Captures, Matcher};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+/// Case changes requested by `\u`, `\l`, `\U` and `\L` for the next group
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CaseOp {
+    UpperNext,
+    LowerNext,
+    Upper,
+    Lower,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Piece {
+    Literal(String),
+    Group { index: usize, case: Vec<CaseOp> },
+}
+
+/// A replacement string parsed like VS Code's `ReplacePattern`: `$1`, `$&`,
+/// `$<name>`/`${name}`, `$$`, `\n`, `\t` and the case operators `\u\l\U\L`
+#[derive(Clone, Debug)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    /// Replacements of literal searches are used as is
+    pub fn literal(replacement: &str) -> Self {
+        Template { pieces: vec![Piece::Literal(replacement.to_string())] }
+    }
+
+    pub fn parse(replacement: &str, matcher: &PatternMatcher) -> Self {
+        let group_count = matcher.capture_count();
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut case = Vec::new();
+        let mut chars = replacement.chars().peekable();
+
+        let push_group = |pieces: &mut Vec<Piece>, literal: &mut String, index: usize, case: &mut Vec<CaseOp>| {
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(literal)));
+            }
+            pieces.push(Piece::Group { index, case: std::mem::take(case) });
+        };
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => literal.push('\n'),
+                    Some('t') => literal.push('\t'),
+                    Some('\\') => literal.push('\\'),
+                    Some('u') => case.push(CaseOp::UpperNext),
+                    Some('l') => case.push(CaseOp::LowerNext),
+                    Some('U') => case.push(CaseOp::Upper),
+                    Some('L') => case.push(CaseOp::Lower),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '$' => match chars.peek().copied() {
+                    Some('$') => {
+                        chars.next();
+                        literal.push('$');
+                    }
+                    Some('&') => {
+                        chars.next();
+                        push_group(&mut pieces, &mut literal, 0, &mut case);
+                    }
+                    Some(d) if d.is_ascii_digit() => {
+                        chars.next();
+                        let mut index = d as usize - '0' as usize;
+                        // Two digits only when that names an existing group, as in JS
+                        if let Some(d2) = chars.peek().copied().filter(char::is_ascii_digit) {
+                            let two = index * 10 + (d2 as usize - '0' as usize);
+                            if two < group_count {
+                                chars.next();
+                                index = two;
+                            }
+                        }
+                        if index < group_count {
+                            push_group(&mut pieces, &mut literal, index, &mut case);
+                        } else {
+                            literal.push('$');
+                            literal.push_str(&index.to_string());
+                        }
+                    }
+                    Some(open @ ('<' | '{')) => {
+                        let close = if open == '<' { '>' } else { '}' };
+                        let ahead: String = chars.clone().skip(1).collect();
+                        let group = ahead.find(close).and_then(|end| {
+                            let name = &ahead[..end];
+                            let index = name.parse().ok().filter(|&i| i < group_count).or_else(|| matcher.capture_index(name));
+                            index.map(|index| (index, name.chars().count() + 2))
+                        });
+                        match group {
+                            Some((index, consumed)) => {
+                                for _ in 0..consumed {
+                                    chars.next();
+                                }
+                                push_group(&mut pieces, &mut literal, index, &mut case);
+                            }
+                            None => literal.push('$'),
+                        }
+                    }
+                    _ => literal.push('$'),
+                },
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Template { pieces }
+    }
+
+    fn expand(&self, haystack: &[u8], caps: &impl Captures, dst: &mut Vec<u8>) {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(text) => dst.extend_from_slice(text.as_bytes()),
+                Piece::Group { index, case } => {
+                    let Some(m) = caps.get(*index) else { continue };
+                    if case.is_empty() {
+                        dst.extend_from_slice(&haystack[m]);
+                    } else {
+                        dst.extend_from_slice(apply_case(&String::from_utf8_lossy(&haystack[m]), case).as_bytes());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Apply case operators char by char: `\u`/`\l` change one char, `\U`/`\L` the rest
+fn apply_case(value: &str, ops: &[CaseOp]) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut op = 0;
+    for (i, c) in value.char_indices() {
+        match ops.get(op) {
+            None => {
+                result.push_str(&value[i..]);
+                break;
+            }
+            Some(CaseOp::Upper) => result.extend(c.to_uppercase()),
+            Some(CaseOp::Lower) => result.extend(c.to_lowercase()),
+            Some(CaseOp::UpperNext) => {
+                result.extend(c.to_uppercase());
+                op += 1;
+            }
+            Some(CaseOp::LowerNext) => {
+                result.extend(c.to_lowercase());
+                op += 1;
+            }
+        }
+    }
+    result
+}
+
+/// Port of VS Code's `buildReplaceStringWithCasePreserved`: make `replacement`
+/// follow the casing of `matched`, part by part for `-` and `_` separated words
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    let Some(first) = matched.chars().next() else {
+        return replacement.to_string();
+    };
+
+    let same_parts = |sep: char| {
+        matched.contains(sep) && replacement.contains(sep) && matched.split(sep).count() == replacement.split(sep).count()
+    };
+    let (hyphens, underscores) = (same_parts('-'), same_parts('_'));
+    if hyphens != underscores {
+        let sep = if hyphens { '-' } else { '_' };
+        let parts: Vec<String> = matched.split(sep).zip(replacement.split(sep)).map(|(m, r)| preserve_case(m, r)).collect();
+        return parts.join(&sep.to_string());
+    }
+
+    let capitalize = |upper: bool| {
+        let mut chars = replacement.chars();
+        match chars.next() {
+            Some(c) if upper => c.to_uppercase().chain(chars).collect(),
+            Some(c) => c.to_lowercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    };
+    if matched.to_uppercase() == matched {
+        replacement.to_uppercase()
+    } else if matched.to_lowercase() == matched {
+        replacement.to_lowercase()
+    } else if first.is_uppercase() {
+        capitalize(true)
+    } else if first.is_lowercase() {
+        capitalize(false)
+    } else {
+        replacement.to_string()
+    }
+}
+
+/// One replacement: `range` of the original content becomes `replacement`
+#[derive(Debug)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub replacement: Vec<u8>,
+}
+
+/// How to rewrite the matches of one search
+#[derive(Clone)]
+pub struct Rewrite {
+    pub matcher: PatternMatcher,
+    pub template: Template,
+    pub preserve_case: bool,
+    /// The search's encoding for files without a BOM, if one was given
+    pub encoding: Option<&'static Encoding>,
+}
+
+impl Rewrite {
+    /// The text of `content` as search matches it, transcoded to UTF-8 like
+    /// search does, and the encoding to write the result back in. Files
+    /// whose text would not encode back to the same bytes are refused.
+    fn decode<'c>(&self, path: &str, content: &'c [u8]) -> CoreResult<(Cow<'c, [u8]>, Option<FileEncoding>)> {
+        let head = &content[..content.len().min(encoding::SNIFF_LEN)];
+        let detected = encoding::detect_file(head, head.len() == content.len(), self.encoding);
+        let Some(detected) = detected.filter(|&e| e != UTF_8) else {
+            return Ok((Cow::Borrowed(content), None));
+        };
+
+        let bom_len = encoding::sniff_bom(content).map_or(0, |(_, len)| len);
+        let file = FileEncoding { encoding: detected, bom: content[..bom_len].to_vec() };
+        let text = detected
+            .decode_without_bom_handling_and_without_replacement(&content[bom_len..])
+            .ok_or_else(|| file.unsupported(path, "it has malformed sequences"))?;
+        if file.encode(path, &text)? != content {
+            return Err(file.unsupported(path, "it would not be written back unchanged"));
+        }
+        Ok((Cow::Owned(text.into_owned().into_bytes()), Some(file)))
+    }
+
+    pub fn edits(&self, haystack: &[u8]) -> CoreResult<Vec<Edit>> {
+        let mut edits = Vec::new();
+        let mut caps = self.matcher.new_captures()?;
+        self.matcher.captures_iter(haystack, &mut caps, |caps| {
+            let Some(m) = caps.get(0) else { return true };
+            let mut replacement = Vec::new();
+            self.template.expand(haystack, caps, &mut replacement);
+            if self.preserve_case {
+                let matched = String::from_utf8_lossy(&haystack[m]);
+                replacement = preserve_case(&matched, &String::from_utf8_lossy(&replacement)).into_bytes();
+            }
+            edits.push(Edit { range: m.start()..m.end(), replacement });
+            true
+        })?;
+        Ok(edits)
+    }
+}
+
+pub fn apply_edits(haystack: &[u8], edits: &[Edit]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(haystack.len());
+    let mut last = 0;
+    for edit in edits {
+        result.extend_from_slice(&haystack[last..edit.range.start]);
+        result.extend_from_slice(&edit.replacement);
+        last = edit.range.end;
+    }
+    result.extend_from_slice(&haystack[last..]);
+    result
+}
+
+/// The encoding a transcoded file is written back in
+struct FileEncoding {
+    encoding: &'static Encoding,
+    /// The file's BOM, kept as it was
+    bom: Vec<u8>,
+}
+
+impl FileEncoding {
+    fn encode(&self, path: &str, text: &str) -> CoreResult<Vec<u8>> {
+        let mut bytes = self.bom.clone();
+        // encoding_rs only decodes UTF-16, encoding to it writes UTF-8
+        if self.encoding == UTF_16LE {
+            bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        } else if self.encoding == UTF_16BE {
+            bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        } else {
+            let (encoded, _, unmappable) = self.encoding.encode(text);
+            if unmappable {
+                return Err(self.unsupported(path, "the result has characters the encoding cannot represent"));
+            }
+            bytes.extend_from_slice(&encoded);
+        }
+        Ok(bytes)
+    }
+
+    fn unsupported(&self, path: &str, reason: &str) -> CoreError {
+        CoreError::Unavailable(format!("Cannot replace in {} as {}: {}", path, self.encoding.name(), reason))
+    }
+}
+
+/// Identifies the content a preview was computed from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Version {
+    len: usize,
+    hash: u64,
+}
+
+impl Version {
+    fn of(content: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        Version { len: content.len(), hash: hasher.finish() }
+    }
+}
+
+struct PendingReplace {
+    rewrite: Rewrite,
+    files: HashMap<String, Version>,
+}
+
+/// Previews waiting for `apply_replace`, keyed by the id handed to JS
+#[derive(Default)]
+pub struct PendingReplacements {
+    next_id: AtomicU32,
+    previews: Mutex<HashMap<u32, PendingReplace>>,
+}
+
+impl PendingReplacements {
+    /// Preview the rewrite of one file, or `None` if nothing in it would change
+    pub fn preview_file(rewrite: &Rewrite, path: &str, context_lines: usize) -> CoreResult<Option<(FileReplacePreview, Version)>> {
+        let content = std::fs::read(path).map_err(|e| CoreError::io("Failed to read file", path, e))?;
+        let (text, _) = rewrite.decode(path, &content)?;
+        let edits = rewrite.edits(&text)?;
+        if edits.is_empty() {
+            return Ok(None);
+        }
+        let preview = FileReplacePreview {
+            file_path: path.to_string(),
+            replacement_count: edits.len() as u32,
+            hunks: build_hunks(&text, &edits, context_lines),
+        };
+        Ok(Some((preview, Version::of(&content))))
+    }
+
+    /// Remember a preview until it is applied or discarded and return its id
+    pub fn insert(&self, rewrite: Rewrite, files: HashMap<String, Version>) -> u32 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.previews.lock().unwrap().insert(id, PendingReplace { rewrite, files });
+        id
+    }
+
+    pub fn discard(&self, id: u32) -> bool {
+        self.previews.lock().unwrap().remove(&id).is_some()
+    }
+
+    /// Write the previewed replacements of `paths`, or of every previewed file.
+    ///
+    /// All files are read and compared against the preview first, so nothing
+    /// is written if one of them changed. Files are then replaced one by one
+    /// through atomic renames; if a write fails, the files already written get
+    /// their previous content back. The preview is used up either way.
+    pub fn apply(&self, id: u32, paths: Option<Vec<String>>, signal: &CancelSignal) -> CoreResult<ReplaceSummary> {
+        let pending = self.previews.lock().unwrap().remove(&id)
+            .ok_or_else(|| CoreError::InvalidArgument(format!("Unknown or already applied replace preview {}", id)))?;
+
+        let mut paths = match paths {
+            Some(paths) => paths,
+            None => pending.files.keys().cloned().collect(),
+        };
+        paths.sort();
+        paths.dedup();
+
+        let mut staged = Vec::with_capacity(paths.len());
+        let mut replacement_count = 0;
+        for path in paths {
+            signal.check()?;
+            let expected = *pending.files.get(&path)
+                .ok_or_else(|| CoreError::InvalidArgument(format!("{} is not part of replace preview {}", path, id)))?;
+            let meta = std::fs::metadata(&path).map_err(|e| CoreError::io("Failed to stat file", &path, e))?;
+            let original = std::fs::read(&path).map_err(|e| CoreError::io("Failed to read file", &path, e))?;
+            if Version::of(&original) != expected {
+                return Err(CoreError::Modified { path: path.into() });
+            }
+            let (text, file_encoding) = pending.rewrite.decode(&path, &original)?;
+            let edits = pending.rewrite.edits(&text)?;
+            replacement_count += edits.len() as u32;
+            let mut replaced = apply_edits(&text, &edits);
+            if let Some(file_encoding) = file_encoding {
+                // Matches and replacements are whole characters, so this is still UTF-8
+                replaced = file_encoding.encode(&path, &String::from_utf8_lossy(&replaced))?;
+            }
+            // Write through symlinks instead of replacing the link itself
+            let target = std::fs::canonicalize(&path).map_err(|e| CoreError::io("Failed to resolve file", &path, e))?;
+            staged.push((target, meta, original, replaced));
+        }
+        signal.check()?;
+
+        for (i, (path, meta, _, replaced)) in staged.iter().enumerate() {
+            if let Err(e) = write_atomic(path, replaced, Some(meta), DEFAULT_ATOMIC_POSTFIX) {
+                for (path, meta, original, _) in &staged[..i] {
+                    if let Err(e) = write_atomic(path, original, Some(meta), DEFAULT_ATOMIC_POSTFIX) {
+                        tracing::error!("Failed to restore {} after a failed replace: {}", path.display(), e);
+                    }
+                }
+                return Err(CoreError::io("Failed to write replacement", path, e));
+            }
+        }
+
+        Ok(ReplaceSummary { files_changed: staged.len() as u32, replacement_count })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_engine::matcher::build_matcher;
+    use crate::search_engine::SearchOptions;
+
+    fn replace(pattern: &str, replacement: &str, preserve: bool, text: &str) -> String {
+        let matcher = build_matcher(pattern, &SearchOptions { case_sensitive: Some(false), ..Default::default() }).unwrap();
+        let template = Template::parse(replacement, &matcher);
+        let rewrite = Rewrite { matcher, template, preserve_case: preserve, encoding: None };
+        String::from_utf8(apply_edits(text.as_bytes(), &rewrite.edits(text.as_bytes()).unwrap())).unwrap()
+    }
+
+    #[test]
+    fn test_capture_groups() {
+        assert_eq!(replace(r"(\w+)=(\w+)", "$2=$1", false, "a=b c=d"), "b=a d=c");
+        assert_eq!(replace(r"(?P<key>\w+):", "${key} ->", false, "x: y:"), "x -> y ->");
+        assert_eq!(replace(r"(?P<key>\w+):", "$<key>;", false, "x:"), "x;");
+        assert_eq!(replace(r"o", "[$&]$$", false, "foo"), "f[o]$[o]$");
+        assert_eq!(replace(r"(a)", "$1$2\\n", false, "a"), "a$2\n");
+        assert_eq!(replace(r"(\w+) (\w+)", "\\u$1 \\U$2", false, "hello world"), "Hello WORLD");
+        assert_eq!(replace(r"(\w+)", "\\l\\U$1", false, "Word"), "wORD");
+    }
+
+    #[test]
+    fn test_preserve_case() {
+        assert_eq!(preserve_case("FOO", "bar"), "BAR");
+        assert_eq!(preserve_case("foo", "Bar"), "bar");
+        assert_eq!(preserve_case("Foo", "bar"), "Bar");
+        assert_eq!(preserve_case("fOO", "Bar"), "bar");
+        assert_eq!(preserve_case("Foo-BAR", "baz-qux"), "Baz-QUX");
+        assert_eq!(preserve_case("foo_Bar", "baz_qux"), "baz_Qux");
+        assert_eq!(replace("foo", "bar", true, "foo Foo FOO"), "bar Bar BAR");
+    }
+
+    #[test]
+    fn test_apply_rejects_changed_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let a = temp_dir.path().join("a.txt").to_string_lossy().to_string();
+        let b = temp_dir.path().join("b.txt").to_string_lossy().to_string();
+        std::fs::write(&a, "old value\n").unwrap();
+        std::fs::write(&b, "old\nold\n").unwrap();
+
+        let matcher = build_matcher("old", &SearchOptions::default()).unwrap();
+        let rewrite = Rewrite { template: Template::literal("new"), matcher, preserve_case: false, encoding: None };
+        let pending = PendingReplacements::default();
+        let preview = |pending: &PendingReplacements| {
+            let files = [&a, &b].into_iter().map(|path| {
+                let (file, version) = PendingReplacements::preview_file(&rewrite, path, 3).unwrap().unwrap();
+                (file.file_path, version)
+            });
+            pending.insert(rewrite.clone(), files.collect())
+        };
+
+        let id = preview(&pending);
+        std::fs::write(&b, "old\nold!\n").unwrap();
+        let err = pending.apply(id, None, &CancelSignal::default()).unwrap_err();
+        assert_eq!(err.code(), crate::error::ErrorCode::FileModified);
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "old value\n");
+        assert!(pending.apply(id, None, &CancelSignal::default()).is_err());
+
+        let id = preview(&pending);
+        let summary = pending.apply(id, None, &CancelSignal::default()).unwrap();
+        assert_eq!((summary.files_changed, summary.replacement_count), (2, 3));
+        assert_eq!(std::fs::read_to_string(&a).unwrap(), "new value\n");
+        assert_eq!(std::fs::read_to_string(&b).unwrap(), "new\nnew!\n");
+    }
+
+    #[test]
+    fn test_replaces_in_transcoded_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let utf16 = |text: &str| {
+            let mut bytes = vec![0xFF, 0xFE];
+            bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            bytes
+        };
+        let wide = temp_dir.path().join("wide.txt").to_string_lossy().to_string();
+        std::fs::write(&wide, utf16("key = old value\nother = old\n")).unwrap();
+        let sjis = temp_dir.path().join("sjis.txt").to_string_lossy().to_string();
+        std::fs::write(&sjis, encoding_rs::SHIFT_JIS.encode("キー old 値段です\n").0).unwrap();
+
+        let rewrite = |replacement: &str| Rewrite {
+            matcher: build_matcher("old", &SearchOptions::default()).unwrap(),
+            template: Template::literal(replacement),
+            preserve_case: false,
+            encoding: None,
+        };
+        let pending = PendingReplacements::default();
+        let mut files = HashMap::new();
+        for (path, count) in [(&wide, 2), (&sjis, 1)] {
+            let (preview, version) = PendingReplacements::preview_file(&rewrite("新"), path, 3).unwrap().unwrap();
+            assert_eq!(preview.replacement_count, count);
+            assert!(preview.hunks[0].lines.iter().any(|line| line.contains("新")));
+            files.insert(path.clone(), version);
+        }
+
+        let id = pending.insert(rewrite("新"), files);
+        let summary = pending.apply(id, None, &CancelSignal::default()).unwrap();
+        assert_eq!((summary.files_changed, summary.replacement_count), (2, 3));
+        // Both keep their encoding, and the UTF-16 file its BOM
+        assert_eq!(std::fs::read(&wide).unwrap(), utf16("key = 新 value\nother = 新\n"));
+        assert_eq!(std::fs::read(&sjis).unwrap(), &*encoding_rs::SHIFT_JIS.encode("キー 新 値段です\n").0);
+
+        assert!(PendingReplacements::preview_file(&rewrite("新"), &sjis, 3).unwrap().is_none());
+
+        // Characters the encoding has no bytes for are refused, not written
+        std::fs::write(&sjis, encoding_rs::SHIFT_JIS.encode("キー old 値段です\n").0).unwrap();
+        let (_, version) = PendingReplacements::preview_file(&rewrite("🙂"), &sjis, 3).unwrap().unwrap();
+        let id = pending.insert(rewrite("🙂"), HashMap::from([(sjis.clone(), version)]));
+        let err = pending.apply(id, None, &CancelSignal::default()).unwrap_err();
+        assert_eq!(err.code(), crate::error::ErrorCode::Unavailable);
+        assert_eq!(std::fs::read(&sjis).unwrap(), &*encoding_rs::SHIFT_JIS.encode("キー old 値段です\n").0);
+    }
+}
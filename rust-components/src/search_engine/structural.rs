@@ -0,0 +1,285 @@
+use super::grammar::Grammar;
+use super::preview::{build_match, DEFAULT_PREVIEW_CHARS};
+use super::walk::FileSearcher;
+use super::{SearchOptions, SearchResult};
+use crate::cancellation::CancelSignal;
+use crate::error::{CoreError, CoreResult};
+use crate::file_operations::encoding;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tree_sitter::{Node, Tree};
+
+/// Metavariables are replaced by identifiers with these prefixes before the
+/// pattern is parsed, since `$` cannot start an identifier in every language
+const META_PREFIX: &str = "__cs_meta_";
+const MULTI_PREFIX: &str = "__cs_multi_";
+/// Metavariables that match without binding, so they may differ between uses
+const WILDCARD: &str = "_";
+
+/// A code snippet to look for, in which `$NAME` stands for any single node
+/// and `$$$NAME` for any number of sibling nodes. A name used twice must
+/// match the same text both times, except for `$_` and `$$$`.
+#[derive(Clone)]
+pub struct StructuralPattern {
+    grammar: Grammar,
+    root: Arc<PatternNode>,
+}
+
+#[derive(Debug, PartialEq)]
+enum PatternNode {
+    Meta(String),
+    Multi(String),
+    Leaf { kind: &'static str, text: String },
+    Node { kind: &'static str, children: Vec<PatternNode> },
+}
+
+impl StructuralPattern {
+    pub fn parse(pattern: &str, language_id: &str) -> CoreResult<Self> {
+        let grammar = Grammar::for_language_id(language_id)
+            .ok_or_else(|| CoreError::InvalidArgument(format!("Structural search does not support {}", language_id)))?;
+        let source = replace_metavariables(pattern);
+        if source.trim().is_empty() {
+            return Err(CoreError::InvalidArgument("Empty structural pattern".to_string()));
+        }
+
+        // Expressions only parse as statements in some grammars, e.g. Rust
+        // wants a `;` after a call at the top level
+        for candidate in [source.clone(), format!("{};", source)] {
+            let Some(tree) = grammar.parse(candidate.as_bytes()) else { continue };
+            if tree.root_node().has_error() {
+                continue;
+            }
+            let node = unwrap(tree.root_node(), candidate.as_bytes());
+            return Ok(StructuralPattern { grammar, root: Arc::new(build(node, candidate.as_bytes())) });
+        }
+        Err(CoreError::InvalidArgument(format!("Structural pattern is not valid {} code: {}", language_id, pattern)))
+    }
+
+    /// Every node of `tree` the pattern matches, outer matches before the
+    /// ones nested in them
+    fn find<'t>(&self, tree: &'t Tree, source: &[u8], signal: &CancelSignal) -> Vec<Node<'t>> {
+        let mut found = Vec::new();
+        let mut cursor = tree.walk();
+        let mut visited = 0u32;
+        loop {
+            let node = cursor.node();
+            if node.is_named() && matches(&self.root, node, source, &mut HashMap::new()) {
+                found.push(node);
+            }
+            visited += 1;
+            if visited.is_multiple_of(4096) && signal.is_cancelled() {
+                return found;
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return found;
+                }
+            }
+        }
+    }
+}
+
+impl FileSearcher for StructuralPattern {
+    fn accepts(&self, path: &Path) -> bool {
+        Grammar::for_path(path).is_some_and(|grammar| grammar.same_family(self.grammar))
+    }
+
+    fn search_file(&self, path: &Path, opts: &SearchOptions, signal: &CancelSignal) -> CoreResult<SearchResult> {
+        let mut result = SearchResult { file_path: path.to_string_lossy().to_string(), matches: Vec::new(), context: Vec::new() };
+        let bytes = std::fs::read(path).map_err(|e| CoreError::io("Failed to read file", path, e))?;
+        let head = &bytes[..bytes.len().min(encoding::SNIFF_LEN)];
+        // Grammars parse UTF-8, so other encodings are decoded first and binary files skipped
+        let source: Cow<[u8]> = match encoding::detect(head, head.len() == bytes.len()) {
+            None => return Ok(result),
+            Some(detected) if detected == encoding_rs::UTF_8 => Cow::Borrowed(&bytes),
+            Some(detected) => Cow::Owned(encoding::decode(&bytes, detected).0.into_bytes()),
+        };
+        let grammar = Grammar::for_path(path).unwrap_or(self.grammar);
+        let Some(tree) = grammar.parse(&source) else { return Ok(result) };
+
+        let chars_per_line = opts.preview_chars_per_line.unwrap_or(DEFAULT_PREVIEW_CHARS) as usize;
+        for node in self.find(&tree, &source, signal) {
+            let range = node.byte_range();
+            let start = range.start - node.start_position().column;
+            let end = memchr::memchr(b'\n', &source[range.end..]).map_or(source.len(), |i| range.end + i);
+            let first_line = node.start_position().row as u64 + 1;
+            result.matches.push(build_match(&source[start..end], first_line, range.start - start..range.end - start, chars_per_line));
+        }
+        Ok(result)
+    }
+}
+
+/// Replace `$$$NAME`, `$$$` and `$NAME` with identifiers every grammar accepts.
+/// Names are uppercase, so `$el` and the like stay literal code.
+fn replace_metavariables(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (prefix, after) = match rest.strip_prefix("$$$") {
+            Some(after) => (MULTI_PREFIX, after),
+            None => (META_PREFIX, &rest[1..]),
+        };
+        let name_len = after.find(|c: char| !(c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')).unwrap_or(after.len());
+        if name_len == 0 && prefix == META_PREFIX {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        out.push_str(prefix);
+        out.push_str(&after[..name_len]);
+        rest = &after[name_len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn metavariable(text: &str) -> Option<PatternNode> {
+    let text = text.trim_end().trim_end_matches(';');
+    let is_name = |name: &str| name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    if let Some(name) = text.strip_prefix(MULTI_PREFIX).filter(|name| is_name(name)) {
+        return Some(PatternNode::Multi(if name.is_empty() { WILDCARD.to_string() } else { name.to_string() }));
+    }
+    let name = text.strip_prefix(META_PREFIX).filter(|name| !name.is_empty() && is_name(name))?;
+    Some(PatternNode::Meta(name.to_string()))
+}
+
+/// Descend from the root of the parsed pattern to the node the user wrote,
+/// e.g. from `program > expression_statement > call_expression` to the call
+fn unwrap<'t>(mut node: Node<'t>, source: &[u8]) -> Node<'t> {
+    let text = |n: Node| n.utf8_text(source).unwrap_or_default().trim().trim_end_matches(';').trim_end();
+    loop {
+        let mut cursor = node.walk();
+        let mut named = node.named_children(&mut cursor).filter(|child| !child.is_extra());
+        let (Some(child), None) = (named.next(), named.next()) else { return node };
+        // Wrappers that add code of their own, like a block's braces, are kept
+        if text(child) != text(node) {
+            return node;
+        }
+        node = child;
+    }
+}
+
+fn build(node: Node, source: &[u8]) -> PatternNode {
+    let text = node.utf8_text(source).unwrap_or_default();
+    if let Some(meta) = metavariable(text) {
+        return meta;
+    }
+    if node.child_count() == 0 {
+        return PatternNode::Leaf { kind: node.kind(), text: text.to_string() };
+    }
+    let mut cursor = node.walk();
+    let children = node.children(&mut cursor).filter(|child| !child.is_extra()).map(|child| build(child, source)).collect();
+    PatternNode::Node { kind: node.kind(), children }
+}
+
+fn matches(pattern: &PatternNode, node: Node, source: &[u8], bindings: &mut HashMap<String, String>) -> bool {
+    match pattern {
+        PatternNode::Meta(name) | PatternNode::Multi(name) => bind(name, node.utf8_text(source).unwrap_or_default(), bindings),
+        PatternNode::Leaf { kind, text } => node.kind() == *kind && node.utf8_text(source).is_ok_and(|t| t == text),
+        PatternNode::Node { kind, children } => {
+            if node.kind() != *kind {
+                return false;
+            }
+            let mut cursor = node.walk();
+            let targets: Vec<Node> = node.children(&mut cursor).filter(|child| !child.is_extra()).collect();
+            matches_sequence(children, &targets, source, bindings)
+        }
+    }
+}
+
+/// Match sibling patterns against sibling nodes, trying every split for `$$$`
+fn matches_sequence(patterns: &[PatternNode], nodes: &[Node], source: &[u8], bindings: &mut HashMap<String, String>) -> bool {
+    let Some((first, rest)) = patterns.split_first() else { return nodes.is_empty() };
+    if let PatternNode::Multi(name) = first {
+        for taken in 0..=nodes.len() {
+            let mut attempt = bindings.clone();
+            let text = match (nodes.first(), nodes[..taken].last()) {
+                (Some(first), Some(last)) => std::str::from_utf8(&source[first.start_byte()..last.end_byte()]).unwrap_or_default(),
+                _ => "",
+            };
+            if bind(name, text, &mut attempt) && matches_sequence(rest, &nodes[taken..], source, &mut attempt) {
+                *bindings = attempt;
+                return true;
+            }
+        }
+        return false;
+    }
+    match nodes.split_first() {
+        Some((node, nodes)) => matches(first, *node, source, bindings) && matches_sequence(rest, nodes, source, bindings),
+        None => false,
+    }
+}
+
+fn bind(name: &str, text: &str, bindings: &mut HashMap<String, String>) -> bool {
+    if name == WILDCARD {
+        return true;
+    }
+    match bindings.get(name) {
+        Some(bound) => bound == text,
+        None => {
+            bindings.insert(name.to_string(), text.to_string());
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn find(pattern: &str, language_id: &str, file: &str, code: &str) -> Vec<(f64, String)> {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(file);
+        std::fs::write(&path, code).unwrap();
+        let pattern = StructuralPattern::parse(pattern, language_id).unwrap();
+        assert!(pattern.accepts(&path));
+        let result = pattern.search_file(&path, &SearchOptions::default(), &CancelSignal::default()).unwrap();
+        result.matches.iter().map(|m| {
+            let units: Vec<u16> = m.text.encode_utf16().collect();
+            (m.line_number, String::from_utf16_lossy(&units[m.preview_column_start as usize..m.preview_column_end as usize]))
+        }).collect()
+    }
+
+    #[test]
+    fn test_structural_calls() {
+        let code = "foo(a, b);\nfoo(a);\nfoo(x, /* y */ y);\nbar.foo(1, 2);\nfoo(foo(1, 2), 3);\n";
+        let found = find("foo($A, $B)", "typescript", "a.ts", code);
+        let lines: Vec<f64> = found.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [1.0, 3.0, 5.0, 5.0]);
+        assert_eq!(found[0].1, "foo(a, b)");
+        assert_eq!(found[3].1, "foo(1, 2)");
+
+        // A repeated metavariable has to match the same code
+        let found = find("$A == $A", "javascript", "a.js", "if (x == x) {}\nif (x == y) {}\n");
+        assert_eq!(found, [(1.0, "x == x".to_string())]);
+
+        let found = find("foo($$$ARGS)", "python", "a.py", "foo()\nfoo(1, 2, 3)\nbar(1)\n");
+        assert_eq!(found.len(), 2);
+
+        let found = find("fn $NAME($$$) -> Result<$T> { $$$ }", "rust", "lib.rs", "fn a() -> Result<u8> { Ok(1) }\nfn b(x: u8) -> u8 { x }\n");
+        assert_eq!(found, [(1.0, "fn a() -> Result<u8> { Ok(1) }".to_string())]);
+
+        // Calls in Rust need no trailing `;` in the pattern
+        assert_eq!(find("foo($A)", "rust", "main.rs", "fn main() { foo(1); }\n").len(), 1);
+    }
+
+    #[test]
+    fn test_structural_pattern_errors() {
+        assert!(StructuralPattern::parse("foo(", "typescript").is_err());
+        assert!(StructuralPattern::parse("foo()", "cobol").is_err());
+        assert!(StructuralPattern::parse("  ", "rust").is_err());
+
+        let pattern = StructuralPattern::parse("foo($A)", "typescript").unwrap();
+        assert!(pattern.accepts(Path::new("x/component.tsx")));
+        assert!(!pattern.accepts(Path::new("x/script.js")));
+        assert_eq!(replace_metavariables("$el.on($A, $$$)"), "$el.on(__cs_meta_A, __cs_multi_)");
+    }
+}
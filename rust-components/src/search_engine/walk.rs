@@ -0,0 +1,438 @@
+use super::filter::PathFilter;
+use super::matcher::PatternMatcher;
+use super::preview::{build_context_line, build_match, DEFAULT_PREVIEW_CHARS};
+use super::{Match, SearchOptions, SearchProgress, SearchResult};
+use crate::cancellation::CancelSignal;
+use crate::error::{CoreError, CoreResult};
+use crate::file_operations::encoding;
+use grep::matcher::Matcher;
+use grep::searcher::{BinaryDetection, Encoding, SearcherBuilder, Sink, SinkContext, SinkContextKind, SinkMatch};
+use ignore::{WalkBuilder, WalkState};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Counters shared by the walker threads of one search
+#[derive(Default)]
+pub struct SearchCounters {
+    files_scanned: AtomicU32,
+    files_matched: AtomicU32,
+    matches: AtomicU32,
+    truncated: AtomicBool,
+}
+
+impl SearchCounters {
+    pub fn progress(&self) -> SearchProgress {
+        SearchProgress {
+            files_scanned: self.files_scanned.load(Ordering::Relaxed),
+            files_matched: self.files_matched.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn match_count(&self) -> u32 {
+        self.matches.load(Ordering::Relaxed)
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.truncated.load(Ordering::Relaxed)
+    }
+
+    /// Account for the matches of one file, trimming them to what is left of
+    /// `max_results`. Returns false once the limit is reached.
+    fn admit(&self, matches: &mut Vec<Match>, max_results: Option<u32>) -> bool {
+        let Some(max) = max_results else {
+            self.matches.fetch_add(matches.len() as u32, Ordering::Relaxed);
+            return true;
+        };
+
+        let previous = self.matches.fetch_add(matches.len() as u32, Ordering::Relaxed);
+        let remaining = max.saturating_sub(previous) as usize;
+        if matches.len() >= remaining {
+            self.truncated.store(true, Ordering::Relaxed);
+            self.matches.fetch_sub((matches.len() - remaining) as u32, Ordering::Relaxed);
+            matches.truncate(remaining);
+            return false;
+        }
+        true
+    }
+}
+
+/// Searches one file for `search_tree` and `search_paths`
+pub trait FileSearcher: Clone + Send {
+    /// Whether `path` is worth reading at all; files it rejects are not counted as scanned
+    fn accepts(&self, _path: &Path) -> bool {
+        true
+    }
+
+    fn search_file(&self, path: &Path, opts: &SearchOptions, signal: &CancelSignal) -> CoreResult<SearchResult>;
+}
+
+impl FileSearcher for PatternMatcher {
+    fn search_file(&self, path: &Path, opts: &SearchOptions, signal: &CancelSignal) -> CoreResult<SearchResult> {
+        search_in_file(&path.to_string_lossy(), self, opts, signal)
+    }
+}
+
+/// Search every file below `root` in parallel, handing each file with matches
+/// to `on_result` as soon as it has been searched. The walk stops early when
+/// `signal` is cancelled or `max_results` matches have been found.
+#[allow(clippy::too_many_arguments)]
+pub fn search_tree<S, F>(
+    root: &Path,
+    searcher: &S,
+    filter: &PathFilter,
+    opts: &SearchOptions,
+    signal: &CancelSignal,
+    counters: &SearchCounters,
+    on_result: F,
+) where
+    S: FileSearcher + Sync,
+    F: Fn(SearchResult) + Sync,
+{
+    if opts.max_results == Some(0) {
+        counters.truncated.store(true, Ordering::Relaxed);
+        return;
+    }
+
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(!opts.include_hidden.unwrap_or(false))
+        .ignore(!opts.disable_ignore.unwrap_or(false))
+        .git_ignore(!opts.disable_gitignore.unwrap_or(false))
+        .max_depth(opts.max_depth.map(|d| d as usize))
+        .follow_links(opts.follow_symlinks.unwrap_or(false))
+        .max_filesize(opts.max_filesize.map(|bytes| bytes as u64))
+        .threads(num_cpus::get());
+    if !opts.disable_ignore.unwrap_or(false) {
+        builder.add_custom_ignore_filename(".rgignore");
+    }
+
+    let limit_reached = AtomicBool::new(false);
+    let on_result = &on_result;
+    let limit_reached = &limit_reached;
+
+    builder.build_parallel().run(|| {
+        let searcher = searcher.clone();
+
+        Box::new(move |result| {
+            if signal.is_cancelled() || limit_reached.load(Ordering::Relaxed) {
+                return WalkState::Quit;
+            }
+            // Symlinks leading back into a folder being walked come back as errors
+            let Ok(entry) = result else {
+                return WalkState::Continue;
+            };
+            if entry.depth() > 0 && entry.file_type().is_some_and(|ft| ft.is_dir()) && filter.skips_dir(entry.path()) {
+                return WalkState::Skip;
+            }
+            if !entry.file_type().is_some_and(|ft| ft.is_file()) || filter.skips_file(entry.path()) {
+                return WalkState::Continue;
+            }
+            if visit(entry.path(), &searcher, opts, signal, counters, on_result) {
+                WalkState::Continue
+            } else {
+                limit_reached.store(true, Ordering::Relaxed);
+                WalkState::Quit
+            }
+        })
+    });
+}
+
+/// Search the given files in parallel, e.g. the candidates of a trigram index,
+/// with the same reporting and limits as `search_tree`
+pub fn search_paths<S, F>(
+    paths: &[PathBuf],
+    searcher: &S,
+    opts: &SearchOptions,
+    signal: &CancelSignal,
+    counters: &SearchCounters,
+    on_result: F,
+) where
+    S: FileSearcher + Sync,
+    F: Fn(SearchResult) + Sync,
+{
+    if opts.max_results == Some(0) {
+        counters.truncated.store(true, Ordering::Relaxed);
+        return;
+    }
+
+    let limit_reached = AtomicBool::new(false);
+    paths.par_iter().for_each_init(
+        || searcher.clone(),
+        |searcher, path| {
+            if signal.is_cancelled() || limit_reached.load(Ordering::Relaxed) {
+                return;
+            }
+            if let Some(max) = opts.max_filesize {
+                if std::fs::metadata(path).map_or(true, |meta| meta.len() > max as u64) {
+                    return;
+                }
+            }
+            if !visit(path, searcher, opts, signal, counters, &on_result) {
+                limit_reached.store(true, Ordering::Relaxed);
+            }
+        },
+    );
+}
+
+/// Search one file and report it if it has matches. Returns false once
+/// `max_results` has been reached.
+fn visit<S, F>(path: &Path, searcher: &S, opts: &SearchOptions, signal: &CancelSignal, counters: &SearchCounters, on_result: &F) -> bool
+where
+    S: FileSearcher,
+    F: Fn(SearchResult),
+{
+    if !searcher.accepts(path) {
+        return true;
+    }
+    counters.files_scanned.fetch_add(1, Ordering::Relaxed);
+
+    let mut result = match searcher.search_file(path, opts, signal) {
+        Ok(result) if !result.matches.is_empty() => result,
+        _ => return true,
+    };
+
+    let keep_going = counters.admit(&mut result.matches, opts.max_results);
+    if let (false, Some(last)) = (keep_going, result.matches.last()) {
+        // Drop the context of matches cut off by the limit
+        let last_line = last.end_line_number + opts.after_context.unwrap_or(0) as f64;
+        result.context.retain(|line| line.line_number <= last_line);
+    }
+    if !result.matches.is_empty() {
+        counters.files_matched.fetch_add(1, Ordering::Relaxed);
+        on_result(result);
+    }
+    keep_going
+}
+
+fn search_in_file(path: &str, matcher: &PatternMatcher, opts: &SearchOptions, signal: &CancelSignal) -> CoreResult<SearchResult> {
+    let mut file = File::open(path)
+        .map_err(|e| CoreError::io("Failed to open file", path, e))?;
+
+    // Files in other encodings are transcoded to UTF-8 before they are searched
+    let mut head = Vec::new();
+    (&mut file).take(encoding::SNIFF_LEN as u64).read_to_end(&mut head)
+        .map_err(|e| CoreError::io("Failed to read file", path, e))?;
+    let configured = opts.encoding.as_deref().and_then(encoding::for_label);
+    let detected = encoding::detect_file(&head, head.len() < encoding::SNIFF_LEN, configured);
+    let transcode = detected.filter(|&e| e != encoding_rs::UTF_8).and_then(|e| Encoding::new(e.name()).ok());
+
+    let mut result = SearchResult { file_path: path.to_string(), matches: Vec::new(), context: Vec::new() };
+
+    struct MatchSink<'a> {
+        matcher: &'a PatternMatcher,
+        chars_per_line: usize,
+        result: &'a mut SearchResult,
+        signal: &'a CancelSignal,
+    }
+
+    impl<'a> Sink for MatchSink<'a> {
+        type Error = std::io::Error;
+
+        fn matched(
+            &mut self,
+            _searcher: &grep::searcher::Searcher,
+            mat: &SinkMatch<'_>,
+        ) -> std::result::Result<bool, std::io::Error> {
+            // The searcher reports whole lines; find each match within them,
+            // searching the surrounding buffer so look-arounds see their context
+            let range = mat.bytes_range_in_buffer();
+            let first_line = mat.line_number().unwrap_or(0);
+            let matches = &mut self.result.matches;
+            let chars_per_line = self.chars_per_line;
+            self.matcher.find_iter_at(mat.buffer(), range.start, |m| {
+                if m.start() >= range.end {
+                    return false;
+                }
+                let within = m.start() - range.start..m.end().min(range.end) - range.start;
+                matches.push(build_match(mat.bytes(), first_line, within, chars_per_line));
+                true
+            }).map_err(std::io::Error::other)?;
+            // Stop reading the file once the search has been cancelled
+            Ok(!self.signal.is_cancelled())
+        }
+
+        fn context(
+            &mut self,
+            _searcher: &grep::searcher::Searcher,
+            ctx: &SinkContext<'_>,
+        ) -> std::result::Result<bool, std::io::Error> {
+            if matches!(ctx.kind(), SinkContextKind::Before | SinkContextKind::After) {
+                let line_number = ctx.line_number().unwrap_or(0);
+                self.result.context.push(build_context_line(ctx.bytes(), line_number, self.chars_per_line));
+            }
+            Ok(!self.signal.is_cancelled())
+        }
+    }
+
+    let mut sink = MatchSink {
+        matcher,
+        chars_per_line: opts.preview_chars_per_line.unwrap_or(DEFAULT_PREVIEW_CHARS) as usize,
+        result: &mut result,
+        signal,
+    };
+
+    SearcherBuilder::new()
+        .binary_detection(BinaryDetection::quit(b'\x00'))
+        .encoding(transcode)
+        .line_number(true)
+        .multi_line(matcher.multi_line())
+        .before_context(opts.before_context.unwrap_or(0) as usize)
+        .after_context(opts.after_context.unwrap_or(0) as usize)
+        .build()
+        .search_reader(matcher, BufReader::new(Cursor::new(head).chain(file)), &mut sink)
+        .map_err(|e| CoreError::io("Search failed", path, e))?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_engine::matcher::build_matcher;
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    fn run(root: &Path, pattern: &str, opts: SearchOptions) -> (Vec<SearchResult>, SearchCounters) {
+        let matcher = build_matcher(pattern, &opts).unwrap();
+        let filter = PathFilter::new(root, &opts).unwrap();
+        let counters = SearchCounters::default();
+        let results = Mutex::new(Vec::new());
+        search_tree(root, &matcher, &filter, &opts, &CancelSignal::default(), &counters, |r| results.lock().unwrap().push(r));
+        (results.into_inner().unwrap(), counters)
+    }
+
+    #[test]
+    fn test_search_tree_max_results() {
+        let temp_dir = TempDir::new().unwrap();
+        for i in 0..20 {
+            std::fs::write(temp_dir.path().join(format!("{}.txt", i)), "needle\nhay\nneedle\n").unwrap();
+        }
+
+        let (results, counters) = run(temp_dir.path(), "needle", SearchOptions::default());
+        assert_eq!(results.len(), 20);
+        assert_eq!(counters.match_count(), 40);
+        assert_eq!(counters.progress().files_matched, 20);
+        assert!(!counters.truncated());
+
+        let (results, counters) = run(temp_dir.path(), "needle", SearchOptions { max_results: Some(5), ..Default::default() });
+        let total: usize = results.iter().map(|r| r.matches.len()).sum();
+        assert_eq!(total, 5);
+        assert_eq!(counters.match_count(), 5);
+        assert!(counters.truncated());
+    }
+
+    #[test]
+    fn test_search_tree_multiline() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a.rs"), "fn main() {\n    run();\n}\n").unwrap();
+
+        let (results, _) = run(temp_dir.path(), r"\{\n\s+run", SearchOptions::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].matches.len(), 1);
+        let m = &results[0].matches[0];
+        assert_eq!((m.line_number, m.column_start), (1.0, 10.0));
+        assert_eq!((m.end_line_number, m.column_end), (2.0, 7.0));
+    }
+
+    #[test]
+    fn test_search_tree_matches_and_context() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join("a.txt"), "one\ntwo\nfoo x foo\nthree\nfour\n").unwrap();
+
+        let opts = SearchOptions { before_context: Some(1), after_context: Some(2), ..Default::default() };
+        let (results, _) = run(temp_dir.path(), "foo", opts);
+        let result = &results[0];
+
+        // Each occurrence on a line is a match of its own
+        let columns: Vec<_> = result.matches.iter().map(|m| (m.line_number, m.column_start, m.column_end)).collect();
+        assert_eq!(columns, [(3.0, 0.0, 3.0), (3.0, 6.0, 9.0)]);
+        assert!(result.matches.iter().all(|m| m.text == "foo x foo"));
+
+        let context: Vec<_> = result.context.iter().map(|c| (c.line_number, c.text.as_str())).collect();
+        assert_eq!(context, [(2.0, "two"), (4.0, "three"), (5.0, "four")]);
+    }
+
+    #[test]
+    fn test_search_tree_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        std::fs::create_dir_all(root.join("src/vendor")).unwrap();
+        for file in ["src/a.ts", "src/b.js", "src/vendor/c.ts", "src/skipped.ts", "notes.md"] {
+            std::fs::write(root.join(file), "needle\n").unwrap();
+        }
+        std::fs::write(root.join(".rgignore"), "skipped.ts\n").unwrap();
+
+        let opts = SearchOptions {
+            include_patterns: Some(vec!["*.{ts,js}".to_string()]),
+            exclude_patterns: Some(vec!["vendor".to_string()]),
+            ..Default::default()
+        };
+        let (results, _) = run(root, "needle", opts);
+        let mut files: Vec<_> = results.iter().map(|r| Path::new(&r.file_path).file_name().unwrap().to_string_lossy().to_string()).collect();
+        files.sort();
+        assert_eq!(files, ["a.ts", "b.js"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_search_tree_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new().unwrap();
+        let (root, outside) = (temp_dir.path().join("root"), temp_dir.path().join("outside"));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(root.join("src/a.ts"), "needle\n").unwrap();
+        std::fs::write(root.join("big.txt"), format!("needle\n{}", "x".repeat(4096))).unwrap();
+        std::fs::write(outside.join("b.ts"), "needle\n").unwrap();
+        symlink(&outside, root.join("linked")).unwrap();
+        symlink(&root, root.join("src/loop")).unwrap();
+        // Results stay below the root as given, even if that is a link itself
+        let workspace = temp_dir.path().join("workspace");
+        symlink(&root, &workspace).unwrap();
+
+        let files = |opts| {
+            let (results, _) = run(&workspace, "needle", opts);
+            let mut files: Vec<String> = results.iter().map(|r| Path::new(&r.file_path).strip_prefix(&workspace).unwrap().to_string_lossy().to_string()).collect();
+            files.sort();
+            files
+        };
+        assert_eq!(files(SearchOptions::default()), ["big.txt", "src/a.ts"]);
+        let opts = SearchOptions { follow_symlinks: Some(true), max_filesize: Some(1024.0), ..Default::default() };
+        assert_eq!(files(opts), ["linked/b.ts", "src/a.ts"]);
+    }
+
+    #[test]
+    fn test_search_tree_transcodes() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let text = "// 設定ファイルを読み込みます。\nlet greeting = \"こんにちは世界\";\n";
+        let mut utf16: Vec<u8> = vec![0xFF, 0xFE];
+        utf16.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        std::fs::write(root.join("utf16.ts"), utf16).unwrap();
+        std::fs::write(root.join("sjis.ts"), encoding_rs::SHIFT_JIS.encode(text).0).unwrap();
+        std::fs::write(root.join("latin1.txt"), encoding_rs::WINDOWS_1252.encode("Café greeting\n").0).unwrap();
+
+        let (mut results, _) = run(root, "こんにちは", SearchOptions::default());
+        results.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+        assert_eq!(results.len(), 2);
+        for result in &results {
+            let m = &result.matches[0];
+            assert_eq!((m.line_number, m.column_start, m.column_end), (2.0, 16.0, 21.0));
+            assert_eq!(m.text, "let greeting = \"こんにちは世界\";");
+        }
+
+        let (results, _) = run(root, "Café", SearchOptions::default());
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].matches[0].text, "Café greeting");
+
+        // A forced encoding applies to files without a BOM
+        let opts = SearchOptions { encoding: Some("utf8".to_string()), ..Default::default() };
+        let (results, _) = run(root, "こんにちは", opts);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].file_path.ends_with("utf16.ts"));
+    }
+}